@@ -0,0 +1,110 @@
+//! Bringing graphs that were put in the store some other way under Janus's management.
+//!
+//! A store filled outside Janus has named graphs `<Meta>` knows nothing about, so they don't show
+//! up under `/graph`. Adopting one registers it in `<Meta>` with a type guessed from its content,
+//! records its content hash, and makes what it holds now its first revision.
+
+use oxigraph::SledStore as Store;
+use oxigraph::model::{GraphNameRef, NamedNode, NamedNodeRef, Quad};
+use oxigraph::model::vocab::rdf;
+
+use serde::Serialize;
+
+use crate::meta;
+use crate::api::GraphType;
+use crate::api::fsck::unregistered_graphs;
+use crate::api::history;
+use crate::api::ontology;
+use crate::api::storage::{apply_changes, content_hash_entry, graph_triples};
+use crate::api::vocab::{owl, sh};
+
+/// An unregistered graph, and the type it would be adopted as.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Proposal {
+    pub graph: String,
+    pub graph_type: GraphType,
+    /// Why that type was guessed.
+    pub reason: &'static str,
+    pub triples: usize,
+}
+
+/// The graphs that could be adopted, each with a guess at its type.
+pub fn propose(store: &Store) -> Vec<Proposal> {
+    unregistered_graphs(store)
+        .into_iter()
+        .filter(|g| !ontology::is_ontology_graph(g.as_ref()))
+        .map(|g| {
+            let (graph_type, reason) = guess_type(store, g.as_ref());
+            Proposal {
+                graph: g.to_string(),
+                graph_type,
+                reason,
+                triples: store.quads_for_pattern(None, None, None, Some(GraphNameRef::NamedNode(g.as_ref()))).count(),
+            }
+        })
+        .collect()
+}
+
+/// A type for `graph` going by what it declares: an `owl:Ontology` or `owl:Class` terms make an
+/// Ontology, SHACL shapes make Shapes, and anything else is a Model.
+pub fn guess_type(store: &Store, graph: NamedNodeRef) -> (GraphType, &'static str) {
+    let declares = |class: NamedNodeRef| store.quads_for_pattern(None, Some(rdf::TYPE), Some(class.into()), Some(GraphNameRef::NamedNode(graph)))
+        .next()
+        .is_some();
+
+    if declares(owl::ONTOLOGY) {
+        (GraphType::Ontology, "declares an owl:Ontology")
+    } else if declares(sh::NODE_SHAPE) || declares(sh::PROPERTY_SHAPE) {
+        (GraphType::Shapes, "declares SHACL shapes")
+    } else if declares(owl::CLASS) {
+        (GraphType::Ontology, "declares owl:Class terms")
+    } else {
+        (GraphType::Model, "declares no ontology or shapes")
+    }
+}
+
+/// Registers the unregistered `graph` in `<Meta>` as a `graph_type` graph.
+pub fn adopt(store: &Store, graph: &NamedNode, graph_type: GraphType) -> Result<(), String> {
+    if !unregistered_graphs(store).contains(graph) || ontology::is_ontology_graph(graph.as_ref()) {
+        return Err(format!("{} is not an unregistered graph", graph));
+    }
+
+    let lock = history::lock(graph);
+    let triples = graph_triples(store, graph.as_ref());
+    let revision = history::prepare_write(store, &lock, &triples, &[]);
+    let (hash_entry, stale_hash) = content_hash_entry(store, graph, &triples);
+
+    let remove: Vec<Quad> = stale_hash.into_iter().chain(revision.remove).collect();
+    let insert: Vec<Quad> = std::iter::once(meta::graph_metadata_entry(graph.clone(), graph_type))
+        .chain(hash_entry)
+        .chain(revision.insert)
+        .collect();
+    apply_changes(store, &remove, &insert)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::storage::accounted_graph_list;
+    use crate::api::storage::test::init_store;
+
+    #[test]
+    fn test_propose_and_adopt() {
+        let (s, _f) = init_store();
+        let ontology = NamedNode::new_unchecked("http://example.com/legacy-ontology");
+        let model = NamedNode::new_unchecked("http://example.com/legacy-model");
+        let thing = NamedNode::new_unchecked("http://example.com/thing");
+        s.insert(&Quad::new(ontology.clone(), rdf::TYPE, owl::ONTOLOGY.into_owned(), ontology.clone())).unwrap();
+        s.insert(&Quad::new(thing.clone(), rdf::TYPE, thing.clone(), model.clone())).unwrap();
+
+        let proposals = propose(&s);
+        assert_eq!(vec![(model.to_string(), GraphType::Model), (ontology.to_string(), GraphType::Ontology)],
+            proposals.iter().map(|p| (p.graph.clone(), p.graph_type)).collect::<Vec<_>>());
+
+        adopt(&s, &ontology, GraphType::Ontology).unwrap();
+        assert!(accounted_graph_list(&s).graphs.iter().any(|g| g.id == ontology.to_string() && g.graph_type == GraphType::Ontology));
+        assert_eq!(1, history::current_revision(&s, ontology.as_ref()).unwrap().number);
+        assert_eq!(1, propose(&s).len());
+        assert!(adopt(&s, &ontology, GraphType::Ontology).is_err());
+    }
+}
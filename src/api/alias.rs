@@ -0,0 +1,223 @@
+//! Short aliases for graphs, so a graph can be named in a URL as `go-plus` instead of a
+//! percent-encoded `<http://...>`.
+//!
+//! An alias is recorded in `<Meta>` on its graph:
+//! ```turtle
+//! <http://purl.obolibrary.org/obo/go/extensions/go-plus.owl> meta:alias "go-plus" .
+//! ```
+//! Path segments and query values are decoded before any route sees the store, so a graph named
+//! in a URL is only a [`GraphName`] until the route looks it up in [`Aliases`], which keeps every
+//! alias in memory as Rocket managed state: loaded from `<Meta>` at launch, and updated as aliases
+//! are set and removed.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, RwLock};
+
+use oxigraph::SledStore as Store;
+use oxigraph::model::{Literal, NamedNode, NamedNodeRef, NamedOrBlankNode, Quad, Term};
+
+use crate::meta;
+use crate::api::storage::apply_changes;
+
+/// Aliases are at most this long.
+const MAX_LENGTH: usize = 64;
+
+#[derive(Debug, PartialEq)]
+pub enum AliasError {
+    /// Aliases are lower case letters, digits, `-`, `_` and `.`, starting with a letter or digit.
+    Invalid(String),
+    /// The alias already names another graph.
+    Taken(String, NamedNode),
+    Storage(String),
+}
+
+impl fmt::Display for AliasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AliasError::Invalid(alias) => write!(f, "{:?} is not an alias: use lower case letters, digits, '-', '_' and '.'", alias),
+            AliasError::Taken(alias, graph) => write!(f, "{:?} is already the alias of {}", alias, graph),
+            AliasError::Storage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Whether `alias` could be an alias. Nothing with `<`, `:` or `/` in it could, so aliases are
+/// never mistaken for URIs.
+pub fn is_alias(alias: &str) -> bool {
+    let mut chars = alias.chars();
+    let starts_well = chars.next().map_or(false, |c| c.is_ascii_lowercase() || c.is_ascii_digit());
+    starts_well
+        && alias.len() <= MAX_LENGTH
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_' || c == '.')
+}
+
+/// The alias of `graph`, if it has one.
+pub fn alias_of(store: &Store, graph: NamedNodeRef) -> Option<String> {
+    store.quads_for_pattern(Some(graph.into()), Some(NamedNode::from(meta::alias).as_ref()), None, Some(meta::meta_graph_uri()))
+        .filter_map(Result::ok)
+        .find_map(|q| match q.object {
+            Term::Literal(alias) => Some(alias.value().to_owned()),
+            _ => None,
+        })
+}
+
+/// A graph as a URL names it: by its URI, or by an alias still to be looked up.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GraphName {
+    Uri(NamedNode),
+    Alias(String),
+}
+
+/// Every alias in `<Meta>`, shared between requests as Rocket managed state.
+#[derive(Default)]
+pub struct Aliases {
+    names: RwLock<HashMap<String, NamedNode>>,
+    /// Held while aliases are written, so that two requests can't both take an alias, without
+    /// keeping requests that only resolve aliases waiting on the store.
+    writing: Mutex<()>,
+}
+
+impl Aliases {
+    pub fn load(store: &Store) -> Aliases {
+        let aliases = Aliases::default();
+        aliases.reload(store);
+        aliases
+    }
+
+    /// Reads every alias in `<Meta>` again, replacing what was there.
+    pub fn reload(&self, store: &Store) {
+        let _writing = self.writing.lock().unwrap();
+        let names = store.quads_for_pattern(None, Some(NamedNode::from(meta::alias).as_ref()), None, Some(meta::meta_graph_uri()))
+            .filter_map(Result::ok)
+            .filter_map(|quad| match (quad.subject, quad.object) {
+                (NamedOrBlankNode::NamedNode(graph), Term::Literal(alias)) => Some((alias.value().to_owned(), graph)),
+                _ => None,
+            })
+            .collect();
+        *self.names.write().unwrap() = names;
+    }
+
+    /// The graph `alias` names.
+    pub fn resolve(&self, alias: &str) -> Option<NamedNode> {
+        self.names.read().unwrap().get(alias).cloned()
+    }
+
+    /// The graph `name` names, or why it names none.
+    pub fn graph(&self, name: GraphName) -> Result<NamedNode, String> {
+        match name {
+            GraphName::Uri(graph) => Ok(graph),
+            GraphName::Alias(alias) => self.resolve(&alias).ok_or_else(|| format!("No graph has the alias {:?}", alias)),
+        }
+    }
+
+    /// Makes `alias` the alias of `graph`, replacing any alias it had.
+    pub fn set(&self, store: &Store, graph: &NamedNode, alias: &str) -> Result<(), AliasError> {
+        if !is_alias(alias) {
+            return Err(AliasError::Invalid(alias.to_owned()));
+        }
+        let _writing = self.writing.lock().unwrap();
+        if let Some(other) = self.resolve(alias).filter(|other| other != graph) {
+            return Err(AliasError::Taken(alias.to_owned(), other));
+        }
+
+        let old = alias_of(store, graph.as_ref());
+        let remove: Vec<Quad> = old.iter().map(|old| alias_entry(graph, old)).collect();
+        apply_changes(store, &remove, &[alias_entry(graph, alias)]).map_err(AliasError::Storage)?;
+
+        let mut names = self.names.write().unwrap();
+        if let Some(old) = old {
+            names.remove(&old);
+        }
+        names.insert(alias.to_owned(), graph.clone());
+        Ok(())
+    }
+
+    /// Takes away the alias of `graph`, giving back what it was.
+    pub fn remove(&self, store: &Store, graph: &NamedNode) -> Result<Option<String>, AliasError> {
+        let _writing = self.writing.lock().unwrap();
+        let old = alias_of(store, graph.as_ref());
+        if let Some(old) = &old {
+            apply_changes(store, &[alias_entry(graph, old)], &[]).map_err(AliasError::Storage)?;
+            self.names.write().unwrap().remove(old);
+        }
+        Ok(old)
+    }
+}
+
+/// The path of a graph's resource: `/graph/<alias>` if it has an alias, or else its percent-encoded
+/// `<uri>`.
+pub fn graph_path(graph: NamedNodeRef, alias: Option<&str>) -> String {
+    match alias {
+        Some(alias) => format!("/graph/{}", alias),
+        None => format!("/graph/{}", percent_encode(&graph.to_string())),
+    }
+}
+
+/// Percent-encodes all but the unreserved characters, so the result is a single path segment or
+/// query value.
+pub fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn alias_entry(graph: &NamedNode, alias: &str) -> Quad {
+    Quad::new(graph.clone(), NamedNode::from(meta::alias), Literal::new_simple_literal(alias), meta::meta_graph_uri())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::GraphType;
+    use crate::api::storage::load_triples_into_new_graph;
+    use crate::api::storage::test::init_store;
+
+    #[test]
+    fn test_is_alias() {
+        assert!(is_alias("go-plus"));
+        assert!(is_alias("uberon.2021_01"));
+        assert!(!is_alias(""));
+        assert!(!is_alias("-go"));
+        assert!(!is_alias("GO"));
+        assert!(!is_alias("<http://example.com/G>"));
+        assert!(!is_alias("http://example.com/G"));
+    }
+
+    #[test]
+    fn test_set_and_remove() {
+        let (s, _f) = init_store();
+        let graph = NamedNode::new_unchecked("http://example.com/aliased");
+        let other = NamedNode::new_unchecked("http://example.com/other");
+        load_triples_into_new_graph(&s, graph.clone(), GraphType::Model, vec![]);
+        load_triples_into_new_graph(&s, other.clone(), GraphType::Model, vec![]);
+
+        let aliases = Aliases::load(&s);
+        aliases.set(&s, &graph, "test-aliased").unwrap();
+        assert_eq!(Some(graph.clone()), aliases.resolve("test-aliased"));
+        assert_eq!(Some("test-aliased".to_owned()), alias_of(&s, graph.as_ref()));
+        assert_eq!(Err(AliasError::Taken("test-aliased".into(), graph.clone())), aliases.set(&s, &other, "test-aliased"));
+        assert_eq!(Err(AliasError::Invalid("Not/An/Alias".into())), aliases.set(&s, &other, "Not/An/Alias"));
+
+        aliases.set(&s, &graph, "test-renamed").unwrap();
+        assert_eq!(None, aliases.resolve("test-aliased"));
+        let reloaded = Aliases::load(&s);
+        assert_eq!(Some(graph.clone()), reloaded.resolve("test-renamed"));
+        assert_eq!(Ok(graph.clone()), reloaded.graph(GraphName::Alias("test-renamed".into())));
+        assert!(reloaded.graph(GraphName::Alias("test-aliased".into())).is_err());
+
+        assert_eq!(Some("test-renamed".to_owned()), aliases.remove(&s, &graph).unwrap());
+        assert_eq!(None, aliases.resolve("test-renamed"));
+        assert_eq!(None, alias_of(&s, graph.as_ref()));
+    }
+
+    #[test]
+    fn test_graph_path() {
+        let graph = NamedNodeRef::new_unchecked("http://example.com/G");
+        assert_eq!("/graph/g", graph_path(graph, Some("g")));
+        assert_eq!("/graph/%3Chttp%3A%2F%2Fexample.com%2FG%3E", graph_path(graph, None));
+    }
+}
@@ -0,0 +1,428 @@
+//! Who is making a request, and what they may do with each graph.
+//!
+//! Requests authenticate with a static API key from the auth config, in an `X-API-Key` header, or
+//! with a JWT signed (HS256) with the config's shared secret, as `Authorization: Bearer <token>`.
+//! Either way the request acts as a user, who may be in groups, and who may be an admin.
+//!
+//! What users may do with a graph is granted per graph in `<Meta>`:
+//! ```turtle
+//! <G> meta:readableBy "alice" .
+//! <G> meta:writableBy "group:curators" .
+//! <G> meta:administeredBy "bob" .
+//! <G> meta:readableBy "*" .
+//! ```
+//! Each access includes the ones before it: write includes read, and admin includes write.
+//! `"*"` is everyone, including requests without credentials. Admins may do anything with any graph.
+//!
+//! Without an auth config the server is open, as it always was: every request acts as an admin.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+use chrono::Utc;
+
+use hmac::{Hmac, Mac, NewMac};
+
+use oxigraph::SledStore as Store;
+use oxigraph::model::{Literal, NamedNode, NamedNodeRef, NamedOrBlankNode, Quad, Term};
+
+use serde::{Deserialize, Serialize};
+
+use sha2::{Digest, Sha256};
+use sophia_api::term::SimpleIri;
+
+use strum::IntoEnumIterator;
+use strum_macros::{AsRefStr, EnumIter};
+
+use unicase::UniCase;
+
+use crate::api::storage::apply_changes;
+use crate::meta;
+
+/// Where the auth config is looked for, relative to the working directory like `metadata/`.
+pub fn auth_path() -> &'static Path {
+    Path::new("auth.json")
+}
+
+/// What a request may do with a graph. Each access includes the ones before it.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, EnumIter, AsRefStr)]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+    Read,
+    Write,
+    Admin,
+}
+
+impl Access {
+    /// The `<Meta>` predicate granting this access to a graph.
+    pub fn uri(&self) -> SimpleIri {
+        match self {
+            Access::Read => meta::readableBy,
+            Access::Write => meta::writableBy,
+            Access::Admin => meta::administeredBy,
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a str> for Access {
+    type Error = &'a str;
+
+    fn try_from(val: &'a str) -> Result<Access, Self::Error> {
+        let c = UniCase::new(val);
+        match Access::iter().find(|a| UniCase::new(a.as_ref()) == c) {
+            Some(a) => Ok(a),
+            None => Err(val),
+        }
+    }
+}
+
+/// A static API key, and the user it authenticates as.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiKey {
+    pub key: String,
+    pub user: String,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default)]
+    pub admin: bool,
+}
+
+/// How bearer tokens are verified. Tokens name their user with `sub`, and may carry `groups`,
+/// `admin` and `exp` claims.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct JwtConfig {
+    pub secret: String,
+    /// If given, tokens must have been issued (`iss`) by it.
+    #[serde(default)]
+    pub issuer: Option<String>,
+}
+
+/// The contents of `auth.json`.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub keys: Vec<ApiKey>,
+    #[serde(default)]
+    pub jwt: Option<JwtConfig>,
+    /// What requests without credentials may do with every graph.
+    #[serde(default)]
+    pub anonymous: Option<Access>,
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    sub: String,
+    #[serde(default)]
+    groups: Vec<String>,
+    #[serde(default)]
+    admin: bool,
+    #[serde(default)]
+    exp: Option<i64>,
+    #[serde(default)]
+    iss: Option<String>,
+}
+
+/// Authentication for the server, shared between requests as Rocket managed state.
+#[derive(Debug, Default)]
+pub struct Auth {
+    config: Option<AuthConfig>,
+}
+
+impl Auth {
+    /// Reads the auth config at `path`. Without a file there, auth is off. A file that can't be
+    /// read is an error rather than an open server.
+    pub fn load(path: &Path) -> Result<Auth, String> {
+        if !path.exists() {
+            return Ok(Auth { config: None });
+        }
+        let json = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let config = serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Auth::new(config))
+    }
+
+    pub fn new(config: AuthConfig) -> Auth {
+        Auth { config: Some(config) }
+    }
+
+    /// The principal a request acts as, from its `X-API-Key` or bearer token. A request with
+    /// neither is anonymous; one with credentials that don't check out is refused.
+    pub fn authenticate(&self, api_key: Option<&str>, bearer: Option<&str>) -> Result<Principal, String> {
+        let config = match &self.config {
+            Some(config) => config,
+            None => return Ok(Principal::unrestricted()),
+        };
+
+        let mut principal = if let Some(key) = api_key {
+            let key = Sha256::digest(key.as_bytes());
+            match config.keys.iter().find(|k| Sha256::digest(k.key.as_bytes()) == key) {
+                Some(k) => Principal {
+                    user: Some(k.user.clone()),
+                    groups: k.groups.clone(),
+                    admin: k.admin,
+                    everyone: None,
+                },
+                None => return Err("Unknown API key".to_owned()),
+            }
+        } else if let Some(token) = bearer {
+            let jwt = config.jwt.as_ref().ok_or_else(|| "Bearer tokens are not accepted".to_owned())?;
+            let claims = verify_jwt(token, jwt)?;
+            Principal {
+                user: Some(claims.sub),
+                groups: claims.groups,
+                admin: claims.admin,
+                everyone: None,
+            }
+        } else {
+            Principal::default()
+        };
+
+        principal.everyone = config.anonymous;
+        Ok(principal)
+    }
+}
+
+/// Checks an HS256 JWT's signature, expiry and issuer, returning its claims.
+fn verify_jwt(token: &str, jwt: &JwtConfig) -> Result<Claims, String> {
+    let parts: Vec<&str> = token.split('.').collect();
+    if parts.len() != 3 {
+        return Err("Malformed token".to_owned());
+    }
+
+    let header: serde_json::Value = serde_json::from_slice(&decode_segment(parts[0])?).map_err(|e| e.to_string())?;
+    if header.get("alg").and_then(|a| a.as_str()) != Some("HS256") {
+        return Err("Only HS256 tokens are accepted".to_owned());
+    }
+
+    let mut mac = Hmac::<Sha256>::new_varkey(jwt.secret.as_bytes()).map_err(|e| e.to_string())?;
+    mac.update(parts[0].as_bytes());
+    mac.update(b".");
+    mac.update(parts[1].as_bytes());
+    mac.verify(&decode_segment(parts[2])?).map_err(|_| "Bad token signature".to_owned())?;
+
+    let claims: Claims = serde_json::from_slice(&decode_segment(parts[1])?).map_err(|e| e.to_string())?;
+    if claims.exp.map_or(false, |exp| exp <= Utc::now().timestamp()) {
+        return Err("Token has expired".to_owned());
+    }
+    if jwt.issuer.is_some() && claims.iss != jwt.issuer {
+        return Err("Token is from the wrong issuer".to_owned());
+    }
+    Ok(claims)
+}
+
+fn decode_segment(segment: &str) -> Result<Vec<u8>, String> {
+    base64::decode_config(segment, base64::URL_SAFE_NO_PAD).map_err(|e| e.to_string())
+}
+
+/// Who a request acts as. Anonymous requests have no user.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Principal {
+    pub user: Option<String>,
+    pub groups: Vec<String>,
+    pub admin: bool,
+    /// What everyone may do with every graph, as configured for anonymous requests.
+    pub everyone: Option<Access>,
+}
+
+impl Principal {
+    /// What every request is when auth is off.
+    pub fn unrestricted() -> Principal {
+        Principal { admin: true, ..Principal::default() }
+    }
+
+    pub fn is_anonymous(&self) -> bool {
+        self.user.is_none() && !self.admin
+    }
+
+    /// The agents grants to this principal are recorded for: its user, its groups and everyone.
+    fn agents(&self) -> Vec<String> {
+        let mut agents = vec![EVERYONE.to_owned()];
+        agents.extend(self.user.iter().cloned());
+        agents.extend(self.groups.iter().map(|g| format!("{}{}", GROUP_PREFIX, g)));
+        agents
+    }
+
+    fn may_everywhere(&self, access: Access) -> bool {
+        self.admin || self.everyone.map_or(false, |e| e >= access)
+    }
+
+    /// Whether this principal has `access` to `graph`.
+    pub fn may(&self, store: &Store, graph: NamedNodeRef, access: Access) -> bool {
+        if self.may_everywhere(access) {
+            return true;
+        }
+        let agents = self.agents();
+        grants(store, graph).into_iter().any(|g| g.access >= access && agents.contains(&g.agent))
+    }
+
+    /// Whether this principal may make new graphs: anyone who isn't anonymous, or anyone at all
+    /// if anonymous requests may write.
+    pub fn may_create(&self) -> bool {
+        !self.is_anonymous() || self.may_everywhere(Access::Write)
+    }
+
+    /// The graphs this principal has `access` to, or `None` if that is every graph.
+    pub fn permitted(&self, store: &Store, access: Access) -> Option<HashSet<NamedNode>> {
+        if self.may_everywhere(access) {
+            return None;
+        }
+        let agents = self.agents();
+        let graphs = Access::iter()
+            .filter(|a| *a >= access)
+            .flat_map(|a| {
+                let predicate = NamedNode::from(a.uri());
+                store.quads_for_pattern(None, Some(predicate.as_ref()), None, Some(meta::meta_graph_uri()))
+                    .filter_map(Result::ok)
+                    .collect::<Vec<_>>()
+            })
+            .filter_map(|q| match (q.subject, q.object) {
+                (NamedOrBlankNode::NamedNode(graph), Term::Literal(agent)) if agents.iter().any(|a| a == agent.value()) => Some(graph),
+                _ => None,
+            })
+            .collect();
+        Some(graphs)
+    }
+}
+
+/// The agent that grants to everyone are recorded for.
+pub const EVERYONE: &str = "*";
+
+/// Grants to a group are recorded for `group:<name>`.
+pub const GROUP_PREFIX: &str = "group:";
+
+/// One agent's access to a graph.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Grant {
+    pub agent: String,
+    pub access: Access,
+}
+
+/// Everyone who has been granted access to a graph.
+#[derive(Serialize, Debug)]
+pub struct GraphAccess {
+    pub graph: String,
+    pub grants: Vec<Grant>,
+}
+
+impl GraphAccess {
+    pub fn of(store: &Store, graph: &NamedNode) -> GraphAccess {
+        GraphAccess {
+            graph: graph.to_string(),
+            grants: grants(store, graph.as_ref()),
+        }
+    }
+}
+
+/// The grants recorded for `graph` in `<Meta>`, strongest first.
+pub fn grants(store: &Store, graph: NamedNodeRef) -> Vec<Grant> {
+    let mut grants: Vec<Grant> = Access::iter()
+        .flat_map(|access| {
+            let predicate = NamedNode::from(access.uri());
+            store.quads_for_pattern(Some(graph.into()), Some(predicate.as_ref()), None, Some(meta::meta_graph_uri()))
+                .filter_map(Result::ok)
+                .filter_map(move |q| match q.object {
+                    Term::Literal(agent) => Some(Grant { agent: agent.value().to_owned(), access }),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    grants.sort_by(|a, b| match b.access.cmp(&a.access) {
+        Ordering::Equal => a.agent.cmp(&b.agent),
+        other => other,
+    });
+    grants
+}
+
+/// Gives `agent` exactly `access` to `graph`, replacing whatever it had.
+pub fn grant(store: &Store, graph: &NamedNode, agent: &str, access: Access) -> Result<(), String> {
+    let insert = vec![grant_entry(graph, agent, access)];
+    apply_changes(store, &grant_entries(store, graph, agent), &insert)
+}
+
+/// Takes away whatever access `agent` had to `graph`.
+pub fn revoke(store: &Store, graph: &NamedNode, agent: &str) -> Result<(), String> {
+    apply_changes(store, &grant_entries(store, graph, agent), &[])
+}
+
+fn grant_entry(graph: &NamedNode, agent: &str, access: Access) -> Quad {
+    Quad::new(graph.clone(), NamedNode::from(access.uri()), Literal::new_simple_literal(agent), meta::meta_graph_uri())
+}
+
+fn grant_entries(store: &Store, graph: &NamedNode, agent: &str) -> Vec<Quad> {
+    grants(store, graph.as_ref())
+        .into_iter()
+        .filter(|g| g.agent == agent)
+        .map(|g| grant_entry(graph, &g.agent, g.access))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::storage::test::init_store;
+
+    fn sign(claims: &str, secret: &str) -> String {
+        let encode = |s: &[u8]| base64::encode_config(s, base64::URL_SAFE_NO_PAD);
+        let signed = format!("{}.{}", encode(br#"{"alg":"HS256","typ":"JWT"}"#), encode(claims.as_bytes()));
+        let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).unwrap();
+        mac.update(signed.as_bytes());
+        format!("{}.{}", signed, encode(&mac.finalize().into_bytes()))
+    }
+
+    fn config() -> AuthConfig {
+        AuthConfig {
+            keys: vec![ApiKey { key: "k1".into(), user: "alice".into(), groups: vec!["curators".into()], admin: false }],
+            jwt: Some(JwtConfig { secret: "s3cret".into(), issuer: None }),
+            anonymous: Some(Access::Read),
+        }
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let auth = Auth::new(config());
+
+        let alice = auth.authenticate(Some("k1"), None).unwrap();
+        assert_eq!(Some("alice".to_owned()), alice.user);
+        assert_eq!(Some(Access::Read), alice.everyone);
+        assert!(auth.authenticate(Some("k2"), None).is_err());
+
+        let bob = auth.authenticate(None, Some(&sign(r#"{"sub":"bob","admin":true}"#, "s3cret"))).unwrap();
+        assert_eq!(Some("bob".to_owned()), bob.user);
+        assert!(bob.admin);
+        assert!(auth.authenticate(None, Some(&sign(r#"{"sub":"bob","admin":true}"#, "guess"))).is_err());
+        assert!(auth.authenticate(None, Some(&sign(r#"{"sub":"bob","exp":1}"#, "s3cret"))).is_err());
+
+        assert!(auth.authenticate(None, None).unwrap().is_anonymous());
+        assert!(Auth::default().authenticate(None, None).unwrap().admin);
+    }
+
+    #[test]
+    fn test_grants() {
+        let (s, _f) = init_store();
+        let graph = NamedNode::new_unchecked("http://example.com/model");
+        let alice = Auth::new(AuthConfig { anonymous: None, ..config() }).authenticate(Some("k1"), None).unwrap();
+        let anonymous = Principal::default();
+
+        assert!(!alice.may(&s, graph.as_ref(), Access::Read));
+        grant(&s, &graph, "group:curators", Access::Write).unwrap();
+        assert!(alice.may(&s, graph.as_ref(), Access::Read));
+        assert!(alice.may(&s, graph.as_ref(), Access::Write));
+        assert!(!alice.may(&s, graph.as_ref(), Access::Admin));
+        assert!(!anonymous.may(&s, graph.as_ref(), Access::Read));
+
+        grant(&s, &graph, EVERYONE, Access::Read).unwrap();
+        assert!(anonymous.may(&s, graph.as_ref(), Access::Read));
+        assert_eq!(vec![graph.clone()], anonymous.permitted(&s, Access::Read).unwrap().into_iter().collect::<Vec<_>>());
+        assert!(anonymous.permitted(&s, Access::Write).unwrap().is_empty());
+
+        grant(&s, &graph, "group:curators", Access::Read).unwrap();
+        assert_eq!(2, grants(&s, graph.as_ref()).len());
+        assert!(!alice.may(&s, graph.as_ref(), Access::Write));
+
+        revoke(&s, &graph, "group:curators").unwrap();
+        assert_eq!(vec![Grant { agent: EVERYONE.to_owned(), access: Access::Read }], grants(&s, graph.as_ref()));
+    }
+}
@@ -0,0 +1,280 @@
+//! Term suggestions for curators typing class names.
+//!
+//! Each Ontology graph gets a trie of its terms' labels and synonyms, lowercased. Every label is
+//! also inserted from the start of each later word, so `neur` finds "motor neuron" as well as
+//! "neuron". The tries are kept in memory and rebuilt whenever their Ontology graph is written.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::RwLock;
+
+use oxigraph::SledStore as Store;
+use oxigraph::model::{NamedNode, NamedNodeRef, NamedOrBlankNode, Term};
+use oxigraph::model::vocab::rdfs;
+
+use serde::Serialize;
+
+use crate::api::GraphType;
+use crate::api::storage::graphs_of_type;
+
+/// Predicates whose values are suggested as synonyms of a term's label.
+const SYNONYM_PREDICATES: [&str; 5] = [
+    "http://www.geneontology.org/formats/oboInOwl#hasExactSynonym",
+    "http://www.geneontology.org/formats/oboInOwl#hasRelatedSynonym",
+    "http://www.geneontology.org/formats/oboInOwl#hasBroadSynonym",
+    "http://www.geneontology.org/formats/oboInOwl#hasNarrowSynonym",
+    "http://www.w3.org/2004/02/skos/core#altLabel",
+];
+
+/// Most suggestions given at once.
+pub const MAX_SUGGESTIONS: usize = 100;
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Suggestion {
+    pub id: String,
+    pub label: String,
+    /// The label or synonym that matched.
+    pub matched: String,
+    pub synonym: bool,
+    pub parent: Option<String>,
+    pub graph: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Suggestions {
+    pub context: String,
+    pub query: String,
+    pub suggestions: Vec<Suggestion>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    term: NamedNode,
+    label: String,
+    text: String,
+    synonym: bool,
+    parent: Option<String>,
+}
+
+#[derive(Default, Debug)]
+struct Node {
+    children: BTreeMap<char, Node>,
+    /// Entries whose text ends here, and whether they were inserted from a later word.
+    entries: Vec<(usize, bool)>,
+}
+
+#[derive(Default, Debug)]
+struct Trie {
+    root: Node,
+    entries: Vec<Entry>,
+}
+
+impl Trie {
+    fn add(&mut self, entry: Entry) {
+        let i = self.entries.len();
+        let text = entry.text.to_lowercase();
+        self.insert(&text, (i, false));
+        for (position, _) in text.match_indices(|c: char| !c.is_alphanumeric()) {
+            let rest = text[position..].trim_start_matches(|c: char| !c.is_alphanumeric());
+            if !rest.is_empty() {
+                self.insert(rest, (i, true));
+            }
+        }
+        self.entries.push(entry);
+    }
+
+    fn insert(&mut self, key: &str, entry: (usize, bool)) {
+        let mut node = &mut self.root;
+        for c in key.chars() {
+            node = node.children.entry(c).or_insert_with(Node::default);
+        }
+        if !node.entries.contains(&entry) {
+            node.entries.push(entry);
+        }
+    }
+
+    /// Up to `max` entries under `prefix`, breadth first so the shortest completions come first.
+    fn completions(&self, prefix: &str, max: usize) -> Vec<(&Entry, bool, usize)> {
+        let mut node = &self.root;
+        for c in prefix.chars() {
+            match node.children.get(&c) {
+                Some(child) => node = child,
+                None => return Vec::new(),
+            }
+        }
+
+        let mut found = Vec::new();
+        let mut queue = VecDeque::new();
+        queue.push_back((node, 0));
+        while let Some((node, depth)) = queue.pop_front() {
+            for (i, from_word) in node.entries.iter() {
+                found.push((&self.entries[*i], *from_word, depth));
+                if found.len() >= max {
+                    return found;
+                }
+            }
+            queue.extend(node.children.values().map(|child| (child, depth + 1)));
+        }
+        found
+    }
+}
+
+/// The tries of all Ontology graphs, shared between requests as Rocket managed state.
+#[derive(Default)]
+pub struct Autocomplete {
+    tries: RwLock<HashMap<NamedNode, Trie>>,
+}
+
+impl Autocomplete {
+    pub fn build(store: &Store) -> Autocomplete {
+        let autocomplete = Autocomplete::default();
+        for graph in graphs_of_type(store, GraphType::Ontology) {
+            autocomplete.rebuild_graph(store, &graph);
+        }
+        autocomplete
+    }
+
+    /// Rebuilds the trie of the Ontology graph `graph` from what is in the store now.
+    pub fn rebuild_graph(&self, store: &Store, graph: &NamedNode) {
+        let labels = literals(store, graph, rdfs::LABEL);
+        let mut trie = Trie::default();
+
+        let parents: HashMap<NamedNode, String> = store
+            .quads_for_pattern(None, Some(rdfs::SUB_CLASS_OF), None, Some(graph.as_ref().into()))
+            .filter_map(Result::ok)
+            .filter_map(|q| match (q.subject, q.object) {
+                (NamedOrBlankNode::NamedNode(s), Term::NamedNode(o)) => labels.get(&o).map(|l| (s, l[0].clone())),
+                _ => None,
+            })
+            .collect();
+
+        for (term, term_labels) in labels.iter() {
+            for label in term_labels {
+                trie.add(Entry {
+                    term: term.clone(),
+                    label: term_labels[0].clone(),
+                    text: label.clone(),
+                    synonym: false,
+                    parent: parents.get(term).cloned(),
+                });
+            }
+        }
+        for predicate in SYNONYM_PREDICATES.iter() {
+            for (term, synonyms) in literals(store, graph, NamedNodeRef::new_unchecked(predicate)) {
+                let label = match labels.get(&term) {
+                    Some(l) => l[0].clone(),
+                    None => continue,
+                };
+                for synonym in synonyms {
+                    trie.add(Entry {
+                        term: term.clone(),
+                        label: label.clone(),
+                        text: synonym,
+                        synonym: true,
+                        parent: parents.get(&term).cloned(),
+                    });
+                }
+            }
+        }
+
+        self.tries.write().unwrap().insert(graph.clone(), trie);
+    }
+
+    pub fn remove_graph(&self, graph: &NamedNode) {
+        self.tries.write().unwrap().remove(graph);
+    }
+
+    /// The best `limit` terms whose label or synonym starts with `query`, in the `ontologies` or in
+    /// all Ontology graphs. Whole-label matches beat matches on a later word, labels beat synonyms,
+    /// and shorter completions beat longer ones. There are never more than `MAX_SUGGESTIONS`.
+    pub fn suggest(&self, query: &str, ontologies: Option<&HashSet<NamedNode>>, limit: usize) -> Vec<Suggestion> {
+        let limit = limit.min(MAX_SUGGESTIONS);
+        let prefix = query.trim().to_lowercase();
+        if prefix.is_empty() || limit == 0 {
+            return Vec::new();
+        }
+
+        let tries = self.tries.read().unwrap();
+        let mut candidates: Vec<(&NamedNode, &Entry, bool, usize)> = tries.iter()
+            .filter(|(graph, _)| ontologies.map_or(true, |o| o.contains(*graph)))
+            .flat_map(|(graph, trie)| {
+                trie.completions(&prefix, limit * 10)
+                    .into_iter()
+                    .map(move |(entry, from_word, depth)| (graph, entry, from_word, depth))
+            })
+            .collect();
+        candidates.sort_by(|a, b| (a.2, a.1.synonym, a.3, &a.1.text).cmp(&(b.2, b.1.synonym, b.3, &b.1.text)));
+
+        let mut seen = HashSet::new();
+        candidates.into_iter()
+            .filter(|(_, entry, _, _)| seen.insert(entry.term.clone()))
+            .take(limit)
+            .map(|(graph, entry, _, _)| Suggestion {
+                id: entry.term.as_str().to_owned(),
+                label: entry.label.clone(),
+                matched: entry.text.clone(),
+                synonym: entry.synonym,
+                parent: entry.parent.clone(),
+                graph: graph.as_str().to_owned(),
+            })
+            .collect()
+    }
+}
+
+/// The literal values of `predicate` on each term in `graph`, in the order the store has them.
+fn literals(store: &Store, graph: &NamedNode, predicate: NamedNodeRef) -> HashMap<NamedNode, Vec<String>> {
+    let mut values: HashMap<NamedNode, Vec<String>> = HashMap::new();
+    for quad in store.quads_for_pattern(None, Some(predicate), None, Some(graph.as_ref().into())).filter_map(Result::ok) {
+        if let (NamedOrBlankNode::NamedNode(s), Term::Literal(l)) = (quad.subject, quad.object) {
+            values.entry(s).or_insert_with(Vec::new).push(l.value().to_owned());
+        }
+    }
+    values
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::storage::load_turtle_into_new_graph;
+    use crate::api::storage::test::init_store;
+
+    const ONTOLOGY: &str = r#"
+        @prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .
+        @prefix obo: <http://purl.obolibrary.org/obo/> .
+        @prefix oboInOwl: <http://www.geneontology.org/formats/oboInOwl#> .
+
+        obo:CL_0000000 rdfs:label "cell" .
+        obo:CL_0000540 rdfs:label "neuron" ; rdfs:subClassOf obo:CL_0000000 ; oboInOwl:hasExactSynonym "nerve cell" .
+        obo:CL_0000100 rdfs:label "motor neuron" ; rdfs:subClassOf obo:CL_0000540 .
+        obo:CL_0000125 rdfs:label "neuroglial cell" ; oboInOwl:hasExactSynonym "glial cell" .
+    "#;
+
+    fn ids(suggestions: &[Suggestion]) -> Vec<&str> {
+        suggestions.iter().map(|s| s.id.trim_start_matches("http://purl.obolibrary.org/obo/")).collect()
+    }
+
+    #[test]
+    fn test_suggest() {
+        let (s, _f) = init_store();
+        let graph = NamedNode::new_unchecked("http://example.com/cl");
+        load_turtle_into_new_graph(&s, graph.clone(), GraphType::Ontology, ONTOLOGY.as_bytes().to_vec());
+        let autocomplete = Autocomplete::build(&s);
+
+        let suggestions = autocomplete.suggest("Neur", None, 10);
+        assert_eq!(vec!["CL_0000540", "CL_0000125", "CL_0000100"], ids(&suggestions));
+        assert_eq!(Some("cell".to_owned()), suggestions[0].parent);
+        assert_eq!(Some("neuron".to_owned()), suggestions[2].parent);
+
+        let suggestions = autocomplete.suggest("nerve", Some(&vec![graph.clone()].into_iter().collect()), 10);
+        assert_eq!(vec!["CL_0000540"], ids(&suggestions));
+        assert!(suggestions[0].synonym);
+        assert_eq!("neuron", suggestions[0].label);
+        assert_eq!("nerve cell", suggestions[0].matched);
+
+        assert_eq!(1, autocomplete.suggest("neur", None, 1).len());
+        assert_eq!(3, autocomplete.suggest("neur", None, usize::MAX).len());
+        assert!(autocomplete.suggest("neur", Some(&vec![NamedNode::new_unchecked("http://example.com/other")].into_iter().collect()), 10).is_empty());
+
+        autocomplete.remove_graph(&graph);
+        assert!(autocomplete.suggest("neur", None, 10).is_empty());
+    }
+}
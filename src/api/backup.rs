@@ -0,0 +1,200 @@
+//! Backups of the whole store, every graph including `<Meta>`, `<MetaOnt>` and `<History>`, as
+//! gzipped N-Quads or TriG.
+//!
+//! A backup is restored into a fresh, empty store, and then checked: the store must hold as many
+//! quads as the archive did, each graph registered in `<Meta>` must still have the content hash
+//! recorded for it, and `<Meta>` must agree with the graphs in the store, as `fsck` finds.
+
+use std::convert::TryFrom;
+use std::io::{BufRead, BufReader, Write};
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+use oxigraph::SledStore as Store;
+use oxigraph::io::{DatasetFormat, DatasetParser, DatasetSerializer};
+use oxigraph::model::NamedNode;
+
+use serde::Serialize;
+
+use strum::IntoEnumIterator;
+use strum_macros::{AsRefStr, EnumIter};
+
+use unicase::UniCase;
+
+use crate::meta;
+use crate::api::unbracket;
+use crate::api::fsck::{self, FsckReport};
+use crate::api::storage::{accounted_graph_list, apply_changes, content_hash, graph_triples, recorded_content_hash};
+
+/// Restored quads are written this many at a time, each batch in one transaction.
+const RESTORE_BATCH_SIZE: usize = 10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, EnumIter, AsRefStr)]
+pub enum ArchiveFormat {
+    #[strum(serialize = "nquads")]
+    NQuads,
+    #[strum(serialize = "trig")]
+    TriG,
+}
+
+impl ArchiveFormat {
+    pub fn dataset_format(&self) -> DatasetFormat {
+        match self {
+            ArchiveFormat::NQuads => DatasetFormat::NQuads,
+            ArchiveFormat::TriG => DatasetFormat::TriG,
+        }
+    }
+
+    /// The usual file name ending of an archive in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::NQuads => "nq.gz",
+            ArchiveFormat::TriG => "trig.gz",
+        }
+    }
+
+    /// The format of an archive going by its file name, if it ends in `.nq.gz` or `.trig.gz`.
+    pub fn from_file_name(name: &str) -> Option<ArchiveFormat> {
+        ArchiveFormat::iter().find(|f| name.ends_with(&format!(".{}", f.extension())))
+    }
+}
+
+impl<'a> TryFrom<&'a str> for ArchiveFormat {
+    type Error = &'a str;
+
+    fn try_from(val: &'a str) -> Result<ArchiveFormat, Self::Error> {
+        let c = UniCase::new(val);
+        match ArchiveFormat::iter().find(|f| UniCase::new(f.as_ref()) == c) {
+            Some(f) => Ok(f),
+            None => Err(val),
+        }
+    }
+}
+
+/// What a restore found.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct RestoreReport {
+    pub quads: usize,
+    pub graphs: usize,
+    /// Registered graphs whose content no longer matches their recorded hash.
+    pub inconsistent: Vec<String>,
+    /// What checking the restored store as `fsck` does found.
+    pub fsck: FsckReport,
+}
+
+impl RestoreReport {
+    pub fn is_consistent(&self) -> bool {
+        self.inconsistent.is_empty() && self.fsck.is_clean()
+    }
+}
+
+/// Writes every quad in the store to `writer`, gzipped, giving back how many were written.
+pub fn dump(store: &Store, format: ArchiveFormat, writer: impl Write) -> Result<usize, String> {
+    let mut encoder = GzEncoder::new(writer, Compression::default());
+    let mut quads = DatasetSerializer::from_format(format.dataset_format())
+        .quad_writer(&mut encoder)
+        .map_err(|e| e.to_string())?;
+    let mut dumped = 0;
+    for quad in store.iter() {
+        quads.write(&quad.map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        dumped += 1;
+    }
+    quads.finish().map_err(|e| e.to_string())?;
+    encoder.finish().map_err(|e| e.to_string())?;
+    Ok(dumped)
+}
+
+/// Loads a gzipped archive into `store`, which must be empty, and checks what was loaded.
+pub fn restore(store: &Store, format: ArchiveFormat, archive: impl BufRead) -> Result<RestoreReport, String> {
+    if !store.is_empty() {
+        return Err("Backups can only be restored into an empty store".to_owned());
+    }
+
+    let quads = DatasetParser::from_format(format.dataset_format())
+        .read_quads(BufReader::new(GzDecoder::new(archive)))
+        .map_err(|e| e.to_string())?;
+    let mut archived = 0;
+    let mut batch = Vec::with_capacity(RESTORE_BATCH_SIZE);
+    for quad in quads {
+        batch.push(quad.map_err(|e| format!("Archive is damaged after {} quads: {}", archived, e))?);
+        archived += 1;
+        if batch.len() == RESTORE_BATCH_SIZE {
+            apply_changes(store, &[], &batch)?;
+            batch.clear();
+        }
+    }
+    apply_changes(store, &[], &batch)?;
+    if store.len() != archived {
+        return Err(format!("Archive had {} quads but {} were restored", archived, store.len()));
+    }
+
+    verify(store, archived)
+}
+
+/// Checks that `<Meta>` came through, that the graphs it registers have their recorded content,
+/// and that `fsck` finds nothing wrong.
+fn verify(store: &Store, quads: usize) -> Result<RestoreReport, String> {
+    if store.quads_for_pattern(None, None, None, Some(meta::meta_graph_uri())).next().is_none() {
+        return Err("Archive has no <Meta> graph".to_owned());
+    }
+
+    let graphs: Vec<NamedNode> = accounted_graph_list(store)
+        .graphs
+        .into_iter()
+        .filter_map(|g| NamedNode::new(unbracket(&g.id)).ok())
+        .collect();
+    let inconsistent = graphs.iter()
+        .filter(|g| match recorded_content_hash(store, g.as_ref()) {
+            Some(recorded) => Ok(recorded) != content_hash(&graph_triples(store, g.as_ref())),
+            None => false,
+        })
+        .map(|g| g.to_string())
+        .collect();
+
+    let fsck = fsck::check(store, false)?;
+
+    Ok(RestoreReport { quads, graphs: graphs.len(), inconsistent, fsck })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use oxigraph::model::Triple;
+    use oxigraph::model::vocab::rdf;
+    use crate::api::GraphType;
+    use crate::api::storage::load_triples_into_new_graph;
+    use crate::api::storage::test::{init_store, make_temp_store};
+
+    #[test]
+    fn test_dump_and_restore() {
+        let (s, _f) = init_store();
+        let graph = NamedNode::new_unchecked("http://example.com/backed-up");
+        let triple = Triple::new(NamedNode::new_unchecked("http://example.com/thing"), rdf::TYPE, NamedNode::new_unchecked("http://example.com/Thing"));
+        load_triples_into_new_graph(&s, graph, GraphType::Model, vec![triple]);
+
+        for format in ArchiveFormat::iter() {
+            let mut archive = Vec::new();
+            let dumped = dump(&s, format, &mut archive).unwrap();
+            assert_eq!(s.len(), dumped);
+
+            let (restored, _r) = make_temp_store();
+            let report = restore(&restored, format, Cursor::new(&archive)).unwrap();
+            assert_eq!(dumped, report.quads);
+            assert_eq!(accounted_graph_list(&s).graphs.len(), report.graphs);
+            assert!(report.is_consistent());
+            assert!(report.fsck.problems.is_empty());
+
+            assert!(restore(&restored, format, Cursor::new(&archive)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_archive_format() {
+        assert_eq!(Ok(ArchiveFormat::TriG), ArchiveFormat::try_from("TriG"));
+        assert_eq!(Some(ArchiveFormat::NQuads), ArchiveFormat::from_file_name("backup.nq.gz"));
+        assert_eq!(None, ArchiveFormat::from_file_name("backup.ttl"));
+    }
+}
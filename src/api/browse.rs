@@ -0,0 +1,322 @@
+//! What the HTML pages show, worked out from the store for the templates in `templates/` to
+//! render: the graphs grouped by type, a page of a graph's triples with labels for the IRIs in
+//! it, and SPARQL results as a table.
+//!
+//! Every IRI is shown by its `rdfs:label` in the graphs the requester may read, if it has one,
+//! and links to its description under `/resource`.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use oxigraph::SledStore as Store;
+use oxigraph::model::{GraphName, GraphNameRef, NamedNode, NamedOrBlankNode, Term, Triple};
+use oxigraph::model::vocab::rdfs;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::api::{unbracket, GraphData, GraphType};
+use crate::api::provenance;
+use crate::api::resource::{resource_path, Description};
+use crate::api::shacl::inferred_graph;
+
+/// Triples shown on each page of a graph.
+pub const PAGE_SIZE: usize = 100;
+
+/// A term as shown on a page, linking to its description if it is an IRI.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Cell {
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub href: Option<String>,
+    /// The IRI, when `text` is its label.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iri: Option<String>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Row {
+    pub subject: Cell,
+    pub predicate: Cell,
+    pub object: Cell,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct GraphLink {
+    pub id: String,
+    pub href: String,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct GraphGroup {
+    pub graph_type: GraphType,
+    pub graphs: Vec<GraphLink>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct GraphPage {
+    pub graph: GraphLink,
+    pub graph_type: GraphType,
+    pub triples: usize,
+    pub page: usize,
+    pub pages: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+    pub rows: Vec<Row>,
+    /// The Inferred graph worked out from this one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inferences: Option<GraphLink>,
+    /// The graphs this Inferred or Closure graph is worked out from.
+    pub inferred_from: Vec<GraphLink>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ResourcePage {
+    pub iri: String,
+    pub title: String,
+    pub rows: Vec<Row>,
+    pub graphs: Vec<String>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ResultsTable {
+    pub variables: Vec<String>,
+    pub rows: Vec<Vec<Cell>>,
+}
+
+/// The page a graph is browsed at.
+pub fn graph_href(graph: &GraphData) -> String {
+    format!("/ui{}", graph.path)
+}
+
+fn graph_link(graph: &GraphData) -> GraphLink {
+    GraphLink { id: unbracket(&graph.id).to_owned(), href: graph_href(graph) }
+}
+
+/// `graphs` grouped by their type, in the order the types are declared in.
+pub fn grouped(graphs: &[GraphData]) -> Vec<GraphGroup> {
+    let mut groups: BTreeMap<usize, GraphGroup> = BTreeMap::new();
+    for graph in graphs {
+        groups.entry(graph.graph_type as usize)
+            .or_insert_with(|| GraphGroup { graph_type: graph.graph_type, graphs: Vec::new() })
+            .graphs
+            .push(graph_link(graph));
+    }
+    let mut groups: Vec<GraphGroup> = groups.into_iter().map(|(_, g)| g).collect();
+    for group in groups.iter_mut() {
+        group.graphs.sort_by(|a, b| a.id.cmp(&b.id));
+    }
+    groups
+}
+
+/// Page `page`, counting from 1, of `graph`'s triples, or the last page if there aren't that
+/// many. `readable` are the graphs the requester may read, which labels are looked up in and
+/// which the related graphs are kept to.
+pub fn graph_page(store: &Store, graph: &GraphData, readable: &[GraphData], page: usize) -> GraphPage {
+    let name = NamedNode::new_unchecked(unbracket(&graph.id));
+    let in_graph = GraphNameRef::NamedNode(name.as_ref());
+    let triples = store.quads_for_pattern(None, None, None, Some(in_graph)).count();
+    let pages = (triples + PAGE_SIZE - 1) / PAGE_SIZE;
+    let page = page.min(pages).max(1);
+    let shown: Vec<Triple> = store.quads_for_pattern(None, None, None, Some(in_graph))
+        .filter_map(Result::ok)
+        .skip((page - 1) * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .map(Triple::from)
+        .collect();
+
+    let labels = labels_of(store, shown.iter().flat_map(iris_of), readable);
+    let rows = shown.iter()
+        .map(|t| Row {
+            subject: cell(&t.subject.clone().into(), &labels),
+            predicate: cell(&t.predicate.clone().into(), &labels),
+            object: cell(&t.object, &labels),
+        })
+        .collect();
+
+    let related = |g: &NamedNode| readable.iter().find(|r| r.id == g.to_string()).map(graph_link);
+    let href = graph_href(graph);
+    GraphPage {
+        graph: graph_link(graph),
+        graph_type: graph.graph_type,
+        triples,
+        page,
+        pages,
+        previous: if page > 1 { Some(format!("{}?page={}", href, page - 1)) } else { None },
+        next: if page < pages { Some(format!("{}?page={}", href, page + 1)) } else { None },
+        rows,
+        inferences: inferred_graph(store, name.as_ref()).and_then(|g| related(&g)),
+        inferred_from: provenance::input_graphs(store, &name, graph.graph_type).iter().filter_map(related).collect(),
+    }
+}
+
+/// A resource's description, leaving out the labels of the IRIs it links to, which are shown in
+/// place of those IRIs instead.
+pub fn resource_page(description: &Description) -> ResourcePage {
+    let labels: HashMap<NamedNode, String> = description.triples.iter()
+        .filter(|t| t.predicate.as_ref() == rdfs::LABEL)
+        .filter_map(|t| match (&t.subject, &t.object) {
+            (NamedOrBlankNode::NamedNode(s), Term::Literal(l)) => Some((s.clone(), l.value().to_owned())),
+            _ => None,
+        })
+        .collect();
+    let resource = &description.resource;
+    let rows = description.triples.iter()
+        .filter(|t| !is_linked_label(t, resource))
+        .map(|t| Row {
+            subject: match &t.subject {
+                NamedOrBlankNode::NamedNode(n) if n == resource => Cell { text: String::new(), href: None, iri: None },
+                subject => cell(&subject.clone().into(), &labels),
+            },
+            predicate: cell(&t.predicate.clone().into(), &labels),
+            object: cell(&t.object, &labels),
+        })
+        .collect();
+    ResourcePage {
+        iri: resource.as_str().to_owned(),
+        title: labels.get(resource).cloned().unwrap_or_else(|| resource.as_str().to_owned()),
+        rows,
+        graphs: description.graphs.iter().map(|g| g.as_str().to_owned()).collect(),
+    }
+}
+
+/// Whether `triple` is only there to label an IRI the resource links to.
+fn is_linked_label(triple: &Triple, resource: &NamedNode) -> bool {
+    triple.predicate.as_ref() == rdfs::LABEL && match &triple.subject {
+        NamedOrBlankNode::NamedNode(n) => n != resource,
+        NamedOrBlankNode::BlankNode(_) => false,
+    }
+}
+
+/// Solutions in SPARQL JSON results as a table, one column per variable. A boolean answer is a
+/// single `result` column.
+pub fn results_table(store: &Store, results: &str, readable: &[GraphData]) -> Option<ResultsTable> {
+    let results: Value = serde_json::from_str(results).ok()?;
+    if let Some(answer) = results.get("boolean").and_then(Value::as_bool) {
+        let text = answer.to_string();
+        return Some(ResultsTable { variables: vec!["result".to_owned()], rows: vec![vec![Cell { text, href: None, iri: None }]] });
+    }
+
+    let variables: Vec<String> = results.pointer("/head/vars")?.as_array()?.iter().filter_map(|v| v.as_str().map(str::to_owned)).collect();
+    let bindings = results.pointer("/results/bindings")?.as_array()?;
+    let iris: Vec<NamedNode> = bindings.iter()
+        .flat_map(|b| variables.iter().filter_map(move |v| b.get(v)))
+        .filter(|value| value.get("type").and_then(Value::as_str) == Some("uri"))
+        .filter_map(|value| value.get("value").and_then(Value::as_str).and_then(|v| NamedNode::new(v).ok()))
+        .collect();
+    let labels = labels_of(store, iris.into_iter(), readable);
+
+    let rows = bindings.iter()
+        .map(|binding| variables.iter()
+            .map(|v| match binding.get(v) {
+                Some(value) => {
+                    let text = value.get("value").and_then(Value::as_str).unwrap_or_default();
+                    match value.get("type").and_then(Value::as_str) {
+                        Some("uri") => match NamedNode::new(text) {
+                            Ok(iri) => cell(&iri.into(), &labels),
+                            Err(_) => Cell { text: text.to_owned(), href: None, iri: None },
+                        },
+                        Some("bnode") => Cell { text: format!("_:{}", text), href: None, iri: None },
+                        _ => Cell { text: text.to_owned(), href: None, iri: None },
+                    }
+                }
+                None => Cell { text: String::new(), href: None, iri: None },
+            })
+            .collect())
+        .collect();
+    Some(ResultsTable { variables, rows })
+}
+
+/// The `rdfs:label` of each of `iris` that has one in the `readable` graphs.
+pub fn labels_of(store: &Store, iris: impl Iterator<Item = NamedNode>, readable: &[GraphData]) -> HashMap<NamedNode, String> {
+    let readable: HashSet<String> = readable.iter().map(|g| g.id.clone()).collect();
+    let mut labels = HashMap::new();
+    for iri in iris {
+        if labels.contains_key(&iri) {
+            continue;
+        }
+        let label = store.quads_for_pattern(Some(iri.as_ref().into()), Some(rdfs::LABEL), None, None)
+            .filter_map(Result::ok)
+            .find_map(|q| match (q.graph_name, q.object) {
+                (GraphName::NamedNode(g), Term::Literal(l)) if readable.contains(&g.to_string()) => Some(l.value().to_owned()),
+                _ => None,
+            });
+        if let Some(label) = label {
+            labels.insert(iri, label);
+        }
+    }
+    labels
+}
+
+fn iris_of(triple: &Triple) -> Vec<NamedNode> {
+    let mut iris = vec![triple.predicate.clone()];
+    if let NamedOrBlankNode::NamedNode(s) = &triple.subject {
+        iris.push(s.clone());
+    }
+    if let Term::NamedNode(o) = &triple.object {
+        iris.push(o.clone());
+    }
+    iris
+}
+
+/// A term as shown on a page, by its label if `labels` has one.
+pub fn cell(term: &Term, labels: &HashMap<NamedNode, String>) -> Cell {
+    match term {
+        Term::NamedNode(n) => match labels.get(n) {
+            Some(label) => Cell { text: label.clone(), href: Some(resource_path(n)), iri: Some(n.as_str().to_owned()) },
+            None => Cell { text: n.as_str().to_owned(), href: Some(resource_path(n)), iri: None },
+        },
+        Term::BlankNode(b) => Cell { text: b.to_string(), href: None, iri: None },
+        Term::Literal(l) => Cell { text: l.value().to_owned(), href: None, iri: None },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use oxigraph::model::Literal;
+    use crate::api::storage::{accounted_graph_list, load_triples_into_new_graph};
+    use crate::api::storage::test::init_store;
+
+    #[test]
+    fn test_graph_page() {
+        let (s, _f) = init_store();
+        let ex = |local: &str| NamedNode::new_unchecked(format!("http://example.com/{}", local));
+        let people = ex("people");
+        let triples: Vec<Triple> = (0..PAGE_SIZE + 1).map(|n| Triple::new(ex(&format!("person{}", n)), ex("livesIn"), ex("paris"))).collect();
+        load_triples_into_new_graph(&s, people.clone(), GraphType::Model, triples);
+        load_triples_into_new_graph(&s, ex("places"), GraphType::Model, vec![Triple::new(ex("paris"), rdfs::LABEL, Literal::new_simple_literal("Paris"))]);
+
+        let graphs = accounted_graph_list(&s).graphs;
+        let graph = graphs.iter().find(|g| g.id == people.to_string()).unwrap();
+        let first = graph_page(&s, graph, &graphs, 1);
+        assert_eq!(2, first.pages);
+        assert_eq!(PAGE_SIZE, first.rows.len());
+        assert_eq!(None, first.previous);
+        assert_eq!("Paris", first.rows[0].object.text);
+        assert_eq!(Some("http://example.com/paris".to_owned()), first.rows[0].object.iri);
+
+        let second = graph_page(&s, graph, &graphs, 2);
+        assert_eq!(1, second.rows.len());
+        assert_eq!(None, second.next);
+        assert!(second.previous.unwrap().ends_with("?page=1"));
+        assert_eq!(2, graph_page(&s, graph, &graphs, usize::MAX).page);
+
+        let groups = grouped(&graphs);
+        assert!(groups.iter().any(|g| g.graph_type == GraphType::Model && g.graphs.len() == 2));
+    }
+
+    #[test]
+    fn test_results_table() {
+        let (s, _f) = init_store();
+        let results = r#"{"head":{"vars":["s","n"]},"results":{"bindings":[{"s":{"type":"uri","value":"http://example.com/a"},"n":{"type":"literal","value":"1"}},{"n":{"type":"literal","value":"2"}}]}}"#;
+        let table = results_table(&s, results, &[]).unwrap();
+        assert_eq!(vec!["s", "n"], table.variables);
+        assert_eq!(Some(resource_path(&NamedNode::new_unchecked("http://example.com/a"))), table.rows[0][0].href);
+        assert_eq!("", table.rows[1][0].text);
+        assert_eq!("2", table.rows[1][1].text);
+
+        assert_eq!(vec![vec![Cell { text: "true".to_owned(), href: None, iri: None }]], results_table(&s, r#"{"head":{},"boolean":true}"#, &[]).unwrap().rows);
+    }
+}
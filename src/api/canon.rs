@@ -0,0 +1,543 @@
+//! Canonicalization
+//!
+//! Blank node labels are local to a document: loading the same file twice gives two graphs
+//! that are equal in every way except for the labels of their blank nodes. To compare graphs
+//! we relabel blank nodes with the RDF Dataset Canonicalization algorithm (RDFC-1.0, formerly
+//! URDNA2015, https://www.w3.org/TR/rdf-canon/), which gives isomorphic graphs identical
+//! labels `_:c14n0`, `_:c14n1`, ...
+//!
+//! Graphs here are a single graph of `Triple`s, so every quad of the algorithm is in the default
+//! graph and the `g` position never comes up.
+//!
+//! Telling apart blank nodes that look alike tries every ordering of them, so graphs can be made
+//! to take practically forever, like a clique of blank nodes. As RDFC-1.0 asks, the work is
+//! limited: past `MAX_STEPS`, canonicalization fails.
+
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use oxigraph::model::{BlankNode, Literal, NamedOrBlankNode, Term, Triple};
+use oxigraph::model::vocab::xsd;
+
+use sha2::{Digest, Sha256};
+
+/// How many N-degree hashes and orderings of related blank nodes canonicalizing one graph may
+/// go through.
+pub const MAX_STEPS: usize = 100_000;
+
+/// Relabels the blank nodes of `triples` with their canonical labels. The result is sorted
+/// in canonical N-Triples order, so isomorphic graphs give equal vectors.
+pub fn canonicalize(triples: &[Triple]) -> Result<Vec<Triple>, String> {
+    let labels = canonical_labels(triples)?;
+    let mut canonical: Vec<Triple> = triples.iter().map(|t| relabel(t, &labels)).collect();
+
+    canonical.sort_by_cached_key(|t| ntriples_line(t, &|b: &BlankNode| format!("_:{}", b.as_str())));
+    canonical.dedup();
+    Ok(canonical)
+}
+
+/// `after` with its blank nodes renamed to those of `before` wherever a blank node component of
+/// `after` is isomorphic to one of `before`, and renamed apart from the blank nodes of `before`
+/// everywhere else. A component is some blank nodes linked to each other by triples, along with
+/// all the triples they are in. Taking the set difference of `before` and the result then leaves
+/// out the components that are the same in both, whatever their blank nodes are called.
+pub fn align_blank_nodes(before: &[Triple], after: &[Triple]) -> Result<Vec<Triple>, String> {
+    // The components of `before` by their canonical N-Triples, each with its canonical labels
+    // mapped back to its own blank nodes
+    let mut unmatched: HashMap<String, Vec<HashMap<BlankNode, BlankNode>>> = HashMap::new();
+    for component in components(before) {
+        let labels = canonical_labels(&component)?;
+        let key = canonical_lines(&component, &labels);
+        unmatched.entry(key).or_insert_with(Vec::new).push(labels.into_iter().map(|(node, label)| (label, node)).collect());
+    }
+
+    let taken: HashSet<&BlankNode> = before.iter().flat_map(blank_nodes).collect();
+    let mut renamed: HashMap<BlankNode, BlankNode> = HashMap::new();
+    for component in components(after) {
+        let labels = canonical_labels(&component)?;
+        match unmatched.get_mut(&canonical_lines(&component, &labels)).and_then(Vec::pop) {
+            Some(originals) => renamed.extend(labels.into_iter().map(|(node, label)| (node, originals[&label].clone()))),
+            None => renamed.extend(labels.into_iter().filter(|(node, _)| taken.contains(node)).map(|(node, _)| (node, BlankNode::default()))),
+        }
+    }
+
+    Ok(after.iter().map(|t| relabel(t, &renamed)).collect())
+}
+
+/// The triples with blank nodes in them, grouped into components by the blank nodes they share.
+fn components(triples: &[Triple]) -> Vec<Vec<Triple>> {
+    let mut parent: HashMap<BlankNode, BlankNode> = HashMap::new();
+    fn root(parent: &mut HashMap<BlankNode, BlankNode>, node: &BlankNode) -> BlankNode {
+        let mut root = node.clone();
+        while let Some(up) = parent.get(&root).filter(|up| **up != root) {
+            root = up.clone();
+        }
+        let mut node = node.clone();
+        while node != root {
+            let up = parent.insert(node, root.clone()).unwrap_or_else(|| root.clone());
+            node = up;
+        }
+        root
+    }
+
+    for triple in triples {
+        let mut nodes = blank_nodes(triple);
+        if let Some(first) = nodes.next() {
+            let first = root(&mut parent, first);
+            parent.entry(first.clone()).or_insert_with(|| first.clone());
+            for other in nodes {
+                let other = root(&mut parent, other);
+                parent.insert(other, first.clone());
+            }
+        }
+    }
+
+    let mut components: HashMap<BlankNode, Vec<Triple>> = HashMap::new();
+    for triple in triples {
+        if let Some(node) = blank_nodes(triple).next() {
+            let root = root(&mut parent, node);
+            components.entry(root).or_insert_with(Vec::new).push(triple.clone());
+        }
+    }
+    components.into_iter().map(|(_, component)| component).collect()
+}
+
+fn blank_nodes(triple: &Triple) -> impl Iterator<Item = &BlankNode> {
+    let subject = match &triple.subject {
+        NamedOrBlankNode::BlankNode(b) => Some(b),
+        _ => None,
+    };
+    let object = match &triple.object {
+        Term::BlankNode(b) => Some(b),
+        _ => None,
+    };
+    subject.into_iter().chain(object)
+}
+
+/// Renames the blank nodes of `triple` that `labels` has a new name for.
+fn relabel(triple: &Triple, labels: &HashMap<BlankNode, BlankNode>) -> Triple {
+    let relabel = |node: &BlankNode| labels.get(node).cloned().unwrap_or_else(|| node.clone());
+    let subject = match &triple.subject {
+        NamedOrBlankNode::BlankNode(b) => NamedOrBlankNode::from(relabel(b)),
+        s => s.clone(),
+    };
+    let object = match &triple.object {
+        Term::BlankNode(b) => Term::from(relabel(b)),
+        o => o.clone(),
+    };
+    Triple::new(subject, triple.predicate.clone(), object)
+}
+
+/// The canonical N-Triples of `triples`, given their canonical `labels`.
+fn canonical_lines(triples: &[Triple], labels: &HashMap<BlankNode, BlankNode>) -> String {
+    let mut lines: Vec<String> = triples.iter()
+        .map(|t| ntriples_line(&relabel(t, labels), &|b: &BlankNode| format!("_:{}", b.as_str())))
+        .collect();
+    lines.sort();
+    lines.dedup();
+    lines.concat()
+}
+
+/// Canonical N-Triples: one line per triple of the canonicalized graph, sorted.
+pub fn canonical_ntriples(triples: &[Triple]) -> Result<String, String> {
+    Ok(canonicalize(triples)?
+        .iter()
+        .map(|t| ntriples_line(t, &|b: &BlankNode| format!("_:{}", b.as_str())))
+        .collect())
+}
+
+/// Maps every blank node in `triples` to its canonical `c14nN` blank node.
+pub fn canonical_labels(triples: &[Triple]) -> Result<HashMap<BlankNode, BlankNode>, String> {
+    let canonicalizer = Canonicalizer::new(triples);
+    let issuer = canonicalizer.issue_canonical_identifiers()?;
+
+    Ok(issuer
+        .issued
+        .into_iter()
+        .map(|(node, id)| (node, BlankNode::new_unchecked(id)))
+        .collect())
+}
+
+/// Hex encoded SHA-256, the hash algorithm RDFC-1.0 uses throughout.
+pub fn sha256_hex(input: &str) -> String {
+    hex::encode(Sha256::digest(input.as_bytes()))
+}
+
+/// Serializes a triple as a canonical N-Triples line, with blank nodes written by `label`.
+pub fn ntriples_line(triple: &Triple, label: &dyn Fn(&BlankNode) -> String) -> String {
+    let subject = match &triple.subject {
+        NamedOrBlankNode::NamedNode(n) => format!("<{}>", n.as_str()),
+        NamedOrBlankNode::BlankNode(b) => label(b),
+    };
+    let object = match &triple.object {
+        Term::NamedNode(n) => format!("<{}>", n.as_str()),
+        Term::BlankNode(b) => label(b),
+        Term::Literal(l) => literal_string(l),
+    };
+    format!("{} <{}> {} .\n", subject, triple.predicate.as_str(), object)
+}
+
+/// A literal as N-Triples writes it.
+pub fn literal_string(literal: &Literal) -> String {
+    let mut out = String::with_capacity(literal.value().len() + 2);
+    out.push('"');
+    for c in literal.value().chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            c if c <= '\u{1f}' || c == '\u{7f}' => out.push_str(&format!("\\u{:04X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+
+    if let Some(language) = literal.language() {
+        out.push('@');
+        out.push_str(language);
+    } else if literal.datatype() != xsd::STRING {
+        out.push_str(&format!("^^<{}>", literal.datatype().as_str()));
+    }
+    out
+}
+
+/// Hands out identifiers `<prefix>0`, `<prefix>1`, ... remembering the order they were issued in.
+#[derive(Clone)]
+struct IdentifierIssuer {
+    prefix: &'static str,
+    issued: HashMap<BlankNode, String>,
+    order: Vec<BlankNode>,
+}
+
+impl IdentifierIssuer {
+    fn new(prefix: &'static str) -> IdentifierIssuer {
+        IdentifierIssuer {
+            prefix,
+            issued: HashMap::new(),
+            order: Vec::new(),
+        }
+    }
+
+    fn issue(&mut self, node: &BlankNode) -> String {
+        if let Some(id) = self.issued.get(node) {
+            return id.clone();
+        }
+        let id = format!("{}{}", self.prefix, self.order.len());
+        self.issued.insert(node.clone(), id.clone());
+        self.order.push(node.clone());
+        id
+    }
+
+    fn get(&self, node: &BlankNode) -> Option<&String> {
+        self.issued.get(node)
+    }
+}
+
+struct Canonicalizer<'a> {
+    mentions: HashMap<BlankNode, Vec<&'a Triple>>,
+    first_degree: HashMap<BlankNode, String>,
+    canonical: IdentifierIssuer,
+    steps: Cell<usize>,
+}
+
+impl<'a> Canonicalizer<'a> {
+    fn new(triples: &'a [Triple]) -> Canonicalizer<'a> {
+        let mut mentions: HashMap<BlankNode, Vec<&'a Triple>> = HashMap::new();
+        for triple in triples {
+            let subject = match &triple.subject {
+                NamedOrBlankNode::BlankNode(b) => Some(b),
+                _ => None,
+            };
+            if let Some(b) = subject {
+                mentions.entry(b.clone()).or_insert_with(Vec::new).push(triple);
+            }
+            if let Term::BlankNode(b) = &triple.object {
+                if subject != Some(b) {
+                    mentions.entry(b.clone()).or_insert_with(Vec::new).push(triple);
+                }
+            }
+        }
+
+        let first_degree = mentions
+            .iter()
+            .map(|(node, triples)| {
+                let mut lines: Vec<String> = triples
+                    .iter()
+                    .map(|t| ntriples_line(t, &|b: &BlankNode| if b == node { "_:a".to_owned() } else { "_:z".to_owned() }))
+                    .collect();
+                lines.sort();
+                (node.clone(), sha256_hex(&lines.concat()))
+            })
+            .collect();
+
+        Canonicalizer {
+            mentions,
+            first_degree,
+            canonical: IdentifierIssuer::new("c14n"),
+            steps: Cell::new(0),
+        }
+    }
+
+    /// Counts `steps` more work, failing once there has been too much.
+    fn spend(&self, steps: usize) -> Result<(), String> {
+        let spent = self.steps.get().saturating_add(steps);
+        self.steps.set(spent);
+        if spent > MAX_STEPS {
+            Err(format!("The graph has too many blank nodes that look alike to canonicalize them in {} steps", MAX_STEPS))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The main loop of the algorithm: blank nodes with a unique first degree hash get their
+    /// identifiers first, in hash order. The rest are told apart by their N-degree hashes.
+    fn issue_canonical_identifiers(mut self) -> Result<IdentifierIssuer, String> {
+        let mut by_hash: BTreeMap<String, Vec<BlankNode>> = BTreeMap::new();
+        for (node, hash) in self.first_degree.iter() {
+            by_hash.entry(hash.clone()).or_insert_with(Vec::new).push(node.clone());
+        }
+
+        for nodes in by_hash.values().filter(|nodes| nodes.len() == 1) {
+            self.canonical.issue(&nodes[0]);
+        }
+
+        for nodes in by_hash.values().filter(|nodes| nodes.len() > 1) {
+            let mut results: Vec<(String, IdentifierIssuer)> = nodes
+                .iter()
+                .filter(|node| self.canonical.get(node).is_none())
+                .map(|node| {
+                    let mut temporary = IdentifierIssuer::new("b");
+                    temporary.issue(node);
+                    self.hash_n_degree(node, temporary)
+                })
+                .collect::<Result<_, _>>()?;
+            results.sort_by(|a, b| a.0.cmp(&b.0));
+
+            for (_, issuer) in results {
+                for node in issuer.order.iter() {
+                    self.canonical.issue(node);
+                }
+            }
+        }
+
+        Ok(self.canonical)
+    }
+
+    fn hash_related(&self, related: &BlankNode, triple: &Triple, issuer: &IdentifierIssuer, position: char) -> String {
+        let identifier = match self.canonical.get(related).or_else(|| issuer.get(related)) {
+            Some(id) => format!("_:{}", id),
+            None => self.first_degree[related].clone(),
+        };
+
+        sha256_hex(&format!("{}<{}>{}", position, triple.predicate.as_str(), identifier))
+    }
+
+    fn hash_n_degree(&self, node: &BlankNode, mut issuer: IdentifierIssuer) -> Result<(String, IdentifierIssuer), String> {
+        self.spend(1)?;
+        let mut related_by_hash: BTreeMap<String, Vec<BlankNode>> = BTreeMap::new();
+        for triple in self.mentions[node].iter() {
+            if let NamedOrBlankNode::BlankNode(b) = &triple.subject {
+                if b != node {
+                    let hash = self.hash_related(b, triple, &issuer, 's');
+                    related_by_hash.entry(hash).or_insert_with(Vec::new).push(b.clone());
+                }
+            }
+            if let Term::BlankNode(b) = &triple.object {
+                if b != node {
+                    let hash = self.hash_related(b, triple, &issuer, 'o');
+                    related_by_hash.entry(hash).or_insert_with(Vec::new).push(b.clone());
+                }
+            }
+        }
+
+        let mut data_to_hash = String::new();
+        for (hash, related) in related_by_hash {
+            data_to_hash.push_str(&hash);
+            let mut chosen_path = String::new();
+            let mut chosen_issuer = None;
+
+            // Checked before the orderings are made, as there are n! of them
+            self.spend((1..=related.len()).try_fold(1usize, |n, k| n.checked_mul(k)).unwrap_or(usize::MAX))?;
+            'permutation: for permutation in permutations(&related) {
+                let mut issuer_copy = issuer.clone();
+                let mut path = String::new();
+                let mut recursion = Vec::new();
+
+                for r in permutation.iter() {
+                    match self.canonical.get(r) {
+                        Some(id) => path.push_str(&format!("_:{}", id)),
+                        None => {
+                            if issuer_copy.get(r).is_none() {
+                                recursion.push(r.clone());
+                            }
+                            path.push_str(&format!("_:{}", issuer_copy.issue(r)));
+                        }
+                    }
+                    if !chosen_path.is_empty() && path.len() >= chosen_path.len() && path > chosen_path {
+                        continue 'permutation;
+                    }
+                }
+
+                for r in recursion {
+                    let (result_hash, result_issuer) = self.hash_n_degree(&r, issuer_copy.clone())?;
+                    path.push_str(&format!("_:{}", issuer_copy.issue(&r)));
+                    path.push_str(&format!("<{}>", result_hash));
+                    issuer_copy = result_issuer;
+                    if !chosen_path.is_empty() && path.len() >= chosen_path.len() && path > chosen_path {
+                        continue 'permutation;
+                    }
+                }
+
+                if chosen_path.is_empty() || path < chosen_path {
+                    chosen_path = path;
+                    chosen_issuer = Some(issuer_copy);
+                }
+            }
+
+            data_to_hash.push_str(&chosen_path);
+            if let Some(chosen) = chosen_issuer {
+                issuer = chosen;
+            }
+        }
+
+        Ok((sha256_hex(&data_to_hash), issuer))
+    }
+}
+
+/// Every ordering of `items`, generated with Heap's algorithm.
+fn permutations(items: &[BlankNode]) -> Vec<Vec<BlankNode>> {
+    let mut current = items.to_vec();
+    let mut counters = vec![0; current.len()];
+    let mut all = vec![current.clone()];
+
+    let mut i = 0;
+    while i < current.len() {
+        if counters[i] < i {
+            if i % 2 == 0 {
+                current.swap(0, i);
+            } else {
+                current.swap(counters[i], i);
+            }
+            all.push(current.clone());
+            counters[i] += 1;
+            i = 0;
+        } else {
+            counters[i] = 0;
+            i += 1;
+        }
+    }
+    all
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use oxigraph::model::NamedNode;
+
+    fn triple(s: NamedOrBlankNode, p: &str, o: Term) -> Triple {
+        Triple::new(s, NamedNode::new_unchecked(p), o)
+    }
+
+    fn blank(id: &str) -> BlankNode {
+        BlankNode::new_unchecked(id)
+    }
+
+    // A blank node cycle `_:x -> _:y -> _:x`, plus a named node pointing into it.
+    fn cycle(x: &str, y: &str) -> Vec<Triple> {
+        vec![
+            triple(blank(x).into(), "http://example.com/next", blank(y).into()),
+            triple(blank(y).into(), "http://example.com/next", blank(x).into()),
+            triple(NamedNode::new_unchecked("http://example.com/A").into(), "http://example.com/points", blank(x).into()),
+        ]
+    }
+
+    #[test]
+    fn test_isomorphic_graphs_canonicalize_equal() {
+        // Pointing at the other node of the symmetric cycle is the same graph
+        let mut other = cycle("foo", "bar");
+        other[2] = triple(NamedNode::new_unchecked("http://example.com/A").into(), "http://example.com/points", blank("bar").into());
+
+        assert_eq!(canonicalize(&cycle("a", "b")).unwrap(), canonicalize(&cycle("foo", "bar")).unwrap());
+        assert_eq!(canonicalize(&cycle("a", "b")).unwrap(), canonicalize(&other).unwrap());
+        assert_eq!(canonical_ntriples(&cycle("a", "b")).unwrap(), canonical_ntriples(&cycle("foo", "bar")).unwrap());
+    }
+
+    #[test]
+    fn test_different_graphs_canonicalize_differently() {
+        let mut extra = cycle("a", "b");
+        extra.push(triple(blank("b").into(), "http://example.com/label", Literal::new_simple_literal("B").into()));
+
+        assert_ne!(canonicalize(&cycle("a", "b")).unwrap(), canonicalize(&extra).unwrap());
+    }
+
+    #[test]
+    fn test_canonical_labels_are_c14n() {
+        let labels = canonical_labels(&cycle("a", "b")).unwrap();
+        let mut ids: Vec<_> = labels.values().map(|b| b.as_str().to_owned()).collect();
+        ids.sort();
+        assert_eq!(vec!["c14n0", "c14n1"], ids);
+    }
+
+    #[test]
+    fn test_align_blank_nodes() {
+        let named = || triple(NamedNode::new_unchecked("http://example.com/B").into(), "http://example.com/is", Literal::new_simple_literal("B").into());
+        let mut before = cycle("a", "b");
+        before.push(triple(blank("c").into(), "http://example.com/label", Literal::new_simple_literal("C").into()));
+        // The same cycle and named triple under other labels, and a blank node of its own that
+        // happens to be called like one in `before`
+        let mut after = cycle("x", "y");
+        after.push(named());
+        after.push(triple(blank("a").into(), "http://example.com/label", Literal::new_simple_literal("D").into()));
+
+        let aligned = align_blank_nodes(&before, &after).unwrap();
+        let before: HashSet<Triple> = before.into_iter().collect();
+        let added: Vec<&Triple> = aligned.iter().filter(|t| !before.contains(t)).collect();
+
+        assert_eq!(2, added.len());
+        assert!(added.contains(&&named()));
+        let relabelled = added.iter().find(|t| **t != &named()).unwrap();
+        assert!(matches!(&relabelled.subject, NamedOrBlankNode::BlankNode(b) if b.as_str() != "a" && b.as_str() != "c"));
+    }
+
+    #[test]
+    fn test_components() {
+        let mut triples = cycle("a", "b");
+        triples.push(triple(blank("c").into(), "http://example.com/label", Literal::new_simple_literal("C").into()));
+        triples.push(triple(NamedNode::new_unchecked("http://example.com/B").into(), "http://example.com/is", Literal::new_simple_literal("B").into()));
+
+        let mut sizes: Vec<usize> = components(&triples).iter().map(Vec::len).collect();
+        sizes.sort();
+        assert_eq!(vec![1, 3], sizes);
+    }
+
+    #[test]
+    fn test_poison_graph_fails() {
+        // Every blank node of a clique looks like every other
+        let nodes: Vec<BlankNode> = (0..12).map(|i| blank(&format!("n{}", i))).collect();
+        let clique: Vec<Triple> = nodes.iter()
+            .flat_map(|a| nodes.iter().filter(move |b| *b != a).map(move |b| triple(a.clone().into(), "http://example.com/knows", b.clone().into())))
+            .collect();
+
+        assert!(canonicalize(&clique).is_err());
+        assert!(canonicalize(&clique[..2]).is_ok());
+    }
+
+    #[test]
+    fn test_literal_escaping() {
+        let t = triple(
+            NamedNode::new_unchecked("http://example.com/A").into(),
+            "http://example.com/says",
+            Literal::new_simple_literal("a \"quote\"\n").into());
+        assert_eq!("<http://example.com/A> <http://example.com/says> \"a \\\"quote\\\"\\n\" .\n", ntriples_line(&t, &|_| String::new()));
+    }
+
+    #[test]
+    fn test_permutations() {
+        assert_eq!(6, permutations(&[blank("a"), blank("b"), blank("c")]).len());
+        assert_eq!(1, permutations(&[]).len());
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+
+use oxigraph::model::{BlankNode, NamedNode, Triple};
+
+use serde::Serialize;
+
+use strum::IntoEnumIterator;
+use strum_macros::{AsRefStr, EnumIter};
+
+use unicase::UniCase;
+
+use crate::api::canon;
+use crate::api::history;
+use crate::api::storage::triples_as_ttl_string;
+use crate::meta;
+
+/// The ways a diff can be written out.
+///
+/// * `Turtle`: a blank node with `meta:added` and `meta:removed` `rdf:Statement`s, the same shape
+///   as a changeset in `<History>`.
+/// * `Patch`: an RDF Patch transaction of `D` and `A` rows.
+/// * `Json`: the added and removed triples as lists of N-Triples lines.
+#[derive(Clone, Copy, Debug, PartialEq, EnumIter, AsRefStr)]
+pub enum DiffFormat {
+    Turtle,
+    Patch,
+    Json,
+}
+
+impl<'a> TryFrom<&'a str> for DiffFormat {
+    type Error = &'a str;
+
+    fn try_from(val: &'a str) -> Result<DiffFormat, Self::Error> {
+        let c = UniCase::new(val);
+        match DiffFormat::iter().find(|f| UniCase::new(f.as_ref()) == c) {
+            Some(f) => Ok(f),
+            None => Err(val),
+        }
+    }
+}
+
+/// The triples to add to `left` and remove from it to get `right`.
+///
+/// Blank nodes are matched up through their canonical N-Triples (see `canon::align_blank_nodes`),
+/// so a component of triples linked by shared blank nodes is unchanged when the other side has
+/// one isomorphic to it, whatever the labels of their blank nodes. Removed triples keep the
+/// labels of `left` and added ones those of `right`, relabelled where they clash with `left`, so
+/// the diff can be applied to `left` as it is.
+#[derive(Debug, PartialEq)]
+pub struct GraphDiff {
+    pub added: Vec<Triple>,
+    pub removed: Vec<Triple>,
+}
+
+#[derive(Serialize)]
+pub struct DiffJson {
+    pub context: String,
+    pub left: String,
+    pub right: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// Fails when the blank nodes of either side are too tangled to canonicalize.
+pub fn diff(left: &[Triple], right: &[Triple]) -> Result<GraphDiff, String> {
+    let right = canon::align_blank_nodes(left, right)?;
+
+    let left_set: HashSet<&Triple> = left.iter().collect();
+    let right_set: HashSet<&Triple> = right.iter().collect();
+    let added: Vec<Triple> = right.iter().filter(|t| !left_set.contains(t)).cloned().collect();
+    let removed: Vec<Triple> = left.iter().filter(|t| !right_set.contains(t)).cloned().collect();
+    Ok(GraphDiff { added, removed })
+}
+
+impl GraphDiff {
+    pub fn to_turtle(&self) -> Result<String, String> {
+        let changeset = BlankNode::default();
+        let added = NamedNode::from(meta::added);
+        let removed = NamedNode::from(meta::removed);
+
+        let triples = self.added.iter()
+            .flat_map(|t| history::changeset_triples(&changeset, added.clone(), t))
+            .chain(self.removed.iter().flat_map(|t| history::changeset_triples(&changeset, removed.clone(), t)));
+
+        triples_as_ttl_string(triples)
+    }
+
+    /// Writes the diff as a single RDF Patch transaction (https://afs.github.io/rdf-patch/).
+    pub fn to_patch(&self) -> String {
+        let mut patch = String::from("TX .\n");
+        for triple in self.removed.iter() {
+            patch.push_str("D ");
+            patch.push_str(&ntriples_line(triple));
+        }
+        for triple in self.added.iter() {
+            patch.push_str("A ");
+            patch.push_str(&ntriples_line(triple));
+        }
+        patch.push_str("TC .\n");
+        patch
+    }
+
+    pub fn to_json(&self, context: String, left: String, right: String) -> DiffJson {
+        let lines = |triples: &[Triple]| -> Vec<String> { triples.iter().map(|t| ntriples_line(t).trim_end().to_owned()).collect() };
+
+        DiffJson {
+            context,
+            left,
+            right,
+            added: lines(&self.added),
+            removed: lines(&self.removed),
+        }
+    }
+}
+
+fn ntriples_line(triple: &Triple) -> String {
+    canon::ntriples_line(triple, &|b: &BlankNode| format!("_:{}", b.as_str()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use oxigraph::model::{Literal, NamedOrBlankNode};
+
+    fn labelled(node: BlankNode, label: &str) -> Triple {
+        Triple::new(node, NamedNode::new_unchecked("http://www.w3.org/2000/01/rdf-schema#label"), Literal::new_simple_literal(label))
+    }
+
+    #[test]
+    fn test_diff_ignores_blank_node_labels() {
+        let left = vec![labelled(BlankNode::new_unchecked("x"), "A")];
+        let right = vec![labelled(BlankNode::new_unchecked("y"), "A")];
+
+        let d = diff(&left, &right).unwrap();
+        assert!(d.added.is_empty());
+        assert!(d.removed.is_empty());
+    }
+
+    #[test]
+    fn test_diff_added_and_removed() {
+        let left = vec![labelled(BlankNode::new_unchecked("x"), "A")];
+        let right = vec![labelled(BlankNode::new_unchecked("y"), "B")];
+
+        let d = diff(&left, &right).unwrap();
+        assert_eq!(vec![labelled(BlankNode::new_unchecked("y"), "B")], d.added);
+        assert_eq!(vec![labelled(BlankNode::new_unchecked("x"), "A")], d.removed);
+
+        assert_eq!(
+            "TX .\nD _:x <http://www.w3.org/2000/01/rdf-schema#label> \"A\" .\nA _:y <http://www.w3.org/2000/01/rdf-schema#label> \"B\" .\nTC .\n",
+            d.to_patch());
+
+        let clash = diff(&left, &vec![labelled(BlankNode::new_unchecked("x"), "B")]).unwrap();
+        assert_eq!(clash.removed, d.removed);
+        assert_ne!(NamedOrBlankNode::from(BlankNode::new_unchecked("x")), clash.added[0].subject);
+    }
+
+    #[test]
+    fn test_diff_matches_blank_node_components() {
+        let knows = NamedNode::new_unchecked("http://xmlns.com/foaf/0.1/knows");
+        let alice = NamedNode::new_unchecked("http://example.com/alice");
+        let people = |a: &str, b: &str, name: &str| {
+            let (a, b) = (BlankNode::new_unchecked(a), BlankNode::new_unchecked(b));
+            vec![
+                Triple::new(alice.clone(), knows.clone(), a.clone()),
+                Triple::new(a.clone(), knows.clone(), b.clone()),
+                Triple::new(b.clone(), knows.clone(), a.clone()),
+                labelled(a, "A"),
+                labelled(b, name),
+            ]
+        };
+
+        let mut left = people("a", "b", "B");
+        left.push(labelled(BlankNode::new_unchecked("c"), "C"));
+        let mut right = people("d", "e", "B");
+        right.push(Triple::new(alice.clone(), knows.clone(), NamedNode::new_unchecked("http://example.com/bob")));
+        right.push(labelled(BlankNode::new_unchecked("f"), "C"));
+
+        let d = diff(&left, &right).unwrap();
+        assert_eq!(vec![Triple::new(alice.clone(), knows.clone(), NamedNode::new_unchecked("http://example.com/bob"))], d.added);
+        assert!(d.removed.is_empty());
+
+        let changed = people("g", "h", "E");
+        let d = diff(&left, &changed).unwrap();
+        assert_eq!(5, d.added.len());
+        assert_eq!(6, d.removed.len());
+
+        // Applied to left, the diff gives a graph that differs from right only in its labels.
+        let mut patched: Vec<Triple> = left.iter().filter(|t| !d.removed.contains(t)).cloned().collect();
+        patched.extend(d.added.iter().cloned());
+        assert_eq!(GraphDiff { added: vec![], removed: vec![] }, diff(&patched, &changed).unwrap());
+    }
+
+    #[test]
+    fn test_diff_format_from_str() {
+        assert_eq!(DiffFormat::try_from("patch"), Ok(DiffFormat::Patch));
+        assert_eq!(DiffFormat::try_from("JSON"), Ok(DiffFormat::Json));
+        assert_eq!(DiffFormat::try_from("trig"), Err("trig"));
+    }
+}
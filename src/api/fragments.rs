@@ -0,0 +1,360 @@
+//! Triple pattern access to a graph, after [Linked Data Fragments](https://linkeddatafragments.org/):
+//! the triples matching a pattern of subject, predicate and object, any of which may be left
+//! open, a page at a time.
+//!
+//! Terms are written as in N-Triples, with IRIs also taken without angle brackets, as
+//! Triple Pattern Fragments clients send them: `http://example.com/s`, `_:b0`, `"chat"@fr`,
+//! `"3"^^http://www.w3.org/2001/XMLSchema#integer`. An empty term, or a variable like `?o`,
+//! matches anything.
+//!
+//! The same patterns are also served as [Triple Pattern Fragments](https://www.hydra-cg.com/spec/latest/triple-pattern-fragments/),
+//! whose pages carry Hydra controls for the next page and for making other fragments, and an
+//! estimate of how many triples match so clients can plan their joins. The estimate is the cached
+//! VoID statistics of the graph where they answer it, and otherwise a count of the index range
+//! the pattern falls in. Statistics aren't worked out for the estimate, and the count stops at
+//! `MAX_COUNTED`, when the page says the estimate is only a lower bound.
+
+use oxigraph::SledStore as Store;
+use oxigraph::model::{BlankNode, GraphNameRef, Literal, NamedNode, NamedOrBlankNode, Quad, Term, Triple};
+use oxigraph::model::vocab::{rdf, xsd};
+
+use crate::meta;
+use crate::api::alias::percent_encode;
+use crate::api::vocab::{hydra, void};
+use crate::api::void as void_stats;
+
+/// Triples on a page unless another `limit` is asked for.
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+/// Most triples on a page.
+pub const MAX_PAGE_SIZE: usize = 10_000;
+/// Most triples counted for an estimate that the cached statistics don't give.
+pub const MAX_COUNTED: usize = 10_000;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct TriplePattern {
+    pub subject: Option<NamedOrBlankNode>,
+    pub predicate: Option<NamedNode>,
+    pub object: Option<Term>,
+}
+
+impl TriplePattern {
+    /// The pattern of the given terms, as written in a request.
+    pub fn parse(subject: Option<&str>, predicate: Option<&str>, object: Option<&str>) -> Result<TriplePattern, String> {
+        let subject = match parse_term(subject)? {
+            Some(Term::NamedNode(n)) => Some(n.into()),
+            Some(Term::BlankNode(b)) => Some(b.into()),
+            Some(Term::Literal(l)) => return Err(format!("A literal can't be a subject: {}", l)),
+            None => None,
+        };
+        let predicate = match parse_term(predicate)? {
+            Some(Term::NamedNode(n)) => Some(n),
+            Some(other) => return Err(format!("Only an IRI can be a predicate: {}", other)),
+            None => None,
+        };
+        Ok(TriplePattern { subject, predicate, object: parse_term(object)? })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Fragment {
+    pub triples: Vec<Triple>,
+    /// How many triples match the pattern, on all pages.
+    pub total: usize,
+}
+
+/// The `limit` triples of `graph` matching `pattern` after the first `offset`.
+pub fn fragment(store: &Store, graph: &NamedNode, pattern: &TriplePattern, offset: usize, limit: usize) -> Fragment {
+    Fragment {
+        triples: matching(store, graph, pattern, offset, limit),
+        total: count(store, graph, pattern),
+    }
+}
+
+fn matching(store: &Store, graph: &NamedNode, pattern: &TriplePattern, offset: usize, limit: usize) -> Vec<Triple> {
+    quads_matching(store, graph, pattern).filter_map(Result::ok).skip(offset).take(limit).map(Triple::from).collect()
+}
+
+fn count(store: &Store, graph: &NamedNode, pattern: &TriplePattern) -> usize {
+    quads_matching(store, graph, pattern).count()
+}
+
+fn quads_matching<'a>(store: &'a Store, graph: &'a NamedNode, pattern: &'a TriplePattern) -> impl Iterator<Item = Result<Quad, std::io::Error>> + 'a {
+    store.quads_for_pattern(
+        pattern.subject.as_ref().map(|s| s.as_ref()),
+        pattern.predicate.as_ref().map(|p| p.as_ref()),
+        pattern.object.as_ref().map(|o| o.as_ref()),
+        Some(GraphNameRef::NamedNode(graph.as_ref())),
+    )
+}
+
+/// About how many triples of some graph match a pattern.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Estimate {
+    pub count: usize,
+    /// Whether counting stopped at `MAX_COUNTED`, so that there may be more.
+    pub lower_bound: bool,
+}
+
+/// About how many triples of `graph` match `pattern`: from the graph's cached VoID statistics
+/// for an open pattern, a predicate alone, or the instances of a class, and otherwise counted up
+/// to `MAX_COUNTED`.
+pub fn estimate(store: &Store, graph: &NamedNode, pattern: &TriplePattern) -> Estimate {
+    let stats = void_stats::cached_stats(store, graph);
+    let known = stats.and_then(|stats| match (&pattern.subject, &pattern.predicate, &pattern.object) {
+        (None, None, None) => Some(stats.triples),
+        (None, Some(p), None) => Some(stats.properties.get(p.as_str()).copied().unwrap_or(0)),
+        (None, Some(p), Some(Term::NamedNode(class))) if p.as_ref() == rdf::TYPE => {
+            Some(stats.classes.get(class.as_str()).copied().unwrap_or(0))
+        }
+        _ => None,
+    });
+    match known {
+        Some(count) => Estimate { count, lower_bound: false },
+        None => {
+            let counted = quads_matching(store, graph, pattern).take(MAX_COUNTED + 1).count();
+            Estimate { count: counted.min(MAX_COUNTED), lower_bound: counted > MAX_COUNTED }
+        }
+    }
+}
+
+/// A page of a Triple Pattern Fragment of one graph.
+#[derive(Debug, PartialEq)]
+pub struct FragmentPage {
+    /// The matching triples, followed by the page's metadata and controls.
+    pub triples: Vec<Triple>,
+    pub estimate: Estimate,
+    pub page: usize,
+}
+
+/// Page `page`, counting from 1, of the fragment of `graph` matching `pattern`, with its
+/// metadata and hypermedia controls. `fragments` is the absolute URL of the graph's fragments.
+///
+/// A page too far in for its first triple to be counted in a `usize` is empty.
+pub fn fragment_page(store: &Store, graph: &NamedNode, pattern: &TriplePattern, page: usize, fragments: &str) -> FragmentPage {
+    let page = page.max(1);
+    let offset = (page - 1).checked_mul(DEFAULT_PAGE_SIZE);
+    let mut triples = offset.map_or_else(Vec::new, |offset| matching(store, graph, pattern, offset, DEFAULT_PAGE_SIZE));
+    let mut estimate = estimate(store, graph, pattern);
+    if let Some(offset) = offset {
+        estimate.count = estimate.count.max(offset + triples.len());
+    }
+    let has_next = triples.len() == DEFAULT_PAGE_SIZE
+        && page.checked_mul(DEFAULT_PAGE_SIZE).map_or(false, |seen| estimate.lower_bound || seen < estimate.count);
+    triples.extend(controls(fragments, pattern, page, estimate, has_next));
+    FragmentPage { triples, estimate, page }
+}
+
+/// The URL of a page of the fragment matching `pattern`, with the terms in explicit
+/// representation. The first page has no `page` parameter.
+pub fn page_url(fragments: &str, pattern: &TriplePattern, page: usize) -> String {
+    let mut params = Vec::new();
+    if let Some(subject) = &pattern.subject {
+        params.push(format!("subject={}", percent_encode(&explicit(&subject.clone().into()))));
+    }
+    if let Some(predicate) = &pattern.predicate {
+        params.push(format!("predicate={}", percent_encode(&explicit(&predicate.clone().into()))));
+    }
+    if let Some(object) = &pattern.object {
+        params.push(format!("object={}", percent_encode(&explicit(object))));
+    }
+    if page > 1 {
+        params.push(format!("page={}", page));
+    }
+    if params.is_empty() { fragments.to_owned() } else { format!("{}?{}", fragments, params.join("&")) }
+}
+
+/// A term in Hydra's explicit representation: IRIs bare, literals as in N-Triples but with a bare
+/// datatype IRI.
+fn explicit(term: &Term) -> String {
+    match term {
+        Term::NamedNode(n) => n.as_str().to_owned(),
+        Term::BlankNode(b) => b.to_string(),
+        Term::Literal(l) => {
+            let value = l.value().replace('\\', "\\\\").replace('"', "\\\"");
+            match l.language() {
+                Some(language) => format!("\"{}\"@{}", value, language),
+                None if l.datatype() == xsd::STRING => format!("\"{}\"", value),
+                None => format!("\"{}\"^^{}", value, l.datatype().as_str()),
+            }
+        }
+    }
+}
+
+fn controls(fragments: &str, pattern: &TriplePattern, page: usize, estimate: Estimate, has_next: bool) -> Vec<Triple> {
+    let dataset = NamedNode::new_unchecked(format!("{}#dataset", fragments));
+    let this = NamedNode::new_unchecked(page_url(fragments, pattern, page));
+    let search = BlankNode::default();
+    let count = |n: usize| Literal::new_typed_literal(n.to_string(), xsd::INTEGER);
+
+    let mut triples = vec![
+        Triple::new(dataset.clone(), rdf::TYPE, void::DATASET),
+        Triple::new(dataset.clone(), rdf::TYPE, hydra::COLLECTION),
+        Triple::new(dataset.clone(), void::SUBSET, this.clone()),
+        Triple::new(dataset, hydra::SEARCH, search.clone()),
+        Triple::new(search.clone(), rdf::TYPE, hydra::IRI_TEMPLATE),
+        Triple::new(search.clone(), hydra::TEMPLATE, Literal::new_simple_literal(format!("{}{{?subject,predicate,object}}", fragments))),
+        Triple::new(search.clone(), hydra::VARIABLE_REPRESENTATION, hydra::EXPLICIT_REPRESENTATION),
+    ];
+    for (variable, property) in [("subject", rdf::SUBJECT), ("predicate", rdf::PREDICATE), ("object", rdf::OBJECT)].iter() {
+        let mapping = BlankNode::default();
+        triples.push(Triple::new(search.clone(), hydra::MAPPING, mapping.clone()));
+        triples.push(Triple::new(mapping.clone(), rdf::TYPE, hydra::IRI_TEMPLATE_MAPPING));
+        triples.push(Triple::new(mapping.clone(), hydra::VARIABLE, Literal::new_simple_literal(*variable)));
+        triples.push(Triple::new(mapping, hydra::PROPERTY, *property));
+    }
+
+    triples.push(Triple::new(this.clone(), rdf::TYPE, hydra::PARTIAL_COLLECTION_VIEW));
+    triples.push(Triple::new(this.clone(), void::TRIPLES, count(estimate.count)));
+    triples.push(Triple::new(this.clone(), hydra::TOTAL_ITEMS, count(estimate.count)));
+    if estimate.lower_bound {
+        triples.push(Triple::new(this.clone(), NamedNode::from(meta::countIsLowerBound), Literal::from(true)));
+    }
+    triples.push(Triple::new(this.clone(), hydra::ITEMS_PER_PAGE, count(DEFAULT_PAGE_SIZE)));
+    triples.push(Triple::new(this.clone(), hydra::FIRST, NamedNode::new_unchecked(page_url(fragments, pattern, 1))));
+    if page > 1 {
+        triples.push(Triple::new(this.clone(), hydra::PREVIOUS, NamedNode::new_unchecked(page_url(fragments, pattern, page - 1))));
+    }
+    if has_next {
+        triples.push(Triple::new(this, hydra::NEXT, NamedNode::new_unchecked(page_url(fragments, pattern, page + 1))));
+    }
+    triples
+}
+
+/// A term as written in a request, or `None` for one left open.
+pub fn parse_term(term: Option<&str>) -> Result<Option<Term>, String> {
+    let term = match term.map(str::trim) {
+        None => return Ok(None),
+        Some(t) if t.is_empty() || t.starts_with('?') => return Ok(None),
+        Some(t) => t,
+    };
+    let invalid = || format!("{} is not an IRI, blank node or literal", term);
+
+    if let Some(label) = term.strip_prefix("_:") {
+        return BlankNode::new(label).map(|b| Some(b.into())).map_err(|_| invalid());
+    }
+    if term.starts_with('"') {
+        let end = term.rfind('"').filter(|end| *end > 0).ok_or_else(invalid)?;
+        let value = unescape(&term[1..end]);
+        let suffix = &term[end + 1..];
+        let literal = if let Some(language) = suffix.strip_prefix('@') {
+            Literal::new_language_tagged_literal(value, language).map_err(|_| invalid())?
+        } else if let Some(datatype) = suffix.strip_prefix("^^") {
+            Literal::new_typed_literal(value, iri(datatype).ok_or_else(invalid)?)
+        } else if suffix.is_empty() {
+            Literal::new_simple_literal(value)
+        } else {
+            return Err(invalid());
+        };
+        return Ok(Some(literal.into()));
+    }
+    iri(term).map(|n| Some(n.into())).ok_or_else(invalid)
+}
+
+fn iri(term: &str) -> Option<NamedNode> {
+    let unbracketed = term.strip_prefix('<').and_then(|t| t.strip_suffix('>')).unwrap_or(term);
+    NamedNode::new(unbracketed).ok()
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::GraphType;
+    use crate::api::storage::load_triples_into_new_graph;
+    use crate::api::storage::test::init_store;
+
+    #[test]
+    fn test_parse_term() {
+        let thing = Term::from(NamedNode::new_unchecked("http://example.com/thing"));
+        assert_eq!(Ok(Some(thing.clone())), parse_term(Some("http://example.com/thing")));
+        assert_eq!(Ok(Some(thing)), parse_term(Some("<http://example.com/thing>")));
+        assert_eq!(Ok(None), parse_term(Some("?o")));
+        assert_eq!(Ok(Some(Literal::new_language_tagged_literal_unchecked("say \"chat\"", "fr").into())), parse_term(Some(r#""say \"chat\""@fr"#)));
+        assert_eq!(Ok(Some(Literal::new_typed_literal("3", xsd::INTEGER).into())), parse_term(Some("\"3\"^^http://www.w3.org/2001/XMLSchema#integer")));
+        assert!(parse_term(Some("not an iri")).is_err());
+        assert!(TriplePattern::parse(Some("\"literal\""), None, None).is_err());
+    }
+
+    #[test]
+    fn test_fragment() {
+        let (s, _f) = init_store();
+        let ex = |local: &str| NamedNode::new_unchecked(format!("http://example.com/{}", local));
+        let graph = ex("people");
+        load_triples_into_new_graph(&s, graph.clone(), GraphType::Model, vec![
+            Triple::new(ex("alice"), rdf::TYPE, ex("Person")),
+            Triple::new(ex("bob"), rdf::TYPE, ex("Person")),
+            Triple::new(ex("alice"), ex("knows"), ex("bob")),
+        ]);
+
+        let people = TriplePattern::parse(None, Some("http://www.w3.org/1999/02/22-rdf-syntax-ns#type"), Some("http://example.com/Person")).unwrap();
+        let first = fragment(&s, &graph, &people, 0, 1);
+        assert_eq!(2, first.total);
+        assert_eq!(1, first.triples.len());
+        assert_eq!(1, fragment(&s, &graph, &people, 1, 1).triples.len());
+        assert_eq!(3, fragment(&s, &graph, &TriplePattern::default(), 0, DEFAULT_PAGE_SIZE).total);
+    }
+
+    #[test]
+    fn test_estimate() {
+        let (s, _f) = init_store();
+        let ex = |local: &str| NamedNode::new_unchecked(format!("http://example.com/{}", local));
+        let graph = ex("people");
+        let triples: Vec<Triple> = (0..MAX_COUNTED + 5).map(|n| Triple::new(ex(&format!("person{}", n)), rdf::TYPE, ex("Person"))).collect();
+        load_triples_into_new_graph(&s, graph.clone(), GraphType::Model, triples);
+        let stats = NamedNode::from(meta::stats);
+
+        // Without cached statistics, counting stops, and the statistics aren't worked out
+        let counted = estimate(&s, &graph, &TriplePattern::default());
+        assert_eq!(Estimate { count: MAX_COUNTED, lower_bound: true }, counted);
+        assert!(s.quads_for_pattern(Some(graph.as_ref().into()), Some(stats.as_ref()), None, None).next().is_none());
+
+        void_stats::stats(&s, &graph);
+        assert_eq!(Estimate { count: MAX_COUNTED + 5, lower_bound: false }, estimate(&s, &graph, &TriplePattern::default()));
+    }
+
+    #[test]
+    fn test_fragment_page() {
+        let (s, _f) = init_store();
+        let ex = |local: &str| NamedNode::new_unchecked(format!("http://example.com/{}", local));
+        let graph = ex("people");
+        let triples: Vec<Triple> = (0..DEFAULT_PAGE_SIZE + 1).map(|n| Triple::new(ex(&format!("person{}", n)), rdf::TYPE, ex("Person"))).collect();
+        load_triples_into_new_graph(&s, graph.clone(), GraphType::Model, triples);
+
+        let fragments = "http://localhost:8000/fragments/people";
+        let people = TriplePattern::parse(None, Some(rdf::TYPE.as_str()), Some("http://example.com/Person")).unwrap();
+        let first = fragment_page(&s, &graph, &people, 1, fragments);
+        assert_eq!(Estimate { count: DEFAULT_PAGE_SIZE + 1, lower_bound: false }, first.estimate);
+        let this = NamedNode::new_unchecked(page_url(fragments, &people, 1));
+        let next = NamedNode::new_unchecked(page_url(fragments, &people, 2));
+        assert!(first.triples.contains(&Triple::new(this.clone(), hydra::NEXT, next.clone())));
+        assert!(first.triples.contains(&Triple::new(this, hydra::TOTAL_ITEMS, Literal::new_typed_literal((DEFAULT_PAGE_SIZE + 1).to_string(), xsd::INTEGER))));
+        assert!(next.as_str().ends_with("?predicate=http%3A%2F%2Fwww.w3.org%2F1999%2F02%2F22-rdf-syntax-ns%23type&object=http%3A%2F%2Fexample.com%2FPerson&page=2"));
+
+        let second = fragment_page(&s, &graph, &people, 2, fragments);
+        assert_eq!(1, second.triples.iter().filter(|t| t.predicate.as_ref() == rdf::TYPE && t.object == Term::from(ex("Person"))).count());
+        assert!(!second.triples.iter().any(|t| t.predicate.as_ref() == hydra::NEXT));
+
+        let beyond = fragment_page(&s, &graph, &people, usize::MAX, fragments);
+        assert!(!beyond.triples.iter().any(|t| t.object == Term::from(ex("Person")) || t.predicate.as_ref() == hydra::NEXT));
+
+        assert_eq!("\"say \\\"chat\\\"\"@fr", explicit(&Literal::new_language_tagged_literal_unchecked("say \"chat\"", "fr").into()));
+    }
+}
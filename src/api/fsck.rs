@@ -0,0 +1,197 @@
+//! Checks that `<Meta>` agrees with the named graphs actually in the store.
+//!
+//! `<Meta>` can drift from the store: a load can register a graph whose triples never made it in,
+//! and graphs can be written without going through Janus at all. A check finds
+//! - orphan graphs, which hold data but aren't registered in `<Meta>`,
+//! - graphs registered in `<Meta>` that have none of the data recorded for them,
+//! - `hasInferencesAt` and `inferredFrom` links to graphs that aren't there,
+//! - graphs registered with no known type.
+//!
+//! A repair adopts orphans as `Unknown` graphs, the way `/admin/adopt` would, takes the entries of
+//! missing graphs out of `<Meta>` and drops dangling links. Unknown types are only reported: there's no telling what
+//! they should be.
+
+use std::collections::HashSet;
+
+use oxigraph::SledStore as Store;
+use oxigraph::model::{GraphNameRef, NamedNode, NamedOrBlankNode, Quad, Term};
+
+use serde::Serialize;
+
+use crate::meta;
+use crate::api::{unbracket, GraphType};
+use crate::api::adopt;
+use crate::api::storage::{accounted_graph_list, apply_changes, content_hash, recorded_content_hash};
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "problem", rename_all = "camelCase")]
+pub enum Problem {
+    /// A graph with data that `<Meta>` doesn't know about.
+    Orphan { graph: String },
+    /// A graph registered in `<Meta>` with none of the data it was recorded with.
+    Missing { graph: String },
+    /// A link from a graph's `<Meta>` entry to a graph that isn't there.
+    Dangling { graph: String, link: String, target: String },
+    /// A graph registered without a known type.
+    UnknownType { graph: String },
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FsckReport {
+    pub problems: Vec<Problem>,
+    /// The problems a repair fixed. Empty unless repairing.
+    pub repaired: Vec<Problem>,
+}
+
+/// How a problem is repaired.
+enum Repair {
+    /// Adopting the orphan graph.
+    Adopt(NamedNode),
+    /// Taking these quads out of the store and putting those in.
+    Changes(Vec<Quad>, Vec<Quad>),
+    /// It can't be.
+    None,
+}
+
+impl FsckReport {
+    pub fn is_clean(&self) -> bool {
+        self.problems.len() == self.repaired.len()
+    }
+}
+
+/// Finds the problems in `store`, and with `repair`, fixes those it can.
+pub fn check(store: &Store, repair: bool) -> Result<FsckReport, String> {
+    let mut registered: Vec<(NamedNode, GraphType)> = accounted_graph_list(store)
+        .graphs
+        .into_iter()
+        .filter_map(|g| NamedNode::new(unbracket(&g.id)).ok().map(|n| (n, g.graph_type)))
+        .collect();
+    registered.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+    let names: HashSet<&NamedNode> = registered.iter().map(|(g, _)| g).collect();
+    let existing = stored_graphs(store);
+
+    let mut found: Vec<(Problem, Repair)> = Vec::new();
+
+    let mut orphans: Vec<&NamedNode> = existing.iter().filter(|g| !names.contains(g)).collect();
+    orphans.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    for orphan in orphans {
+        found.push((Problem::Orphan { graph: orphan.to_string() }, Repair::Adopt(orphan.clone())));
+    }
+
+    for (graph, graph_type) in registered.iter() {
+        // Only a graph recorded as empty can be registered without any quads
+        let is_missing = !existing.contains(graph)
+            && recorded_content_hash(store, graph.as_ref()).map_or(true, |h| Ok(h) != content_hash(&[]));
+        if is_missing {
+            let entry = store.quads_for_pattern(Some(graph.as_ref().into()), None, None, Some(meta::meta_graph_uri()))
+                .filter_map(Result::ok)
+                .collect();
+            found.push((Problem::Missing { graph: graph.to_string() }, Repair::Changes(entry, vec![])));
+            continue;
+        }
+
+        for link in [meta::hasInferencesAt, meta::inferredFrom].iter() {
+            let link = NamedNode::from(*link);
+            let dangling = store.quads_for_pattern(Some(graph.as_ref().into()), Some(link.as_ref()), None, Some(meta::meta_graph_uri()))
+                .filter_map(Result::ok)
+                .filter(|q| match &q.object {
+                    Term::NamedNode(target) => !names.contains(target) && !existing.contains(target),
+                    _ => true,
+                });
+            for quad in dangling {
+                let problem = Problem::Dangling { graph: graph.to_string(), link: link.to_string(), target: quad.object.to_string() };
+                found.push((problem, Repair::Changes(vec![quad], vec![])));
+            }
+        }
+
+        if *graph_type == GraphType::Unknown {
+            found.push((Problem::UnknownType { graph: graph.to_string() }, Repair::None));
+        }
+    }
+
+    let mut repaired = Vec::new();
+    if repair {
+        for (problem, repair) in found.iter() {
+            match repair {
+                Repair::Adopt(graph) => adopt::adopt(store, graph, GraphType::Unknown)?,
+                Repair::Changes(remove, insert) => apply_changes(store, remove, insert)?,
+                Repair::None => continue,
+            }
+            repaired.push(problem.clone());
+        }
+    }
+
+    Ok(FsckReport { problems: found.into_iter().map(|(p, _)| p).collect(), repaired })
+}
+
+/// The graphs holding data that aren't registered in `<Meta>`, by name.
+pub fn unregistered_graphs(store: &Store) -> Vec<NamedNode> {
+    let registered: HashSet<String> = accounted_graph_list(store).graphs.into_iter().map(|g| g.id).collect();
+    let mut unregistered: Vec<NamedNode> = stored_graphs(store)
+        .into_iter()
+        .filter(|g| !registered.contains(&g.to_string()))
+        .collect();
+    unregistered.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    unregistered
+}
+
+/// The named graphs holding any data, apart from the reserved ones.
+fn stored_graphs(store: &Store) -> HashSet<NamedNode> {
+    let reserved = meta::reserved_graph_uris();
+    store.named_graphs()
+        .filter_map(Result::ok)
+        .filter_map(|g| match g {
+            NamedOrBlankNode::NamedNode(n) => Some(n),
+            NamedOrBlankNode::BlankNode(_) => None,
+        })
+        .filter(|g| !reserved.contains(&GraphNameRef::NamedNode(g.as_ref())))
+        .filter(|g| store.quads_for_pattern(None, None, None, Some(GraphNameRef::NamedNode(g.as_ref()))).next().is_some())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use oxigraph::model::Triple;
+    use oxigraph::model::vocab::rdf;
+    use crate::api::storage::load_triples_into_new_graph;
+    use crate::api::storage::test::init_store;
+
+    #[test]
+    fn test_check_and_repair() {
+        let (s, _f) = init_store();
+        assert_eq!(FsckReport { problems: vec![], repaired: vec![] }, check(&s, false).unwrap());
+
+        let thing = NamedNode::new_unchecked("http://example.com/thing");
+        let orphan = NamedNode::new_unchecked("http://example.com/orphan");
+        s.insert(&Quad::new(thing.clone(), rdf::TYPE, thing.clone(), orphan.clone())).unwrap();
+
+        let missing = NamedNode::new_unchecked("http://example.com/missing");
+        load_triples_into_new_graph(&s, missing.clone(), GraphType::Model, vec![Triple::new(thing.clone(), rdf::TYPE, thing.clone())]);
+        s.remove(&Quad::new(thing.clone(), rdf::TYPE, thing.clone(), missing.clone())).unwrap();
+
+        let unhashed = NamedNode::new_unchecked("http://example.com/unhashed");
+        s.insert(&meta::graph_metadata_entry(unhashed.clone(), GraphType::Model)).unwrap();
+
+        let model = NamedNode::new_unchecked("http://example.com/model");
+        load_triples_into_new_graph(&s, model.clone(), GraphType::Model, vec![Triple::new(thing.clone(), rdf::TYPE, thing.clone())]);
+        let link = Quad::new(model.clone(), NamedNode::from(meta::hasInferencesAt), NamedNode::new_unchecked("http://example.com/gone"), meta::meta_graph_uri());
+        s.insert(&link).unwrap();
+
+        let report = check(&s, false).unwrap();
+        assert!(report.problems.contains(&Problem::Orphan { graph: orphan.to_string() }));
+        assert!(report.problems.contains(&Problem::Missing { graph: missing.to_string() }));
+        assert!(report.problems.contains(&Problem::Missing { graph: unhashed.to_string() }));
+        assert!(report.problems.contains(&Problem::Dangling { graph: model.to_string(), link: link.predicate.to_string(), target: link.object.to_string() }));
+        assert!(!report.is_clean());
+        assert!(report.repaired.is_empty());
+
+        let repair = check(&s, true).unwrap();
+        assert_eq!(report.problems, repair.problems);
+        assert!(repair.is_clean());
+        assert_eq!(1, crate::api::history::current_revision(&s, orphan.as_ref()).unwrap().number);
+
+        let after = check(&s, false).unwrap();
+        assert_eq!(vec![Problem::UnknownType { graph: orphan.to_string() }], after.problems);
+    }
+}
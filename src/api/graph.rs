@@ -17,9 +17,11 @@ use crate::meta;
 #[derive(Serialize, Clone, Copy, Debug, PartialEq, EnumIter, AsRefStr)]
 pub enum GraphType {
     Ontology,
+    OntologySource,
     Closure,
     Model,
     Inferred,
+    Shapes,
     Unknown,
 }
 
@@ -41,9 +43,11 @@ impl GraphType {
     pub fn uri(&self) -> SimpleIri {
         match self {
             GraphType::Ontology => meta::Ontology,
+            GraphType::OntologySource => meta::OntologySource,
             GraphType::Closure => meta::Closure,
             GraphType::Model => meta::Model,
             GraphType::Inferred => meta::Inferred,
+            GraphType::Shapes => meta::Shapes,
             GraphType::Unknown => meta::Unknown,
         }
     }
@@ -85,6 +89,36 @@ impl<'n> From<NamedNodeRef<'n>> for GraphType {
     }
 }
 
+/// Formats a graph can be read out in.
+///
+/// `Canonical` is N-Triples with blank nodes relabelled by RDFC-1.0 and lines sorted, which is
+/// byte for byte the same for every load of the same content.
+///
+/// `ntriples-star` and `turtle-star` are N-Triples-star and Turtle-star, with the quoted triples of
+/// a Turtle-star upload written back as quoted triples instead of the reifications they are
+/// stored as.
+#[derive(Clone, Copy, Debug, PartialEq, EnumIter, AsRefStr)]
+pub enum ExportFormat {
+    Turtle,
+    Canonical,
+    #[strum(serialize = "ntriples-star")]
+    NTriplesStar,
+    #[strum(serialize = "turtle-star")]
+    TurtleStar,
+}
+
+impl<'a> TryFrom<&'a str> for ExportFormat {
+    type Error = &'a str;
+
+    fn try_from(val: &'a str) -> Result<ExportFormat, Self::Error> {
+        let c = UniCase::new(val);
+        match ExportFormat::iter().find(|f| UniCase::new(f.as_ref()) == c) {
+            Some(f) => Ok(f),
+            None => Err(val),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum KnownGraphType<G> {
     Known(G),
@@ -105,28 +139,72 @@ impl KnownGraphType<GraphType> {
 pub struct GraphData {
     pub id: String,
     pub graph_type: GraphType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    /// Where the graph is on this server, by its alias if it has one.
+    pub path: String,
 }
 
+/// The JSON-LD context of every JSON response.
+pub const CONTEXT: &str = "http://www.purl.org/dougli1sqrd/models/janus-oxide/meta/context.json";
+
 #[derive(Serialize)]
 pub struct GraphList {
     pub context: String,
     pub graphs: Vec<GraphData>,
+    /// How many graphs there are on all pages, when only one page is listed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<usize>,
+    /// The next page, if there is one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct UriWrapper(pub NamedNode);
 
+/// `<iri>` as the bare `iri`, the way graphs are written in listings and reports. Anything not in
+/// angle brackets is given back as it is.
+pub fn unbracket(id: &str) -> &str {
+    id.strip_prefix('<').and_then(|iri| iri.strip_suffix('>')).unwrap_or(id)
+}
+
+/// Where this server is reached, as `scheme://host`, for making absolute links back to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BaseUrl(pub String);
+
+impl BaseUrl {
+    pub fn join(&self, path: &str) -> String {
+        format!("{}{}", self.0.trim_end_matches('/'), path)
+    }
+}
+
 
 #[cfg(test)]
 mod testr {
     use super::*;
 
+    #[test]
+    fn test_unbracket() {
+        assert_eq!("http://example.com/G", unbracket("<http://example.com/G>"));
+        assert_eq!("http://example.com/G", unbracket("http://example.com/G"));
+        assert_eq!("<http://example.com/G", unbracket("<http://example.com/G"));
+    }
+
     #[test]
     fn test_graphtype_uri() {
         assert_eq!(GraphType::Model.uri(), meta::Model);
         assert_eq!(GraphType::Closure.uri(), meta::Closure);
         assert_eq!(GraphType::Inferred.uri(), meta::Inferred);
         assert_eq!(GraphType::Ontology.uri(), meta::Ontology);
+        assert_eq!(GraphType::OntologySource.uri(), meta::OntologySource);
+        assert_eq!(GraphType::Shapes.uri(), meta::Shapes);
         assert_eq!(GraphType::Unknown.uri(), meta::Unknown);
     }
 
@@ -150,6 +228,15 @@ mod testr {
         assert_eq!(GraphType::from(NamedNodeRef::new_unchecked("http::www.example.com/Blah")), GraphType::Unknown);
     }
 
+    #[test]
+    fn test_export_format_from_str() {
+        assert_eq!(ExportFormat::try_from("canonical"), Ok(ExportFormat::Canonical));
+        assert_eq!(ExportFormat::try_from("Turtle"), Ok(ExportFormat::Turtle));
+        assert_eq!(ExportFormat::try_from("NTriples-Star"), Ok(ExportFormat::NTriplesStar));
+        assert_eq!(ExportFormat::try_from("turtle-star"), Ok(ExportFormat::TurtleStar));
+        assert_eq!(ExportFormat::try_from("rdfxml"), Err("rdfxml"));
+    }
+
     #[test]
     fn test_known_graph_from_graphtype() {
         assert_eq!(KnownGraphType::new(GraphType::Model), KnownGraphType::Known(GraphType::Model));
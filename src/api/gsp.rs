@@ -0,0 +1,129 @@
+//! What the Graph Store Protocol routes under `/store` read and write besides single RDF
+//! documents: the formats a graph can be fetched in, and `multipart/form-data` uploads of several
+//! documents at once.
+
+use oxigraph::io::GraphFormat;
+use oxigraph::model::Triple;
+
+use crate::api::jsonld;
+use crate::api::storage::serialize_triples;
+
+/// What a graph is fetched as, chosen by content negotiation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StoreFormat {
+    Turtle,
+    NTriples,
+    RdfXml,
+    JsonLd,
+}
+
+impl StoreFormat {
+    /// The format served for a media type like `application/n-triples`, if there is one.
+    pub fn from_media_type(top: &str, sub: &str) -> Option<StoreFormat> {
+        match (top, sub) {
+            ("text", "turtle") => Some(StoreFormat::Turtle),
+            ("application", "n-triples") => Some(StoreFormat::NTriples),
+            ("application", "rdf+xml") => Some(StoreFormat::RdfXml),
+            ("application", "ld+json") => Some(StoreFormat::JsonLd),
+            _ => None,
+        }
+    }
+
+    /// The media type of the format, as its type and subtype.
+    pub fn media_type(self) -> (&'static str, &'static str) {
+        match self {
+            StoreFormat::Turtle => ("text", "turtle"),
+            StoreFormat::NTriples => ("application", "n-triples"),
+            StoreFormat::RdfXml => ("application", "rdf+xml"),
+            StoreFormat::JsonLd => ("application", "ld+json"),
+        }
+    }
+
+    /// Writes out the triples in this format. JSON-LD is expanded, as on `/resource`.
+    pub fn serialize(self, triples: Vec<Triple>) -> Result<String, String> {
+        match self {
+            StoreFormat::Turtle => serialize_triples(triples, GraphFormat::Turtle),
+            StoreFormat::NTriples => serialize_triples(triples, GraphFormat::NTriples),
+            StoreFormat::RdfXml => serialize_triples(triples, GraphFormat::RdfXml),
+            StoreFormat::JsonLd => Ok(jsonld::expanded(&triples).to_string()),
+        }
+    }
+}
+
+/// One part of a `multipart/form-data` body, with the media type from its own `Content-Type`.
+#[derive(Debug, PartialEq)]
+pub struct Part<'a> {
+    pub content_type: Option<String>,
+    pub body: &'a [u8],
+}
+
+/// The parts of a `multipart/form-data` body whose parts are delimited by `boundary`.
+pub fn multipart_parts<'a>(body: &'a [u8], boundary: &str) -> Result<Vec<Part<'a>>, String> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let start = find(body, &delimiter).ok_or_else(|| format!("No part starts with --{}", boundary))?;
+    let mut rest = &body[start + delimiter.len()..];
+
+    let mut parts = Vec::new();
+    while !rest.starts_with(b"--") {
+        rest = strip_line_break(rest);
+        let end = find(rest, &delimiter).ok_or_else(|| format!("A part is not closed by --{}", boundary))?;
+        let part = &rest[..end];
+        rest = &rest[end + delimiter.len()..];
+
+        let (headers, body) = match find(part, b"\r\n\r\n") {
+            Some(split) => (&part[..split], &part[split + 4..]),
+            None => match find(part, b"\n\n") {
+                Some(split) => (&part[..split], &part[split + 2..]),
+                None => return Err("A part has no blank line after its headers".to_owned()),
+            },
+        };
+        // The line break before the next delimiter belongs to the delimiter
+        let body = body.strip_suffix(b"\r\n").or_else(|| body.strip_suffix(b"\n")).unwrap_or(body);
+        let headers = String::from_utf8_lossy(headers);
+        let content_type = headers.lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("Content-Type"))
+            .map(|(_, value)| value.trim().to_owned());
+        parts.push(Part { content_type, body });
+    }
+    Ok(parts)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn strip_line_break(bytes: &[u8]) -> &[u8] {
+    bytes.strip_prefix(b"\r\n").or_else(|| bytes.strip_prefix(b"\n")).unwrap_or(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_multipart_parts() {
+        let body = b"preamble\r\n--XyZ\r\nContent-Disposition: form-data; name=\"a\"; filename=\"a.ttl\"\r\nContent-Type: text/turtle\r\n\r\n<http://example.com/A> <http://example.com/is> \"A\" .\r\n--XyZ\r\nContent-Disposition: form-data; name=\"b\"\r\n\r\nB\r\n--XyZ--\r\n";
+        let parts = multipart_parts(body, "XyZ").unwrap();
+
+        assert_eq!(2, parts.len());
+        assert_eq!(Some("text/turtle".to_owned()), parts[0].content_type);
+        assert_eq!(&b"<http://example.com/A> <http://example.com/is> \"A\" ."[..], parts[0].body);
+        assert_eq!(Part { content_type: None, body: b"B" }, parts[1]);
+
+        assert!(multipart_parts(b"--XyZ\r\n\r\nunclosed", "XyZ").is_err());
+        assert!(multipart_parts(b"no parts", "XyZ").is_err());
+    }
+
+    #[test]
+    fn test_store_format() {
+        assert_eq!(Some(StoreFormat::RdfXml), StoreFormat::from_media_type("application", "rdf+xml"));
+        assert_eq!(None, StoreFormat::from_media_type("text", "html"));
+        let ntriples = StoreFormat::NTriples.serialize(vec![Triple::new(
+            oxigraph::model::NamedNode::new_unchecked("http://example.com/A"),
+            oxigraph::model::NamedNode::new_unchecked("http://example.com/is"),
+            oxigraph::model::Literal::new_simple_literal("A"),
+        )]).unwrap();
+        assert_eq!("<http://example.com/A> <http://example.com/is> \"A\" .\n", ntriples);
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::HashSet;
+use std::sync::{Condvar, Mutex, PoisonError};
+
+use chrono::{SecondsFormat, Utc};
+
+use oxigraph::SledStore as Store;
+use oxigraph::model::{BlankNode, GraphNameRef, Literal, NamedNode, NamedNodeRef, NamedOrBlankNode, NamedOrBlankNodeRef, Quad, Term, Triple};
+use oxigraph::model::vocab::{rdf, xsd};
+
+use lazy_static::lazy_static;
+
+use serde::Serialize;
+
+use crate::meta;
+
+lazy_static! {
+    /// The graphs being written, and a signal for when one no longer is.
+    static ref WRITING: (Mutex<HashSet<NamedNode>>, Condvar) = (Mutex::new(HashSet::new()), Condvar::new());
+}
+
+/// A numbered write to a graph. Revision numbers start at 1 with the load that created the graph.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Revision {
+    pub number: u64,
+    pub timestamp: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RevisionSummary {
+    pub number: u64,
+    pub timestamp: String,
+    pub added: usize,
+    pub removed: usize,
+}
+
+#[derive(Serialize)]
+pub struct VersionList {
+    pub context: String,
+    pub graph: String,
+    pub versions: Vec<RevisionSummary>,
+}
+
+/// A graph being written. Only one write to a graph holds its lock at a time, from reading what the
+/// graph holds until the write is applied, and it's released on drop.
+///
+/// Sled transactions can't read, so the next revision number is read before the transaction that
+/// records it. Without the lock, two writes to the same graph could both read the same number and
+/// both commit the next one.
+pub struct WriteLock {
+    graph: NamedNode,
+}
+
+impl WriteLock {
+    pub fn graph(&self) -> &NamedNode {
+        &self.graph
+    }
+}
+
+impl Drop for WriteLock {
+    fn drop(&mut self) {
+        let (writing, released) = &*WRITING;
+        writing.lock().unwrap_or_else(PoisonError::into_inner).remove(&self.graph);
+        released.notify_all();
+    }
+}
+
+/// Waits until no other write holds `graph`'s lock, and takes it.
+pub fn lock(graph: &NamedNode) -> WriteLock {
+    let (writing, released) = &*WRITING;
+    let mut writing = writing.lock().unwrap_or_else(PoisonError::into_inner);
+    while writing.contains(graph) {
+        writing = released.wait(writing).unwrap_or_else(PoisonError::into_inner);
+    }
+    writing.insert(graph.clone());
+    WriteLock { graph: graph.clone() }
+}
+
+/// Everything a write needs to do, on top of changing the graph itself, so that the write
+/// becomes a new revision: the changeset in `<History>` and the updated revision in `<Meta>`.
+///
+/// Sled transactions can't read, so this is worked out up front, under the graph's `WriteLock`,
+/// and then applied inside the same transaction as the write.
+pub struct RevisionWrite {
+    pub revision: Revision,
+    pub insert: Vec<Quad>,
+    pub remove: Vec<Quad>,
+}
+
+/// The revision a graph is currently at, as recorded in its `<Meta>` entry.
+/// Graphs written before versioning existed have no revision.
+pub fn current_revision(store: &Store, graph: NamedNodeRef) -> Option<Revision> {
+    let number = literal_value(store, graph.into(), NamedNode::from(meta::currentRevision).as_ref(), meta::meta_graph_uri())?
+        .parse()
+        .ok()?;
+    let timestamp = literal_value(store, graph.into(), NamedNode::from(meta::modified).as_ref(), meta::meta_graph_uri())?;
+
+    Some(Revision { number, timestamp })
+}
+
+/// Builds the next revision of `graph` out of the triples a write adds and removes.
+///
+/// The changeset is recorded in `<History>` as reified statements hanging off a `meta:Revision`:
+/// ```
+/// _:r a meta:Revision ;
+///     meta:revisionOf <G> ;
+///     meta:revisionNumber 2 ;
+///     meta:revisionTime "2021-02-14T12:00:00Z"^^xsd:dateTime ;
+///     meta:added [ rdf:subject <s> ; rdf:predicate <p> ; rdf:object <o> ] .
+/// ```
+/// and the `meta:currentRevision` and `meta:modified` values on `<G>` in `<Meta>` are replaced.
+/// The lock on `<G>` has to be held until the write is applied.
+pub fn prepare_write(store: &Store, lock: &WriteLock, added: &[Triple], removed: &[Triple]) -> RevisionWrite {
+    let graph = lock.graph();
+    let number = current_revision(store, graph.as_ref()).map_or(1, |r| r.number + 1);
+    let revision = Revision {
+        number,
+        timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+    };
+
+    let history = meta::history_graph_uri();
+    let node = BlankNode::default();
+    let number_literal = Literal::new_typed_literal(revision.number.to_string(), xsd::INTEGER);
+    let time_literal = Literal::new_typed_literal(revision.timestamp.clone(), xsd::DATE_TIME);
+
+    let mut insert = vec![
+        Quad::new(node.clone(), rdf::TYPE, NamedNode::from(meta::Revision), history),
+        Quad::new(node.clone(), NamedNode::from(meta::revisionOf), graph.clone(), history),
+        Quad::new(node.clone(), NamedNode::from(meta::revisionNumber), number_literal.clone(), history),
+        Quad::new(node.clone(), NamedNode::from(meta::revisionTime), time_literal.clone(), history),
+    ];
+    for triple in added {
+        insert.extend(statement_quads(&node, NamedNode::from(meta::added), triple));
+    }
+    for triple in removed {
+        insert.extend(statement_quads(&node, NamedNode::from(meta::removed), triple));
+    }
+
+    insert.push(Quad::new(graph.clone(), NamedNode::from(meta::currentRevision), number_literal, meta::meta_graph_uri()));
+    insert.push(Quad::new(graph.clone(), NamedNode::from(meta::modified), time_literal, meta::meta_graph_uri()));
+
+    let current_revision_predicate = NamedNode::from(meta::currentRevision);
+    let modified_predicate = NamedNode::from(meta::modified);
+    let remove = store
+        .quads_for_pattern(Some(graph.as_ref().into()), None, None, Some(meta::meta_graph_uri()))
+        .filter_map(Result::ok)
+        .filter(|q| q.predicate == current_revision_predicate || q.predicate == modified_predicate)
+        .collect();
+
+    RevisionWrite { revision, insert, remove }
+}
+
+/// Lists every recorded revision of `graph`, oldest first.
+pub fn list_revisions(store: &Store, graph: NamedNodeRef) -> Vec<RevisionSummary> {
+    let history = meta::history_graph_uri();
+    let added = NamedNode::from(meta::added);
+    let removed = NamedNode::from(meta::removed);
+
+    revision_nodes(store, graph)
+        .into_iter()
+        .map(|(revision, node)| RevisionSummary {
+            number: revision.number,
+            timestamp: revision.timestamp,
+            added: store.quads_for_pattern(Some(node.as_ref()), Some(added.as_ref()), None, Some(history)).count(),
+            removed: store.quads_for_pattern(Some(node.as_ref()), Some(removed.as_ref()), None, Some(history)).count(),
+        })
+        .collect()
+}
+
+/// Rebuilds the contents of `graph` as they were right after revision `number` by replaying
+/// its changesets in order. `None` if the graph never had that revision.
+pub fn graph_at_revision(store: &Store, graph: NamedNodeRef, number: u64) -> Option<Vec<Triple>> {
+    let nodes = revision_nodes(store, graph);
+    if !nodes.iter().any(|(revision, _)| revision.number == number) {
+        return None;
+    }
+
+    let added = NamedNode::from(meta::added);
+    let removed = NamedNode::from(meta::removed);
+    let triples = nodes
+        .into_iter()
+        .take_while(|(revision, _)| revision.number <= number)
+        .fold(HashSet::new(), |mut triples, (_, node)| {
+            for triple in changeset_statements(store, node.as_ref(), removed.as_ref()) {
+                triples.remove(&triple);
+            }
+            triples.extend(changeset_statements(store, node.as_ref(), added.as_ref()));
+            triples
+        });
+
+    Some(triples.into_iter().collect())
+}
+
+/// The `meta:Revision` nodes of `graph` in `<History>`, sorted by revision number.
+fn revision_nodes(store: &Store, graph: NamedNodeRef) -> Vec<(Revision, NamedOrBlankNode)> {
+    let history = meta::history_graph_uri();
+    let revision_of = NamedNode::from(meta::revisionOf);
+    let revision_number = NamedNode::from(meta::revisionNumber);
+    let revision_time = NamedNode::from(meta::revisionTime);
+
+    let mut nodes: Vec<_> = store
+        .quads_for_pattern(None, Some(revision_of.as_ref()), Some(graph.into()), Some(history))
+        .filter_map(Result::ok)
+        .filter_map(|q| {
+            let number = literal_value(store, q.subject.as_ref(), revision_number.as_ref(), history)?.parse().ok()?;
+            let timestamp = literal_value(store, q.subject.as_ref(), revision_time.as_ref(), history)?;
+            Some((Revision { number, timestamp }, q.subject))
+        })
+        .collect();
+
+    nodes.sort_by_key(|(revision, _)| revision.number);
+    nodes
+}
+
+fn changeset_statements(store: &Store, revision: NamedOrBlankNodeRef, relation: NamedNodeRef) -> Vec<Triple> {
+    store
+        .quads_for_pattern(Some(revision), Some(relation), None, Some(meta::history_graph_uri()))
+        .filter_map(Result::ok)
+        .filter_map(|q| term_as_subject(q.object))
+        .filter_map(|statement| reified_triple(store, statement.as_ref()))
+        .collect()
+}
+
+fn statement_quads(revision: &BlankNode, relation: NamedNode, triple: &Triple) -> Vec<Quad> {
+    changeset_triples(revision, relation, triple)
+        .into_iter()
+        .map(|t| t.in_graph(meta::history_graph_uri()))
+        .collect()
+}
+
+/// `triple` as an `rdf:Statement` linked from `changeset` by `relation` (`meta:added` or `meta:removed`).
+pub fn changeset_triples(changeset: &BlankNode, relation: NamedNode, triple: &Triple) -> Vec<Triple> {
+    let statement = BlankNode::default();
+    vec![
+        Triple::new(changeset.clone(), relation, statement.clone()),
+        Triple::new(statement.clone(), rdf::TYPE, NamedNode::from(rdf::STATEMENT)),
+        Triple::new(statement.clone(), rdf::SUBJECT, triple.subject.clone()),
+        Triple::new(statement.clone(), rdf::PREDICATE, triple.predicate.clone()),
+        Triple::new(statement, rdf::OBJECT, triple.object.clone()),
+    ]
+}
+
+fn reified_triple(store: &Store, statement: NamedOrBlankNodeRef) -> Option<Triple> {
+    let subject = term_as_subject(first_object(store, statement, rdf::SUBJECT)?)?;
+    let predicate = match first_object(store, statement, rdf::PREDICATE)? {
+        Term::NamedNode(p) => p,
+        _ => return None,
+    };
+    let object = first_object(store, statement, rdf::OBJECT)?;
+
+    Some(Triple::new(subject, predicate, object))
+}
+
+fn first_object(store: &Store, subject: NamedOrBlankNodeRef, predicate: NamedNodeRef) -> Option<Term> {
+    store
+        .quads_for_pattern(Some(subject), Some(predicate), None, Some(meta::history_graph_uri()))
+        .filter_map(Result::ok)
+        .map(|q| q.object)
+        .next()
+}
+
+fn literal_value(store: &Store, subject: NamedOrBlankNodeRef, predicate: NamedNodeRef, graph: GraphNameRef) -> Option<String> {
+    store
+        .quads_for_pattern(Some(subject), Some(predicate), None, Some(graph))
+        .filter_map(Result::ok)
+        .find_map(|q| match q.object {
+            Term::Literal(l) => Some(l.value().to_owned()),
+            _ => None,
+        })
+}
+
+fn term_as_subject(term: Term) -> Option<NamedOrBlankNode> {
+    match term {
+        Term::NamedNode(n) => Some(n.into()),
+        Term::BlankNode(b) => Some(b.into()),
+        Term::Literal(_) => None,
+    }
+}
@@ -0,0 +1,321 @@
+//! Resolution of `owl:imports` for Ontology graphs.
+//!
+//! An import is resolved to a graph already in the store when there is a Meta-registered graph
+//! with the imported IRI as its name, or a graph declaring the IRI as an `owl:Ontology`.
+//! Otherwise it is looked up in a local XML catalog, in the format Protégé writes
+//! (`catalog-v001.xml`), and the file it points at is loaded as a new Ontology graph, whose own
+//! imports are then resolved in turn. For an ontology merged into `<O>`, the file is merged into
+//! `<O>` as well, as a source of its own, so that `<O>` stays the one ontology graph.
+//!
+//! Every resolved import is recorded in `<Meta>` as `<O> meta:imports <imported graph>`.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use oxigraph::SledStore as Store;
+use oxigraph::io::{GraphFormat, GraphParser};
+use oxigraph::model::{GraphName, NamedNode, NamedNodeRef, Quad, Term};
+use oxigraph::model::vocab::rdf;
+
+use quick_xml::Reader;
+use quick_xml::events::Event;
+
+use serde::Serialize;
+
+use crate::api::GraphType;
+use crate::api::ontology;
+use crate::api::provenance::Origin;
+use crate::api::storage::{accounted_graph_list, graphs_of_type, load_new_graph};
+use crate::api::vocab::owl;
+use crate::meta;
+
+/// Where the import catalog is looked for: the `import_catalog` extra of the Rocket config, or
+/// else `catalog/catalog-v001.xml`, relative to the working directory like `metadata/`.
+#[derive(Debug, PartialEq)]
+pub struct CatalogLocation(pub PathBuf);
+
+impl Default for CatalogLocation {
+    fn default() -> CatalogLocation {
+        CatalogLocation(PathBuf::from("catalog/catalog-v001.xml"))
+    }
+}
+
+impl CatalogLocation {
+    /// The catalog there, if there is one.
+    pub fn load(&self) -> Option<Catalog> {
+        Catalog::load(&self.0)
+    }
+}
+
+/// What a file the catalog resolves an import to is loaded as.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImportMode {
+    /// A new Ontology graph.
+    Graph,
+    /// A source merged into `<O>`.
+    Merge,
+}
+
+/// The outcome of resolving the imports of an ontology and, transitively, of its imports.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct ImportReport {
+    pub resolved: Vec<String>,
+    pub unresolved: Vec<String>,
+}
+
+/// An OASIS XML catalog mapping ontology IRIs to local files. Only `<uri name="..." uri="..."/>`
+/// entries are understood; relative file paths are relative to the catalog's directory.
+#[derive(Debug, PartialEq)]
+pub struct Catalog {
+    entries: HashMap<String, PathBuf>,
+}
+
+impl Catalog {
+    /// Reads the catalog at `path`. There is no catalog if the file doesn't exist or can't be parsed.
+    pub fn load(path: &Path) -> Option<Catalog> {
+        let xml = fs::read_to_string(path).ok()?;
+        Catalog::parse(&xml, path.parent().unwrap_or_else(|| Path::new(".")))
+    }
+
+    pub fn parse(xml: &str, base: &Path) -> Option<Catalog> {
+        let mut reader = Reader::from_str(xml);
+        reader.trim_text(true);
+
+        let mut entries = HashMap::new();
+        let mut buf = Vec::new();
+        loop {
+            match reader.read_event(&mut buf) {
+                Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) if e.local_name() == b"uri" => {
+                    let mut name = None;
+                    let mut uri = None;
+                    for attribute in e.attributes().filter_map(Result::ok) {
+                        let value = attribute.unescaped_value().ok()
+                            .map(|v| String::from_utf8_lossy(&v).into_owned());
+                        match attribute.key {
+                            b"name" => name = value,
+                            b"uri" => uri = value,
+                            _ => {}
+                        }
+                    }
+                    if let (Some(name), Some(uri)) = (name, uri) {
+                        let file = uri.trim_start_matches("file://").trim_start_matches("file:");
+                        entries.insert(name, base.join(file));
+                    }
+                }
+                Ok(Event::Eof) => break,
+                Err(_) => return None,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        Some(Catalog { entries })
+    }
+
+    pub fn resolve(&self, iri: &str) -> Option<&Path> {
+        self.entries.get(iri).map(PathBuf::as_path)
+    }
+}
+
+/// The IRIs an ontology graph declares with `owl:imports`.
+pub fn declared_imports(store: &Store, graph: NamedNodeRef) -> Vec<NamedNode> {
+    store.quads_for_pattern(None, Some(owl::IMPORTS), None, Some(graph.into()))
+        .filter_map(Result::ok)
+        .filter_map(|q| match q.object {
+            Term::NamedNode(n) => Some(n),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Resolves the imports of the ontology graph `graph`, loading them from `catalog` as `mode` says
+/// when they aren't in the store yet, and records the `meta:imports` links.
+pub fn resolve_imports(store: &Store, graph: &NamedNode, catalog: Option<&Catalog>, mode: ImportMode) -> ImportReport {
+    let mut report = ImportReport::default();
+    let mut seen: HashSet<NamedNode> = HashSet::new();
+    let mut queue: VecDeque<(NamedNode, NamedNode)> = declared_imports(store, graph.as_ref())
+        .into_iter()
+        .map(|import| (graph.clone(), import))
+        .collect();
+
+    while let Some((importer, import)) = queue.pop_front() {
+        let target = match graph_in_store(store, import.as_ref()) {
+            Some(existing) => Some(existing),
+            None => catalog
+                .and_then(|c| c.resolve(import.as_str()))
+                .and_then(|path| load_from_file(store, &import, path, mode)),
+        };
+
+        match target {
+            Some(target) => {
+                let link = Quad::new(importer, NamedNode::from(meta::imports), target.clone(), meta::meta_graph_uri());
+                let _ = store.insert(link.as_ref());
+
+                if seen.insert(target.clone()) {
+                    report.resolved.push(import.to_string());
+                    queue.extend(declared_imports(store, target.as_ref()).into_iter().map(|i| (target.clone(), i)));
+                }
+            }
+            None => {
+                if !report.unresolved.contains(&import.to_string()) {
+                    report.unresolved.push(import.to_string());
+                }
+            }
+        }
+    }
+
+    report
+}
+
+/// The ontology graph and everything it imports, directly or indirectly, following the
+/// `meta:imports` links in `<Meta>`.
+pub fn import_closure(store: &Store, graph: &NamedNode) -> Vec<NamedNode> {
+    let imports = NamedNode::from(meta::imports);
+    let mut closure = vec![graph.clone()];
+    let mut i = 0;
+    while i < closure.len() {
+        let next: Vec<NamedNode> = store
+            .quads_for_pattern(Some(closure[i].as_ref().into()), Some(imports.as_ref()), None, Some(meta::meta_graph_uri()))
+            .filter_map(Result::ok)
+            .filter_map(|q| match q.object {
+                Term::NamedNode(n) => Some(n),
+                _ => None,
+            })
+            .collect();
+        for n in next {
+            if !closure.contains(&n) {
+                closure.push(n);
+            }
+        }
+        i += 1;
+    }
+    closure
+}
+
+/// The Ontology graphs and everything they import, which is what inference over the ontologies
+/// and their Closure graphs are worked out from.
+pub fn ontology_closure(store: &Store) -> Vec<NamedNode> {
+    let mut graphs: Vec<NamedNode> = Vec::new();
+    for ontology in graphs_of_type(store, GraphType::Ontology) {
+        for graph in import_closure(store, &ontology) {
+            if !graphs.contains(&graph) {
+                graphs.push(graph);
+            }
+        }
+    }
+    graphs
+}
+
+/// The Meta-registered graph named `iri`, or else any graph that declares `iri` an `owl:Ontology`.
+fn graph_in_store(store: &Store, iri: NamedNodeRef) -> Option<NamedNode> {
+    let registered = accounted_graph_list(store)
+        .graphs
+        .into_iter()
+        .any(|g| g.id == iri.to_string());
+    if registered {
+        return Some(NamedNode::from(iri));
+    }
+
+    store.quads_for_pattern(Some(iri.into()), Some(rdf::TYPE), Some(owl::ONTOLOGY.into()), None)
+        .filter_map(Result::ok)
+        .find_map(|q| match q.graph_name {
+            GraphName::NamedNode(g) => Some(g),
+            _ => None,
+        })
+}
+
+fn load_from_file(store: &Store, iri: &NamedNode, path: &Path, mode: ImportMode) -> Option<NamedNode> {
+    let file = File::open(path).ok()?;
+    let format = format_for(path);
+    let triples = GraphParser::from_format(format)
+        .with_base_iri(iri.as_str())
+        .ok()?
+        .read_triples(BufReader::new(file))
+        .ok()?
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+
+    let origin = Origin {
+        source: Some(path.display().to_string()),
+        format: Some(format.media_type().to_owned()),
+        ..Origin::default()
+    };
+    let loaded = match mode {
+        ImportMode::Graph => load_new_graph(store, iri.clone(), GraphType::Ontology, triples, Some(&origin)).map(|_| ()),
+        ImportMode::Merge => ontology::merge_source(store, iri, triples, Some(&origin)).map(|_| ()),
+    };
+    loaded.ok().map(|_| iri.clone())
+}
+
+/// Ontology files are mostly RDF/XML whatever their extension, so that's the fallback.
+fn format_for(path: &Path) -> GraphFormat {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("ttl") => GraphFormat::Turtle,
+        Some("nt") => GraphFormat::NTriples,
+        _ => GraphFormat::RdfXml,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use oxigraph::model::Triple;
+    use crate::api::provenance;
+    use crate::api::storage::{graph_triples, load_triples_into_new_graph};
+    use crate::api::storage::test::init_store;
+
+    #[test]
+    fn test_parse_catalog() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>
+<catalog prefer="public" xmlns="urn:oasis:names:tc:entity:xmlns:xml:catalog">
+    <group id="Folder Repository, directory=, recursive=false, Auto-Update=false, version=2" prefer="public" xml:base="">
+        <uri id="User Entered Import Resolution" name="http://purl.obolibrary.org/obo/ro.owl" uri="imports/ro.owl"/>
+    </group>
+    <uri name="http://example.com/other.ttl" uri="file:other.ttl"/>
+</catalog>"#;
+
+        let catalog = Catalog::parse(xml, Path::new("catalog")).unwrap();
+        assert_eq!(Some(Path::new("catalog/imports/ro.owl")), catalog.resolve("http://purl.obolibrary.org/obo/ro.owl"));
+        assert_eq!(Some(Path::new("catalog/other.ttl")), catalog.resolve("http://example.com/other.ttl"));
+        assert_eq!(None, catalog.resolve("http://example.com/missing.owl"));
+    }
+
+    #[test]
+    fn test_ontology_closure() {
+        let (s, _f) = init_store();
+        let go = NamedNode::new_unchecked("http://example.com/go");
+        let ro = NamedNode::new_unchecked("http://example.com/ro");
+        load_triples_into_new_graph(&s, go.clone(), GraphType::Ontology, vec![Triple::new(go.clone(), owl::IMPORTS, ro.clone())]);
+        s.insert(Quad::new(ro.clone(), rdf::TYPE, NamedNode::from(owl::ONTOLOGY), ro.clone()).as_ref()).unwrap();
+
+        assert_eq!(vec![go.clone()], ontology_closure(&s));
+        assert_eq!(vec![ro.to_string()], resolve_imports(&s, &go, None, ImportMode::Graph).resolved);
+        assert_eq!(vec![go.clone(), ro.clone()], ontology_closure(&s));
+        assert_eq!(vec![go, ro], provenance::input_graphs(&s, &NamedNode::new_unchecked("http://example.com/closure"), GraphType::Closure));
+    }
+
+    #[test]
+    fn test_merged_imports_go_into_the_ontology() {
+        let (s, f) = init_store();
+        let go = NamedNode::new_unchecked("http://example.com/go");
+        let ro = NamedNode::new_unchecked("http://example.com/ro");
+        let part = Triple::new(NamedNode::new_unchecked("http://example.com/Part"), rdf::TYPE, NamedNode::from(owl::CLASS));
+        fs::write(f.path().join("ro.nt"), format!("{} .\n", part)).unwrap();
+        let catalog = Catalog::parse(r#"<catalog><uri name="http://example.com/ro" uri="ro.nt"/></catalog>"#, f.path()).unwrap();
+
+        ontology::merge_source(&s, &go, vec![Triple::new(go.clone(), owl::IMPORTS, ro.clone())], None).unwrap();
+        assert_eq!(vec![ro.to_string()], resolve_imports(&s, &go, Some(&catalog), ImportMode::Merge).resolved);
+
+        assert!(ontology::sources(&s).contains(&ro));
+        assert!(graph_triples(&s, meta::ontology_graph_uri()).contains(&part));
+        assert!(!graphs_of_type(&s, GraphType::Ontology).contains(&ro));
+    }
+
+    #[test]
+    fn test_format_for() {
+        assert_eq!(GraphFormat::Turtle, format_for(Path::new("go.ttl")));
+        assert_eq!(GraphFormat::RdfXml, format_for(Path::new("go.owl")));
+    }
+}
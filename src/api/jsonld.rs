@@ -0,0 +1,85 @@
+//! Triples as JSON-LD, in expanded and flattened form: one node object per subject, with every
+//! value in an array, so no `@context` is needed to read it.
+
+use std::collections::BTreeMap;
+
+use oxigraph::model::{Literal, NamedOrBlankNode, Term, Triple};
+use oxigraph::model::vocab::{rdf, xsd};
+
+use serde_json::{json, Map, Value};
+
+/// The triples as an array of node objects, subjects and their properties in order.
+pub fn expanded(triples: &[Triple]) -> Value {
+    let mut nodes: BTreeMap<String, Map<String, Value>> = BTreeMap::new();
+    for triple in triples {
+        let id = subject_id(&triple.subject);
+        let node = nodes.entry(id.clone()).or_insert_with(|| {
+            let mut node = Map::new();
+            node.insert("@id".to_owned(), Value::String(id));
+            node
+        });
+        let (key, value) = match (&triple.object, triple.predicate.as_ref() == rdf::TYPE) {
+            (Term::NamedNode(class), true) => ("@type".to_owned(), Value::String(class.as_str().to_owned())),
+            (object, _) => (triple.predicate.as_str().to_owned(), object_value(object)),
+        };
+        match node.entry(key).or_insert_with(|| Value::Array(vec![])) {
+            Value::Array(values) => values.push(value),
+            _ => unreachable!(),
+        }
+    }
+    Value::Array(nodes.into_iter().map(|(_, node)| Value::Object(node)).collect())
+}
+
+fn subject_id(subject: &NamedOrBlankNode) -> String {
+    match subject {
+        NamedOrBlankNode::NamedNode(n) => n.as_str().to_owned(),
+        NamedOrBlankNode::BlankNode(b) => b.to_string(),
+    }
+}
+
+fn object_value(object: &Term) -> Value {
+    match object {
+        Term::NamedNode(n) => json!({"@id": n.as_str()}),
+        Term::BlankNode(b) => json!({"@id": b.to_string()}),
+        Term::Literal(l) => literal_value(l),
+    }
+}
+
+fn literal_value(literal: &Literal) -> Value {
+    if let Some(language) = literal.language() {
+        json!({"@value": literal.value(), "@language": language})
+    } else if literal.datatype() == xsd::STRING {
+        json!({"@value": literal.value()})
+    } else {
+        json!({"@value": literal.value(), "@type": literal.datatype().as_str()})
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use oxigraph::model::{BlankNode, NamedNode};
+
+    #[test]
+    fn test_expanded() {
+        let thing = NamedNode::new_unchecked("http://example.com/thing");
+        let label = NamedNode::new_unchecked("http://www.w3.org/2000/01/rdf-schema#label");
+        let part = BlankNode::new_unchecked("part");
+        let triples = vec![
+            Triple::new(thing.clone(), rdf::TYPE, NamedNode::new_unchecked("http://example.com/Thing")),
+            Triple::new(thing.clone(), label.clone(), Literal::new_language_tagged_literal_unchecked("thing", "en")),
+            Triple::new(thing.clone(), NamedNode::new_unchecked("http://example.com/part"), part.clone()),
+            Triple::new(part, NamedNode::new_unchecked("http://example.com/size"), Literal::new_typed_literal("3", xsd::INTEGER)),
+        ];
+
+        assert_eq!(json!([
+            {"@id": "_:part", "http://example.com/size": [{"@value": "3", "@type": "http://www.w3.org/2001/XMLSchema#integer"}]},
+            {
+                "@id": "http://example.com/thing",
+                "@type": ["http://example.com/Thing"],
+                "http://www.w3.org/2000/01/rdf-schema#label": [{"@value": "thing", "@language": "en"}],
+                "http://example.com/part": [{"@id": "_:part"}]
+            }
+        ]), expanded(&triples));
+    }
+}
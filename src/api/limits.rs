@@ -0,0 +1,294 @@
+//! Limits that keep one client from flooding the store: how many write and query requests each
+//! client may make a minute, how long a SPARQL query may run and how many may run at once.
+//!
+//! Each client has a bucket holding up to a minute's worth of requests, which refills steadily.
+//! A request takes one from the bucket, and a request finding the bucket empty is turned away
+//! with how long until there's another.
+
+use std::collections::{BTreeSet, HashMap};
+use std::net::IpAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How large an upload may be, in bytes, unless a limit is configured for its format.
+pub const DEFAULT_UPLOAD_LIMIT: u64 = 64 * 1024 * 1024;
+
+/// A minute without requests fills any bucket, so a client idle that long is as good as new and
+/// its bucket can be forgotten.
+const IDLE: Duration = Duration::from_secs(60);
+
+/// At most this many buckets are kept. Past that, the clients seen longest ago are forgotten.
+const MAX_BUCKETS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    requests: f64,
+    updated: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    by_client: HashMap<String, Bucket>,
+    /// The clients by when they were last seen, longest ago first.
+    by_time: BTreeSet<(Instant, String)>,
+}
+
+/// A rate limit of one kind of request, per client.
+#[derive(Debug)]
+pub struct Throttle {
+    per_minute: u32,
+    buckets: Mutex<Buckets>,
+}
+
+impl Throttle {
+    /// A limit of `per_minute` requests a minute. `0` is no limit.
+    pub fn new(per_minute: u32) -> Throttle {
+        Throttle {
+            per_minute,
+            buckets: Mutex::new(Buckets::default()),
+        }
+    }
+
+    pub fn per_minute(&self) -> u32 {
+        self.per_minute
+    }
+
+    /// Counts a request by `client`, or says how long it has to wait if it is over the limit.
+    pub fn admit(&self, client: &str) -> Result<(), Duration> {
+        self.admit_at(client, Instant::now())
+    }
+
+    fn admit_at(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        if self.per_minute == 0 {
+            return Ok(());
+        }
+        let capacity = f64::from(self.per_minute);
+        let per_second = capacity / 60.0;
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let Buckets { by_client, by_time } = &mut *buckets;
+        let mut bucket = match by_client.remove(client) {
+            Some(bucket) => {
+                by_time.remove(&(bucket.updated, client.to_owned()));
+                bucket
+            }
+            None => Bucket { requests: capacity, updated: now },
+        };
+        while let Some((seen, oldest)) = by_time.iter().next().cloned() {
+            if now.saturating_duration_since(seen) < IDLE && by_client.len() < MAX_BUCKETS {
+                break;
+            }
+            by_time.remove(&(seen, oldest.clone()));
+            by_client.remove(&oldest);
+        }
+
+        let refilled = now.saturating_duration_since(bucket.updated).as_secs_f64() * per_second;
+        bucket.requests = (bucket.requests + refilled).min(capacity);
+        bucket.updated = now;
+        let admitted = if bucket.requests >= 1.0 {
+            bucket.requests -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.requests) / per_second))
+        };
+
+        by_time.insert((now, client.to_owned()));
+        by_client.insert(client.to_owned(), bucket);
+        admitted
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A fixed number of threads for work that may take long, like SPARQL queries. Work is turned
+/// away when every thread is busy rather than queued, so that there is never more of it going
+/// on than there are threads, even when whoever asked for it has stopped waiting.
+#[derive(Debug)]
+pub struct Workers {
+    size: usize,
+    busy: Arc<AtomicUsize>,
+    jobs: Mutex<mpsc::Sender<Job>>,
+}
+
+impl Workers {
+    pub fn new(size: usize) -> Workers {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let busy = Arc::new(AtomicUsize::new(0));
+        for _ in 0..size {
+            let receiver = receiver.clone();
+            let busy = busy.clone();
+            thread::spawn(move || loop {
+                let job = match receiver.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    Err(_) => break,
+                };
+                // A job that panics shouldn't take its thread with it
+                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                busy.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+
+        Workers {
+            size,
+            busy,
+            jobs: Mutex::new(sender),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Hands `job` to an idle thread. `false` if they are all busy, and the job won't run.
+    pub fn try_run(&self, job: impl FnOnce() + Send + 'static) -> bool {
+        if self.busy.fetch_add(1, Ordering::SeqCst) >= self.size {
+            self.busy.fetch_sub(1, Ordering::SeqCst);
+            return false;
+        }
+        if self.jobs.lock().unwrap().send(Box::new(job)).is_err() {
+            self.busy.fetch_sub(1, Ordering::SeqCst);
+            return false;
+        }
+        true
+    }
+}
+
+/// A request body read within the upload limit of its format.
+#[derive(Debug)]
+pub struct Upload(pub Vec<u8>);
+
+/// A write request that its client's write limit let through.
+#[derive(Debug)]
+pub struct WriteQuota;
+
+/// A query request that its client's query limit let through.
+#[derive(Debug)]
+pub struct QueryQuota;
+
+/// The limits of the server, shared between requests as Rocket managed state.
+#[derive(Debug)]
+pub struct Limits {
+    pub writes: Throttle,
+    pub queries: Throttle,
+    /// How long a SPARQL query is waited for before its request is answered with a timeout.
+    /// `None` is no limit.
+    pub query_timeout: Option<Duration>,
+    /// The threads SPARQL queries are evaluated on, which is how many may run at once.
+    pub query_workers: Workers,
+    /// The reverse proxies whose `X-Real-IP` is believed. Anyone else could send one to get a
+    /// fresh bucket with every request.
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl Limits {
+    pub fn new(writes_per_minute: u32, queries_per_minute: u32, query_timeout: Option<Duration>, query_workers: usize) -> Limits {
+        Limits {
+            writes: Throttle::new(writes_per_minute),
+            queries: Throttle::new(queries_per_minute),
+            query_timeout,
+            query_workers: Workers::new(query_workers),
+            trusted_proxies: Vec::new(),
+        }
+    }
+
+    /// The address a client's requests are counted under: the one a request came from, or the
+    /// one in `X-Real-IP` if it came through a trusted proxy.
+    pub fn client_address(&self, remote: Option<IpAddr>, real_ip: Option<IpAddr>) -> Option<IpAddr> {
+        match remote {
+            Some(proxy) if self.trusted_proxies.contains(&proxy) => real_ip.or(remote),
+            _ => remote,
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits::new(60, 600, Some(Duration::from_secs(30)), 4)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_throttle() {
+        let throttle = Throttle::new(2);
+        let start = Instant::now();
+
+        assert!(throttle.admit_at("alice", start).is_ok());
+        assert!(throttle.admit_at("alice", start).is_ok());
+        let wait = throttle.admit_at("alice", start).unwrap_err();
+        assert_eq!(30, wait.as_secs());
+        assert!(throttle.admit_at("bob", start).is_ok());
+
+        assert!(throttle.admit_at("alice", start + Duration::from_secs(31)).is_ok());
+        assert!(throttle.admit_at("alice", start + Duration::from_secs(31)).is_err());
+
+        let unlimited = Throttle::new(0);
+        assert!((0..1000).all(|_| unlimited.admit_at("alice", start).is_ok()));
+    }
+
+    #[test]
+    fn test_idle_buckets_are_forgotten() {
+        let throttle = Throttle::new(2);
+        let start = Instant::now();
+
+        assert!(throttle.admit_at("alice", start).is_ok());
+        assert!(throttle.admit_at("bob", start + Duration::from_secs(30)).is_ok());
+        assert!(throttle.admit_at("carol", start + Duration::from_secs(61)).is_ok());
+        let clients: Vec<String> = throttle.buckets.lock().unwrap().by_client.keys().cloned().collect();
+        assert!(!clients.contains(&"alice".to_owned()));
+        assert_eq!(2, clients.len());
+    }
+
+    #[test]
+    fn test_buckets_are_capped() {
+        let throttle = Throttle::new(600);
+        let start = Instant::now();
+
+        // Each client keeps its bucket part drained, so none of them are idle
+        for i in 0..MAX_BUCKETS + 10 {
+            assert!(throttle.admit_at(&format!("client{}", i), start + Duration::from_millis(i as u64)).is_ok());
+        }
+        let buckets = throttle.buckets.lock().unwrap();
+        assert_eq!(MAX_BUCKETS, buckets.by_client.len());
+        assert_eq!(MAX_BUCKETS, buckets.by_time.len());
+        assert!(!buckets.by_client.contains_key("client0"));
+        assert!(buckets.by_client.contains_key(&format!("client{}", MAX_BUCKETS + 9)));
+    }
+
+    #[test]
+    fn test_workers() {
+        let workers = Workers::new(1);
+        let (release, wait) = mpsc::channel::<()>();
+
+        assert!(workers.try_run(move || { let _ = wait.recv(); }));
+        assert!(!workers.try_run(|| {}));
+        release.send(()).unwrap();
+        assert!((0..1000).any(|_| {
+            thread::sleep(Duration::from_millis(1));
+            workers.try_run(|| {})
+        }));
+
+        assert!(!Workers::new(0).try_run(|| {}));
+    }
+
+    #[test]
+    fn test_client_address() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let client: IpAddr = "203.0.113.7".parse().unwrap();
+        let claimed: IpAddr = "198.51.100.1".parse().unwrap();
+
+        let mut limits = Limits::default();
+        assert_eq!(Some(client), limits.client_address(Some(client), Some(claimed)));
+        limits.trusted_proxies.push(proxy);
+        assert_eq!(Some(claimed), limits.client_address(Some(proxy), Some(claimed)));
+        assert_eq!(Some(proxy), limits.client_address(Some(proxy), None));
+        assert_eq!(Some(client), limits.client_address(Some(client), Some(claimed)));
+    }
+}
@@ -0,0 +1,195 @@
+//! Filtering, sorting and paging the list of graphs.
+//!
+//! A page is either `offset` graphs in, or follows a cursor. A cursor names the last graph of the
+//! page before it by its sort key and id, so paging on with it neither skips nor repeats graphs
+//! when graphs are added or removed in between.
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use strum::IntoEnumIterator;
+use strum_macros::{AsRefStr, EnumIter};
+
+use unicase::UniCase;
+
+use crate::api::GraphData;
+
+/// The most graphs a page may have, whatever `limit` asks for.
+pub const MAX_PAGE_SIZE: usize = 1_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, EnumIter, AsRefStr)]
+pub enum SortKey {
+    Id,
+    Type,
+    Modified,
+    Revision,
+}
+
+/// A sort key, descending when written with a leading `-`, as in `-modified`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GraphSort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl Default for GraphSort {
+    fn default() -> GraphSort {
+        GraphSort { key: SortKey::Id, descending: false }
+    }
+}
+
+impl<'a> TryFrom<&'a str> for GraphSort {
+    type Error = &'a str;
+
+    fn try_from(val: &'a str) -> Result<GraphSort, Self::Error> {
+        let (name, descending) = match val.strip_prefix('-') {
+            Some(name) => (name, true),
+            None => (val, false),
+        };
+        let c = UniCase::new(name);
+        match SortKey::iter().find(|k| UniCase::new(k.as_ref()) == c) {
+            Some(key) => Ok(GraphSort { key, descending }),
+            None => Err(val),
+        }
+    }
+}
+
+/// Which graphs to list, and which page of them.
+#[derive(Debug, Default)]
+pub struct GraphQuery {
+    /// Part of the graph's URI, or its alias.
+    pub q: Option<String>,
+    pub modified_since: Option<DateTime<Utc>>,
+    pub sort: GraphSort,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct GraphPage {
+    pub graphs: Vec<GraphData>,
+    /// How many graphs there are on all pages.
+    pub total: usize,
+    /// The cursor of the next page, if there is one.
+    pub next: Option<String>,
+}
+
+/// A `modified-since` time, either RFC 3339 or a date, taken as its start in UTC.
+pub fn parse_time(val: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(val)
+        .map(|t| t.with_timezone(&Utc))
+        .ok()
+        .or_else(|| NaiveDate::parse_from_str(val, "%Y-%m-%d").ok().map(|d| DateTime::from_utc(d.and_hms(0, 0, 0), Utc)))
+}
+
+/// The page of `graphs` that `query` asks for.
+pub fn page(graphs: Vec<GraphData>, query: &GraphQuery) -> Result<GraphPage, String> {
+    let mut graphs: Vec<GraphData> = graphs.into_iter().filter(|g| matches(g, query)).collect();
+    graphs.sort_by(|a, b| compare(&sort_key(a, query.sort.key), &sort_key(b, query.sort.key), query.sort.descending));
+    let total = graphs.len();
+
+    let start = match &query.cursor {
+        Some(cursor) => {
+            let after = decode_cursor(cursor)?;
+            graphs.iter().position(|g| compare(&sort_key(g, query.sort.key), &after, query.sort.descending) == Ordering::Greater).unwrap_or(total)
+        }
+        None => query.offset.unwrap_or(0).min(total),
+    };
+    let end = query.limit.map_or(total, |limit| start.saturating_add(limit.min(MAX_PAGE_SIZE)).min(total));
+    let next = if end < total && end > start { Some(encode_cursor(&sort_key(&graphs[end - 1], query.sort.key))) } else { None };
+
+    Ok(GraphPage { graphs: graphs.drain(start..end).collect(), total, next })
+}
+
+fn matches(graph: &GraphData, query: &GraphQuery) -> bool {
+    let q_matches = query.q.as_ref().map_or(true, |q| {
+        let q = q.to_lowercase();
+        graph.id.to_lowercase().contains(&q) || graph.alias.as_ref().map_or(false, |a| a.contains(&q))
+    });
+    let modified_matches = query.modified_since.map_or(true, |since| {
+        graph.modified.as_deref().and_then(parse_time).map_or(false, |modified| modified >= since)
+    });
+    q_matches && modified_matches
+}
+
+/// The value `graph` is sorted by, and its id to break ties.
+fn sort_key(graph: &GraphData, key: SortKey) -> (String, String) {
+    let value = match key {
+        SortKey::Id => String::new(),
+        SortKey::Type => graph.graph_type.as_ref().to_owned(),
+        SortKey::Modified => graph.modified.clone().unwrap_or_default(),
+        SortKey::Revision => format!("{:020}", graph.revision.unwrap_or(0)),
+    };
+    (value, graph.id.clone())
+}
+
+fn compare(a: &(String, String), b: &(String, String), descending: bool) -> Ordering {
+    if descending { b.cmp(a) } else { a.cmp(b) }
+}
+
+fn encode_cursor((value, id): &(String, String)) -> String {
+    base64::encode_config(format!("{}\n{}", value, id), base64::URL_SAFE_NO_PAD)
+}
+
+fn decode_cursor(cursor: &str) -> Result<(String, String), String> {
+    let invalid = || format!("{} is not a cursor", cursor);
+    let decoded = base64::decode_config(cursor, base64::URL_SAFE_NO_PAD).map_err(|_| invalid())?;
+    let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+    let mut parts = decoded.splitn(2, '\n');
+    match (parts.next(), parts.next()) {
+        (Some(value), Some(id)) => Ok((value.to_owned(), id.to_owned())),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::GraphType;
+
+    fn graph(id: &str, graph_type: GraphType, modified: &str) -> GraphData {
+        GraphData {
+            id: format!("<http://example.com/{}>", id),
+            graph_type,
+            revision: Some(1),
+            modified: Some(modified.to_owned()),
+            content_hash: None,
+            alias: None,
+            path: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_page() {
+        let graphs = || vec![
+            graph("c", GraphType::Model, "2021-03-01T00:00:00Z"),
+            graph("a", GraphType::Ontology, "2021-01-01T00:00:00Z"),
+            graph("b", GraphType::Model, "2021-02-01T00:00:00Z"),
+        ];
+        let ids = |page: &GraphPage| page.graphs.iter().map(|g| g.id.clone()).collect::<Vec<_>>();
+
+        let first = page(graphs(), &GraphQuery { limit: Some(2), ..GraphQuery::default() }).unwrap();
+        assert_eq!(vec!["<http://example.com/a>", "<http://example.com/b>"], ids(&first));
+        assert_eq!(3, first.total);
+        let second = page(graphs(), &GraphQuery { limit: Some(2), cursor: first.next, ..GraphQuery::default() }).unwrap();
+        assert_eq!(vec!["<http://example.com/c>"], ids(&second));
+        assert_eq!(None, second.next);
+
+        let newest = GraphQuery { sort: GraphSort::try_from("-modified").unwrap(), offset: Some(1), ..GraphQuery::default() };
+        assert_eq!(vec!["<http://example.com/b>", "<http://example.com/a>"], ids(&page(graphs(), &newest).unwrap()));
+
+        let recent = GraphQuery { modified_since: parse_time("2021-02-01"), q: Some("EXAMPLE.com/C".to_owned()), ..GraphQuery::default() };
+        assert_eq!(vec!["<http://example.com/c>"], ids(&page(graphs(), &recent).unwrap()));
+
+        assert!(page(graphs(), &GraphQuery { cursor: Some("?".to_owned()), ..GraphQuery::default() }).is_err());
+
+        let huge = GraphQuery { limit: Some(usize::MAX), offset: Some(usize::MAX), ..GraphQuery::default() };
+        assert!(page(graphs(), &huge).unwrap().graphs.is_empty());
+        let unlimited = page(graphs(), &GraphQuery { limit: Some(usize::MAX), offset: Some(1), ..GraphQuery::default() }).unwrap();
+        assert_eq!(2, unlimited.graphs.len());
+        assert_eq!(None, unlimited.next);
+    }
+}
@@ -1,9 +1,40 @@
 mod graph;
+pub mod adopt;
+pub mod alias;
+pub mod auth;
+pub mod backup;
+pub mod browse;
+pub mod autocomplete;
+pub mod canon;
+pub mod diff;
+pub mod fragments;
+pub mod fsck;
+pub mod gsp;
+pub mod history;
+pub mod imports;
+pub mod jsonld;
+pub mod limits;
+pub mod listing;
 pub mod model;
+pub mod ontology;
+pub mod provenance;
+pub mod resource;
+pub mod search;
+pub mod service;
+pub mod shacl;
+pub mod sparql;
+pub mod star;
 pub mod storage;
+pub mod term;
+pub mod vocab;
+pub mod void;
 
 pub use graph::GraphType;
 pub use graph::KnownGraphType;
 pub use graph::GraphData;
 pub use graph::GraphList;
 pub use graph::UriWrapper;
+pub use graph::BaseUrl;
+pub use graph::ExportFormat;
+pub use graph::CONTEXT;
+pub use graph::unbracket;
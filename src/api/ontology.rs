@@ -0,0 +1,271 @@
+//! The single ontology graph, `<O>`.
+//!
+//! Ontology uploads in merge mode don't become graphs of their own. Each upload is a *source*:
+//! its triples are kept in a named graph under the source's URI, registered in `<Meta>` as a
+//! `meta:OntologySource` that is `meta:mergedInto <O>`, and `<O>` holds the union of all the
+//! sources. The source graphs are the record of which upload contributed which triples, so a
+//! source can be replaced or taken out of `<O>` again without disturbing triples that other
+//! sources also contributed.
+
+use std::collections::HashSet;
+
+use oxigraph::SledStore as Store;
+use oxigraph::model::{NamedNode, NamedNodeRef, NamedOrBlankNode, Quad, Triple};
+use oxigraph::model::vocab::rdf;
+
+use serde::Serialize;
+
+use crate::api::GraphType;
+use crate::api::canon;
+use crate::api::history;
+use crate::api::provenance::{self, Origin};
+use crate::api::storage::{apply_changes, content_hash_entry, graph_triples};
+use crate::meta;
+
+/// What a merge or removal did to `<O>`.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct MergeReport {
+    pub source: String,
+    pub ontology: String,
+    pub added: usize,
+    pub removed: usize,
+}
+
+/// The sources merged into `<O>`, from `<Meta>`.
+pub fn sources(store: &Store) -> Vec<NamedNode> {
+    let source_type = NamedNode::from(meta::OntologySource);
+    store.quads_for_pattern(None, Some(rdf::TYPE), Some(source_type.as_ref().into()), Some(meta::meta_graph_uri()))
+        .filter_map(Result::ok)
+        .filter_map(|q| match q.subject {
+            NamedOrBlankNode::NamedNode(n) => Some(n),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Merges `triples` into `<O>` as the contents of `source`. If `source` was merged before, this
+/// replaces it: triples only the old version contributed are taken out of `<O>`. With an
+/// `origin`, the load of the source and of `<O>` are recorded in `<Provenance>` in the same
+/// transaction.
+pub fn merge_source(store: &Store, source: &NamedNode, triples: Vec<Triple>, origin: Option<&Origin>) -> Result<MergeReport, String> {
+    let locks = lock(source);
+    let old = graph_triples(store, source.as_ref());
+    // Blank nodes are renamed on every parse, so the same axioms would otherwise come back as
+    // all their blank node triples removed and added again
+    let new: HashSet<Triple> = canon::align_blank_nodes(&old, &triples)?.into_iter().collect();
+    let old: HashSet<Triple> = old.into_iter().collect();
+
+    let loaded = new.len();
+    let mut changes = write_source(store, &locks, &old, &new);
+    if let Some(origin) = origin {
+        let loads = [
+            (&locks.1, GraphType::OntologySource, loaded, 0),
+            (&locks.0, GraphType::Ontology, changes.report.added, changes.report.removed),
+        ];
+        for &(lock, graph_type, added, removed) in loads.iter() {
+            let revision = changes.revisions.iter().find(|(graph, _)| graph == lock.graph()).map(|(_, revision)| revision.clone())
+                .or_else(|| history::current_revision(store, lock.graph().as_ref()));
+            changes.insert.extend(provenance::load_record(store, lock.graph(), graph_type, origin, added, removed, revision));
+        }
+    }
+    let ontology = NamedNode::from(meta::ontology_graph_uri());
+    changes.insert.push(meta::graph_metadata_entry(ontology.clone(), GraphType::Ontology));
+    changes.insert.push(meta::graph_metadata_entry(source.clone(), GraphType::OntologySource));
+    changes.insert.push(Quad::new(source.clone(), NamedNode::from(meta::mergedInto), ontology, meta::meta_graph_uri()));
+
+    apply_changes(store, &changes.remove, &changes.insert)?;
+    Ok(changes.report)
+}
+
+/// Takes `source` out of `<O>`, keeping the triples other sources also contributed, and removes
+/// the source graph and its `<Meta>` entry. `None` if `source` was never merged.
+pub fn remove_source(store: &Store, source: &NamedNode) -> Option<Result<MergeReport, String>> {
+    if !sources(store).contains(source) {
+        return None;
+    }
+
+    let locks = lock(source);
+    let old: HashSet<Triple> = graph_triples(store, source.as_ref()).into_iter().collect();
+    let mut changes = write_source(store, &locks, &old, &HashSet::new());
+
+    // Nothing of the source is left but its history
+    let source_subject = NamedOrBlankNode::from(source.clone());
+    changes.remove.extend(
+        store.quads_for_pattern(Some(source.as_ref().into()), None, None, Some(meta::meta_graph_uri()))
+            .filter_map(Result::ok));
+    changes.insert.retain(|q| !(q.subject == source_subject && q.graph_name.as_ref() == meta::meta_graph_uri()));
+
+    Some(apply_changes(store, &changes.remove, &changes.insert).map(|_| changes.report))
+}
+
+/// The write locks on `<O>` and on `source`, always taken in that order.
+fn lock(source: &NamedNode) -> (history::WriteLock, history::WriteLock) {
+    let ontology = history::lock(&NamedNode::from(meta::ontology_graph_uri()));
+    (ontology, history::lock(source))
+}
+
+struct SourceChanges {
+    report: MergeReport,
+    /// The revisions the changes make, of the graphs that change.
+    revisions: Vec<(NamedNode, history::Revision)>,
+    insert: Vec<Quad>,
+    remove: Vec<Quad>,
+}
+
+/// The changes to the source graph and to `<O>`, with their revisions and content hashes, for
+/// the source going from `old` to `new`.
+fn write_source(store: &Store, locks: &(history::WriteLock, history::WriteLock), old: &HashSet<Triple>, new: &HashSet<Triple>) -> SourceChanges {
+    let (ontology_lock, source_lock) = locks;
+    let source = source_lock.graph();
+    let ontology = NamedNode::from(meta::ontology_graph_uri());
+    let ontology_now: HashSet<Triple> = graph_triples(store, ontology.as_ref()).into_iter().collect();
+    let others: HashSet<Triple> = sources(store)
+        .into_iter()
+        .filter(|s| s != source)
+        .flat_map(|s| graph_triples(store, s.as_ref()))
+        .collect();
+
+    let ontology_removed: HashSet<Triple> = old.iter()
+        .filter(|t| !new.contains(t) && !others.contains(t) && ontology_now.contains(t))
+        .cloned()
+        .collect();
+    let ontology_added: Vec<Triple> = new.iter()
+        .filter(|t| !ontology_now.contains(t))
+        .cloned()
+        .collect();
+    let source_removed: Vec<Triple> = old.difference(new).cloned().collect();
+    let source_added: Vec<Triple> = new.difference(old).cloned().collect();
+
+    let mut insert = Vec::new();
+    let mut remove = Vec::new();
+    let mut revisions = Vec::new();
+
+    let ontology_after: Vec<Triple> = ontology_now.iter()
+        .filter(|t| !ontology_removed.contains(t))
+        .chain(ontology_added.iter())
+        .cloned()
+        .collect();
+    let ontology_removed: Vec<Triple> = ontology_removed.into_iter().collect();
+    let source_after: Vec<Triple> = new.iter().cloned().collect();
+
+    for (lock, added, removed, after) in vec![
+        (ontology_lock, &ontology_added, &ontology_removed, &ontology_after),
+        (source_lock, &source_added, &source_removed, &source_after),
+    ] {
+        let graph = lock.graph();
+        if added.is_empty() && removed.is_empty() && history::current_revision(store, graph.as_ref()).is_some() {
+            continue;
+        }
+        let revision = history::prepare_write(store, lock, added, removed);
+        let (hash, stale_hash) = content_hash_entry(store, graph, after);
+
+        remove.extend(removed.iter().map(|t| t.clone().in_graph(graph.clone())));
+        remove.extend(revision.remove);
+        remove.extend(stale_hash);
+        insert.extend(added.iter().map(|t| t.clone().in_graph(graph.clone())));
+        insert.extend(revision.insert);
+        insert.extend(hash);
+        revisions.push((graph.clone(), revision.revision));
+    }
+
+    SourceChanges {
+        report: MergeReport {
+            source: source.to_string(),
+            ontology: ontology.to_string(),
+            added: ontology_added.len(),
+            removed: ontology_removed.len(),
+        },
+        revisions,
+        insert,
+        remove,
+    }
+}
+
+/// Whether `graph` is `<O>` itself, which is only ever written by merging sources.
+pub fn is_ontology_graph(graph: NamedNodeRef) -> bool {
+    graph == meta::ontology_graph_uri()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::storage::parse_turtle;
+    use crate::api::storage::test::init_store;
+
+    fn triple(s: &str, o: &str) -> Triple {
+        Triple::new(
+            NamedNode::new_unchecked(format!("http://example.com/{}", s)),
+            NamedNode::new_unchecked("http://www.w3.org/2000/01/rdf-schema#subClassOf"),
+            NamedNode::new_unchecked(format!("http://example.com/{}", o)))
+    }
+
+    fn ontology_contents(store: &Store) -> HashSet<Triple> {
+        graph_triples(store, meta::ontology_graph_uri()).into_iter().collect()
+    }
+
+    #[test]
+    fn test_merge_and_remove_sources() {
+        let (s, _f) = init_store();
+        let a = NamedNode::new_unchecked("http://example.com/a.owl");
+        let b = NamedNode::new_unchecked("http://example.com/b.owl");
+
+        let report = merge_source(&s, &a, vec![triple("A", "Thing"), triple("Shared", "Thing")], None).unwrap();
+        assert_eq!(2, report.added);
+        merge_source(&s, &b, vec![triple("B", "Thing"), triple("Shared", "Thing")], None).unwrap();
+
+        assert_eq!(3, ontology_contents(&s).len());
+        assert_eq!(2, sources(&s).len());
+
+        let removed = remove_source(&s, &a).unwrap().unwrap();
+        assert_eq!(1, removed.removed);
+
+        let expected: HashSet<Triple> = vec![triple("B", "Thing"), triple("Shared", "Thing")].into_iter().collect();
+        assert_eq!(expected, ontology_contents(&s));
+        assert_eq!(vec![b.clone()], sources(&s));
+        assert!(graph_triples(&s, a.as_ref()).is_empty());
+        assert!(remove_source(&s, &a).is_none());
+    }
+
+    #[test]
+    fn test_replace_source() {
+        let (s, _f) = init_store();
+        let a = NamedNode::new_unchecked("http://example.com/a.owl");
+
+        merge_source(&s, &a, vec![triple("A", "Thing"), triple("Old", "Thing")], None).unwrap();
+        let report = merge_source(&s, &a, vec![triple("A", "Thing"), triple("New", "Thing")], None).unwrap();
+
+        assert_eq!(1, report.added);
+        assert_eq!(1, report.removed);
+        let expected: HashSet<Triple> = vec![triple("A", "Thing"), triple("New", "Thing")].into_iter().collect();
+        assert_eq!(expected, ontology_contents(&s));
+        assert_eq!(Some(2), history::current_revision(&s, meta::ontology_graph_uri()).map(|r| r.number));
+    }
+
+    #[test]
+    fn test_remerge_keeps_blank_nodes() {
+        let (s, _f) = init_store();
+        let a = NamedNode::new_unchecked("http://example.com/a.owl");
+        let axiom = br#"
+            <http://example.com/Hand> <http://www.w3.org/2000/01/rdf-schema#subClassOf> [
+                <http://www.w3.org/2002/07/owl#onProperty> <http://example.com/part_of> ;
+                <http://www.w3.org/2002/07/owl#someValuesFrom> <http://example.com/Arm>
+            ] .
+        "#;
+
+        merge_source(&s, &a, parse_turtle(axiom).unwrap(), None).unwrap();
+        let merged = ontology_contents(&s);
+
+        // Every parse names the blank node afresh
+        let report = merge_source(&s, &a, parse_turtle(axiom).unwrap(), None).unwrap();
+        assert_eq!((0, 0), (report.added, report.removed));
+        assert_eq!(merged, ontology_contents(&s));
+        assert_eq!(Some(1), history::current_revision(&s, meta::ontology_graph_uri()).map(|r| r.number));
+        assert_eq!(Some(1), history::current_revision(&s, a.as_ref()).map(|r| r.number));
+
+        let mut changed = parse_turtle(axiom).unwrap();
+        changed.push(triple("Arm", "Limb"));
+        let report = merge_source(&s, &a, changed, None).unwrap();
+        assert_eq!((1, 0), (report.added, report.removed));
+        assert_eq!(merged.len() + 1, ontology_contents(&s).len());
+    }
+}
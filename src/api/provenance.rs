@@ -0,0 +1,240 @@
+//! PROV-O provenance for every load into a Meta-registered graph.
+//!
+//! Each load is recorded in `<Provenance>` as an activity that generated the graph:
+//! ```turtle
+//! <G> prov:wasGeneratedBy _:load .
+//! _:load a prov:Activity ;
+//!     prov:generated <G> ;
+//!     prov:startedAtTime "2021-02-14T12:00:00Z"^^xsd:dateTime ;
+//!     prov:endedAtTime "2021-02-14T12:00:01Z"^^xsd:dateTime ;
+//!     prov:used [ a prov:Entity ; prov:atLocation "go.ttl" ] ;
+//!     prov:wasAssociatedWith [ a prov:Agent ; rdfs:label "alice" ] ;
+//!     meta:claimedUploader "curator@example.com" ;
+//!     meta:revisionNumber 2 ;
+//!     meta:tripleCount 1200 ;
+//!     meta:format "text/turtle" .
+//! ```
+//! Inferred and Closure graphs also record the `meta:reasonerProfile` that produced them, and
+//! use the revisions their input graphs were at: a Model for an Inferred graph, the Ontology
+//! graphs and everything they import for a Closure.
+//!
+//! The graph itself points at `<Provenance>` from `<Meta>` with `meta:hasProvenanceAt`.
+
+use chrono::{SecondsFormat, Utc};
+
+use oxigraph::SledStore as Store;
+use oxigraph::model::{BlankNode, GraphNameRef, Literal, NamedNode, NamedNodeRef, NamedOrBlankNode, Quad, Term, Triple};
+use oxigraph::model::vocab::{rdf, rdfs, xsd};
+
+use crate::api::GraphType;
+use crate::api::history::{self, Revision};
+use crate::api::imports;
+use crate::api::vocab::prov;
+use crate::meta;
+
+/// Where the data of a load came from, as far as the client told us.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Origin {
+    /// A file name or URL.
+    pub source: Option<String>,
+    /// The authenticated user that made the load.
+    pub user: Option<String>,
+    /// Who the client says the uploader is, unverified.
+    pub uploader: Option<String>,
+    /// The media type the data was parsed as.
+    pub format: Option<String>,
+    /// For Inferred and Closure graphs, the reasoner or profile that produced them.
+    pub reasoner: Option<String>,
+}
+
+/// The quads recording a load that added `added` triples to `graph`, and took out `removed`, as a
+/// new activity in `<Provenance>`, with `revision` the revision the load makes. They go in the
+/// same transaction as the load, so that a load never commits without its provenance.
+pub fn load_record(store: &Store, graph: &NamedNode, graph_type: GraphType, origin: &Origin, added: usize, removed: usize, revision: Option<Revision>) -> Vec<Quad> {
+    let provenance = meta::provenance_graph_uri();
+    let activity = BlankNode::default();
+    let ended = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    let started = revision.as_ref().map_or_else(|| ended.clone(), |r| r.timestamp.clone());
+
+    let mut quads = vec![
+        Quad::new(graph.clone(), prov::WAS_GENERATED_BY, activity.clone(), provenance),
+        Quad::new(activity.clone(), rdf::TYPE, prov::ACTIVITY, provenance),
+        Quad::new(activity.clone(), prov::GENERATED, graph.clone(), provenance),
+        Quad::new(activity.clone(), prov::STARTED_AT_TIME, Literal::new_typed_literal(started, xsd::DATE_TIME), provenance),
+        Quad::new(activity.clone(), prov::ENDED_AT_TIME, Literal::new_typed_literal(ended, xsd::DATE_TIME), provenance),
+        Quad::new(activity.clone(), NamedNode::from(meta::tripleCount), integer(added as u64), provenance),
+        Quad::new(graph.clone(), NamedNode::from(meta::hasProvenanceAt), provenance_graph(), meta::meta_graph_uri()),
+    ];
+    if removed > 0 {
+        quads.push(Quad::new(activity.clone(), NamedNode::from(meta::triplesRemoved), integer(removed as u64), provenance));
+    }
+    if let Some(revision) = revision {
+        quads.push(Quad::new(activity.clone(), NamedNode::from(meta::revisionNumber), integer(revision.number), provenance));
+    }
+    if let Some(format) = &origin.format {
+        quads.push(Quad::new(activity.clone(), NamedNode::from(meta::format), Literal::new_simple_literal(format), provenance));
+    }
+
+    if let Some(source) = &origin.source {
+        match NamedNode::new(source.as_str()) {
+            Ok(url) => quads.push(Quad::new(activity.clone(), prov::USED, url, provenance)),
+            Err(_) => {
+                let entity = BlankNode::default();
+                quads.push(Quad::new(activity.clone(), prov::USED, entity.clone(), provenance));
+                quads.push(Quad::new(entity.clone(), rdf::TYPE, prov::ENTITY, provenance));
+                quads.push(Quad::new(entity, prov::AT_LOCATION, Literal::new_simple_literal(source), provenance));
+            }
+        }
+    }
+    if let Some(user) = &origin.user {
+        let agent = BlankNode::default();
+        quads.push(Quad::new(activity.clone(), prov::WAS_ASSOCIATED_WITH, agent.clone(), provenance));
+        quads.push(Quad::new(agent.clone(), rdf::TYPE, prov::AGENT, provenance));
+        quads.push(Quad::new(agent, rdfs::LABEL, Literal::new_simple_literal(user), provenance));
+    }
+    if let Some(uploader) = &origin.uploader {
+        quads.push(Quad::new(activity.clone(), NamedNode::from(meta::claimedUploader), Literal::new_simple_literal(uploader), provenance));
+    }
+
+    if graph_type == GraphType::Inferred || graph_type == GraphType::Closure {
+        if let Some(reasoner) = &origin.reasoner {
+            quads.push(Quad::new(activity.clone(), NamedNode::from(meta::reasonerProfile), Literal::new_simple_literal(reasoner), provenance));
+        }
+        for input in input_graphs(store, graph, graph_type) {
+            let entity = BlankNode::default();
+            quads.push(Quad::new(activity.clone(), prov::USED, entity.clone(), provenance));
+            quads.push(Quad::new(entity.clone(), rdf::TYPE, prov::ENTITY, provenance));
+            quads.push(Quad::new(entity.clone(), prov::SPECIALIZATION_OF, input.clone(), provenance));
+            if let Some(revision) = history::current_revision(store, input.as_ref()) {
+                quads.push(Quad::new(entity, NamedNode::from(meta::revisionNumber), integer(revision.number), provenance));
+            }
+        }
+    }
+
+    quads
+}
+
+/// The graphs an Inferred or Closure graph is worked out from: the Model an Inferred graph is
+/// `meta:inferredFrom` (or that `meta:hasInferencesAt` it), or all the Ontology graphs and their
+/// imports for a Closure.
+pub fn input_graphs(store: &Store, graph: &NamedNode, graph_type: GraphType) -> Vec<NamedNode> {
+    match graph_type {
+        GraphType::Closure => imports::ontology_closure(store),
+        GraphType::Inferred => {
+            let inferred_from = NamedNode::from(meta::inferredFrom);
+            let has_inferences_at = NamedNode::from(meta::hasInferencesAt);
+            let mut inputs: Vec<NamedNode> = store
+                .quads_for_pattern(Some(graph.as_ref().into()), Some(inferred_from.as_ref()), None, Some(meta::meta_graph_uri()))
+                .filter_map(Result::ok)
+                .filter_map(|q| match q.object {
+                    Term::NamedNode(n) => Some(n),
+                    _ => None,
+                })
+                .collect();
+            for quad in store.quads_for_pattern(None, Some(has_inferences_at.as_ref()), Some(graph.as_ref().into()), Some(meta::meta_graph_uri())).filter_map(Result::ok) {
+                if let NamedOrBlankNode::NamedNode(model) = quad.subject {
+                    if !inputs.contains(&model) {
+                        inputs.push(model);
+                    }
+                }
+            }
+            inputs
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Every load recorded for `graph`: the activities that generated it, with everything hanging
+/// off them.
+pub fn provenance_of(store: &Store, graph: NamedNodeRef) -> Vec<Triple> {
+    let provenance = meta::provenance_graph_uri();
+    let mut triples = Vec::new();
+    let mut todo: Vec<NamedOrBlankNode> = Vec::new();
+
+    for quad in store.quads_for_pattern(Some(graph.into()), Some(prov::WAS_GENERATED_BY), None, Some(provenance)).filter_map(Result::ok) {
+        if let Term::BlankNode(activity) = &quad.object {
+            todo.push(activity.clone().into());
+        }
+        triples.push(Triple::from(quad));
+    }
+
+    while let Some(node) = todo.pop() {
+        for quad in store.quads_for_pattern(Some(node.as_ref()), None, None, Some(provenance)).filter_map(Result::ok) {
+            if let Term::BlankNode(b) = &quad.object {
+                todo.push(b.clone().into());
+            }
+            triples.push(Triple::from(quad));
+        }
+    }
+    triples
+}
+
+fn provenance_graph() -> NamedNode {
+    match meta::provenance_graph_uri() {
+        GraphNameRef::NamedNode(n) => n.into_owned(),
+        _ => unreachable!("the provenance graph is a named graph"),
+    }
+}
+
+fn integer(value: u64) -> Literal {
+    Literal::new_typed_literal(value.to_string(), xsd::INTEGER)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::storage::{load_new_graph, load_triples_into_new_graph};
+    use crate::api::storage::test::init_store;
+
+    fn triple(s: &str) -> Triple {
+        Triple::new(
+            NamedNode::new_unchecked(format!("http://example.com/{}", s)),
+            rdfs::SUB_CLASS_OF,
+            NamedNode::new_unchecked("http://example.com/Thing"))
+    }
+
+    fn objects(triples: &[Triple], predicate: NamedNodeRef) -> Vec<Term> {
+        triples.iter().filter(|t| t.predicate.as_ref() == predicate).map(|t| t.object.clone()).collect()
+    }
+
+    #[test]
+    fn test_record_load() {
+        let (s, _f) = init_store();
+        let graph = NamedNode::new_unchecked("http://example.com/ont");
+        let origin = Origin {
+            source: Some("ont.ttl".into()),
+            user: Some("alice".into()),
+            uploader: Some("curator@example.com".into()),
+            format: Some("text/turtle".into()),
+            reasoner: None,
+        };
+        assert_eq!(Ok(2), load_new_graph(&s, graph.clone(), GraphType::Ontology, vec![triple("A"), triple("B")], Some(&origin)));
+
+        let triples = provenance_of(&s, graph.as_ref());
+        assert_eq!(vec![Term::from(integer(2))], objects(&triples, NamedNode::from(meta::tripleCount).as_ref()));
+        assert_eq!(vec![Term::from(integer(1))], objects(&triples, NamedNode::from(meta::revisionNumber).as_ref()));
+        assert_eq!(vec![Term::from(Literal::new_simple_literal("ont.ttl"))], objects(&triples, prov::AT_LOCATION));
+        assert_eq!(vec![Term::from(Literal::new_simple_literal("alice"))], objects(&triples, rdfs::LABEL));
+        assert_eq!(vec![Term::from(Literal::new_simple_literal("curator@example.com"))], objects(&triples, NamedNode::from(meta::claimedUploader).as_ref()));
+        assert!(objects(&triples, NamedNode::from(meta::reasonerProfile).as_ref()).is_empty());
+
+        let link = Quad::new(graph.clone(), NamedNode::from(meta::hasProvenanceAt), provenance_graph(), meta::meta_graph_uri());
+        assert!(s.contains(link.as_ref()).unwrap());
+    }
+
+    #[test]
+    fn test_closure_records_inputs() {
+        let (s, _f) = init_store();
+        let ontology = NamedNode::new_unchecked("http://example.com/ont");
+        let closure = NamedNode::new_unchecked("http://example.com/ont-closure");
+        load_triples_into_new_graph(&s, ontology.clone(), GraphType::Ontology, vec![triple("A")]);
+
+        let origin = Origin { reasoner: Some("relation-graph".into()), ..Origin::default() };
+        load_new_graph(&s, closure.clone(), GraphType::Closure, vec![triple("A")], Some(&origin)).unwrap();
+
+        let triples = provenance_of(&s, closure.as_ref());
+        assert!(objects(&triples, prov::WAS_ASSOCIATED_WITH).is_empty());
+        assert_eq!(vec![Term::from(Literal::new_simple_literal("relation-graph"))], objects(&triples, NamedNode::from(meta::reasonerProfile).as_ref()));
+        assert_eq!(vec![Term::from(ontology)], objects(&triples, prov::SPECIALIZATION_OF));
+    }
+}
@@ -0,0 +1,125 @@
+//! Linked Data descriptions of resources, so the IRIs in our graphs can be dereferenced.
+//!
+//! A resource is described by its concise bounded description in each graph that mentions it as
+//! a subject: the triples about it, and about any blank nodes those lead to. The labels of the
+//! IRIs it links to come along too, so that the description can be read without looking each of
+//! them up.
+
+use std::collections::HashSet;
+
+use oxigraph::SledStore as Store;
+use oxigraph::model::{BlankNode, NamedNode, NamedOrBlankNode, Term, Triple};
+use oxigraph::model::vocab::rdfs;
+
+use crate::api::alias::percent_encode;
+
+/// How a resource is described, chosen by content negotiation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResourceFormat {
+    Html,
+    Turtle,
+    JsonLd,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Description {
+    pub resource: NamedNode,
+    pub triples: Vec<Triple>,
+    /// The graphs the resource is described in.
+    pub graphs: Vec<NamedNode>,
+}
+
+/// The concise bounded description of `resource` in `graphs`, with the labels of the IRIs it
+/// links to, or `None` if none of them mention it as a subject.
+pub fn describe(store: &Store, resource: &NamedNode, graphs: &[NamedNode]) -> Option<Description> {
+    let mut triples = Vec::new();
+    let mut seen = HashSet::new();
+    let mut described_in = Vec::new();
+
+    for graph in graphs {
+        let mut found = false;
+        let mut pending = vec![NamedOrBlankNode::from(resource.clone())];
+        let mut visited: HashSet<BlankNode> = HashSet::new();
+        while let Some(subject) = pending.pop() {
+            for quad in store.quads_for_pattern(Some(subject.as_ref()), None, None, Some(graph.as_ref().into())).filter_map(Result::ok) {
+                found = true;
+                if let Term::BlankNode(b) = &quad.object {
+                    if visited.insert(b.clone()) {
+                        pending.push(b.clone().into());
+                    }
+                }
+                let triple = Triple::from(quad);
+                if seen.insert(triple.clone()) {
+                    triples.push(triple);
+                }
+            }
+        }
+        if found {
+            described_in.push(graph.clone());
+        }
+    }
+    if described_in.is_empty() {
+        return None;
+    }
+
+    let linked: Vec<NamedNode> = triples.iter()
+        .filter_map(|t| match &t.object {
+            Term::NamedNode(n) if n != resource => Some(n.clone()),
+            _ => None,
+        })
+        .collect();
+    for object in linked {
+        for graph in graphs {
+            for quad in store.quads_for_pattern(Some(object.as_ref().into()), Some(rdfs::LABEL), None, Some(graph.as_ref().into())).filter_map(Result::ok) {
+                let triple = Triple::from(quad);
+                if seen.insert(triple.clone()) {
+                    triples.push(triple);
+                }
+            }
+        }
+    }
+
+    Some(Description { resource: resource.clone(), triples, graphs: described_in })
+}
+
+/// The path `resource` is described at.
+pub fn resource_path(resource: &NamedNode) -> String {
+    format!("/resource/{}", percent_encode(&resource.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use oxigraph::model::Literal;
+    use crate::api::GraphType;
+    use crate::api::storage::load_triples_into_new_graph;
+    use crate::api::storage::test::init_store;
+
+    #[test]
+    fn test_describe() {
+        let (s, _f) = init_store();
+        let ex = |local: &str| NamedNode::new_unchecked(format!("http://example.com/{}", local));
+        let address = BlankNode::new_unchecked("address");
+        let people = ex("people");
+        let places = ex("places");
+        load_triples_into_new_graph(&s, people.clone(), GraphType::Model, vec![
+            Triple::new(ex("alice"), ex("livesIn"), ex("paris")),
+            Triple::new(ex("alice"), ex("address"), address.clone()),
+            Triple::new(address.clone(), ex("street"), Literal::new_simple_literal("Rue <Cler>")),
+            Triple::new(ex("bob"), ex("knows"), ex("alice")),
+        ]);
+        load_triples_into_new_graph(&s, places.clone(), GraphType::Model, vec![
+            Triple::new(ex("paris"), rdfs::LABEL, Literal::new_simple_literal("Paris")),
+            Triple::new(ex("paris"), ex("in"), ex("france")),
+        ]);
+
+        let description = describe(&s, &ex("alice"), &[people.clone(), places.clone()]).unwrap();
+        assert_eq!(4, description.triples.len());
+        assert_eq!(vec![people.clone()], description.graphs);
+        assert!(description.triples.contains(&Triple::new(ex("paris"), rdfs::LABEL, Literal::new_simple_literal("Paris"))));
+        assert!(!description.triples.iter().any(|t| t.subject == NamedOrBlankNode::from(ex("bob"))));
+
+        assert_eq!(None, describe(&s, &ex("alice"), &[places]));
+        assert_eq!(None, describe(&s, &ex("nobody"), &[people]));
+    }
+}
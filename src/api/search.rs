@@ -0,0 +1,395 @@
+//! Full-text search over the literal values of label-like predicates.
+//!
+//! The index lives in memory next to the store and follows what the storage layer records of its
+//! writes: each registered graph is indexed at its revision and content hash in `<Meta>`, and
+//! every search first re-indexes the graphs whose record has changed since and forgets those that
+//! are gone. So a graph is found however it was written, by any route, by `fsck --repair` or by a
+//! restore, without the writer having to see to the index. Ontology sources are left out, since
+//! whatever they hold is also in `<O>`.
+//!
+//! Text is split into lowercased alphanumeric tokens. A query token matches an indexed token
+//! exactly, as a prefix of it, or, for tokens of four characters or more, within a small edit
+//! distance. Every query token has to match for a value to be a hit, and hits are ranked by how
+//! well they match and by which predicate they were found in, labels first.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::RwLock;
+
+use oxigraph::SledStore as Store;
+use oxigraph::model::{NamedNode, NamedNodeRef, NamedOrBlankNode, Term};
+use oxigraph::model::vocab::rdf;
+
+use serde::Serialize;
+
+use crate::api::{unbracket, GraphType};
+use crate::api::history;
+use crate::api::storage::{accounted_graph_list, recorded_content_hash};
+
+/// The predicates whose values are indexed, with the weight a match in each counts for.
+const INDEXED_PREDICATES: [(&str, f64); 14] = [
+    ("http://www.w3.org/2000/01/rdf-schema#label", 2.0),
+    ("http://www.w3.org/2004/02/skos/core#prefLabel", 2.0),
+    ("http://purl.org/dc/terms/title", 2.0),
+    ("http://purl.org/dc/elements/1.1/title", 2.0),
+    ("http://www.w3.org/2004/02/skos/core#altLabel", 1.5),
+    ("http://www.w3.org/2004/02/skos/core#hiddenLabel", 1.5),
+    ("http://www.geneontology.org/formats/oboInOwl#hasExactSynonym", 1.5),
+    ("http://www.geneontology.org/formats/oboInOwl#hasRelatedSynonym", 1.2),
+    ("http://www.geneontology.org/formats/oboInOwl#hasBroadSynonym", 1.2),
+    ("http://www.geneontology.org/formats/oboInOwl#hasNarrowSynonym", 1.2),
+    ("http://www.w3.org/2004/02/skos/core#definition", 1.0),
+    ("http://purl.obolibrary.org/obo/IAO_0000115", 1.0),
+    ("http://www.w3.org/2000/01/rdf-schema#comment", 1.0),
+    ("http://purl.org/dc/terms/description", 1.0),
+];
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SearchHit {
+    pub id: String,
+    pub graph: String,
+    pub predicate: String,
+    pub text: String,
+    pub score: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SearchResults {
+    pub context: String,
+    pub query: String,
+    pub hits: Vec<SearchHit>,
+}
+
+/// One indexed literal: `subject predicate "text"`.
+#[derive(Debug)]
+struct Document {
+    subject: NamedNode,
+    predicate: NamedNode,
+    text: String,
+    weight: f64,
+}
+
+/// What `<Meta>` records of a graph's last write: its revision and when it was made, and its
+/// content hash.
+type Version = (Option<(u64, String)>, Option<String>);
+
+#[derive(Default)]
+struct Inner {
+    documents: HashMap<NamedNode, Vec<Document>>,
+    tokens: BTreeMap<String, HashSet<(NamedNode, usize)>>,
+    indexed: HashMap<NamedNode, Version>,
+}
+
+/// The search index, shared between requests as Rocket managed state.
+#[derive(Default)]
+pub struct SearchIndex {
+    inner: RwLock<Inner>,
+}
+
+impl SearchIndex {
+    /// Indexes every Meta-registered graph but the ontology sources.
+    pub fn build(store: &Store) -> SearchIndex {
+        let index = SearchIndex::default();
+        index.refresh(store);
+        index
+    }
+
+    /// Re-indexes the graphs written since they were indexed, and forgets the ones no longer
+    /// registered.
+    fn refresh(&self, store: &Store) {
+        let current: HashMap<NamedNode, Version> = accounted_graph_list(store).graphs.into_iter()
+            .filter(|g| g.graph_type != GraphType::OntologySource)
+            .filter_map(|g| NamedNode::new(unbracket(&g.id)).ok())
+            .map(|graph| {
+                let revision = history::current_revision(store, graph.as_ref()).map(|r| (r.number, r.timestamp));
+                let hash = recorded_content_hash(store, graph.as_ref());
+                (graph, (revision, hash))
+            })
+            .collect();
+
+        let (stale, gone): (Vec<(NamedNode, Version)>, Vec<NamedNode>) = {
+            let inner = self.inner.read().unwrap();
+            let stale = current.iter().filter(|(g, v)| inner.indexed.get(*g) != Some(*v)).map(|(g, v)| (g.clone(), v.clone())).collect();
+            let gone = inner.indexed.keys().filter(|g| !current.contains_key(*g)).cloned().collect();
+            (stale, gone)
+        };
+        for (graph, version) in stale {
+            self.index_graph(store, &graph, version);
+        }
+        if !gone.is_empty() {
+            let mut inner = self.inner.write().unwrap();
+            for graph in gone.iter() {
+                inner.remove(graph);
+            }
+        }
+    }
+
+    /// (Re-)indexes `graph` from what is in the store now, which is at `version`.
+    fn index_graph(&self, store: &Store, graph: &NamedNode, version: Version) {
+        let mut documents = Vec::new();
+        for (predicate, weight) in INDEXED_PREDICATES.iter() {
+            let predicate = NamedNodeRef::new_unchecked(predicate);
+            for quad in store.quads_for_pattern(None, Some(predicate), None, Some(graph.as_ref().into())).filter_map(Result::ok) {
+                if let (NamedOrBlankNode::NamedNode(subject), Term::Literal(text)) = (quad.subject, quad.object) {
+                    documents.push(Document {
+                        subject,
+                        predicate: quad.predicate,
+                        text: text.value().to_owned(),
+                        weight: *weight,
+                    });
+                }
+            }
+        }
+
+        let mut inner = self.inner.write().unwrap();
+        inner.remove(graph);
+        for (i, document) in documents.iter().enumerate() {
+            for token in tokenize(&document.text) {
+                inner.tokens.entry(token).or_insert_with(HashSet::new).insert((graph.clone(), i));
+            }
+        }
+        inner.documents.insert(graph.clone(), documents);
+        inner.indexed.insert(graph.clone(), version);
+    }
+
+    /// Every resource with a value matching `query`, in the `graphs` if given. This is what
+    /// `meta:textMatch` filters SPARQL solutions by.
+    pub fn matching_resources(&self, store: &Store, query: &str, graphs: Option<&HashSet<NamedNode>>) -> Vec<NamedNode> {
+        let mut ids: Vec<String> = self.search(store, query, graphs, None, usize::MAX)
+            .into_iter()
+            .map(|hit| hit.id)
+            .collect();
+        ids.sort();
+        ids.dedup();
+        ids.into_iter().map(NamedNode::new_unchecked).collect()
+    }
+
+    /// The best `limit` hits for `query`, at most one per resource and graph. Hits can be
+    /// restricted to some graphs, and to resources with a given `rdf:type` in any graph.
+    pub fn search(&self, store: &Store, query: &str, graphs: Option<&HashSet<NamedNode>>, class: Option<&NamedNode>, limit: usize) -> Vec<SearchHit> {
+        let query_tokens = tokenize(query);
+        if query_tokens.is_empty() {
+            return Vec::new();
+        }
+
+        self.refresh(store);
+        let inner = self.inner.read().unwrap();
+
+        // The best match score of each query token, for each document any of them matched
+        let mut matches: HashMap<&(NamedNode, usize), Vec<f64>> = HashMap::new();
+        for (i, query_token) in query_tokens.iter().enumerate() {
+            for (score, postings) in inner.matching_tokens(query_token) {
+                for posting in postings.iter().filter(|(g, _)| graphs.map_or(true, |graphs| graphs.contains(g))) {
+                    let scores = matches.entry(posting).or_insert_with(|| vec![0.0; query_tokens.len()]);
+                    if scores[i] < score {
+                        scores[i] = score;
+                    }
+                }
+            }
+        }
+
+        let query_text = query.trim().to_lowercase();
+        let mut best: HashMap<(&NamedNode, &NamedNode), (f64, &Document)> = HashMap::new();
+        for ((g, i), scores) in matches.into_iter().filter(|(_, scores)| scores.iter().all(|s| *s > 0.0)) {
+            let document = &inner.documents[g][*i];
+            let mut score = scores.iter().sum::<f64>() * document.weight;
+            if document.text.to_lowercase() == query_text {
+                score += 2.0 * document.weight;
+            }
+            let entry = best.entry((&document.subject, g)).or_insert((score, document));
+            if entry.0 < score {
+                *entry = (score, document);
+            }
+        }
+
+        let mut hits: Vec<SearchHit> = best.into_iter()
+            .filter(|((subject, _), _)| class.map_or(true, |class| {
+                store.quads_for_pattern(Some((*subject).as_ref().into()), Some(rdf::TYPE), Some(class.as_ref().into()), None)
+                    .next()
+                    .is_some()
+            }))
+            .map(|((subject, g), (score, document))| SearchHit {
+                id: subject.as_str().to_owned(),
+                graph: g.as_str().to_owned(),
+                predicate: document.predicate.as_str().to_owned(),
+                text: document.text.clone(),
+                score,
+            })
+            .collect();
+
+        hits.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal).then_with(|| a.id.cmp(&b.id)));
+        hits.truncate(limit);
+        hits
+    }
+}
+
+impl Inner {
+    fn remove(&mut self, graph: &NamedNode) {
+        self.indexed.remove(graph);
+        if let Some(documents) = self.documents.remove(graph) {
+            for (i, document) in documents.iter().enumerate() {
+                for token in tokenize(&document.text) {
+                    if let Some(postings) = self.tokens.get_mut(&token) {
+                        postings.remove(&(graph.clone(), i));
+                        if postings.is_empty() {
+                            self.tokens.remove(&token);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The indexed tokens `query_token` matches, with how well it matches them.
+    fn matching_tokens(&self, query_token: &str) -> Vec<(f64, &HashSet<(NamedNode, usize)>)> {
+        if query_token.chars().count() < 4 {
+            // Too short for fuzzy matching, so only prefixes count
+            return self.tokens.range(query_token.to_owned()..)
+                .take_while(|(token, _)| token.starts_with(query_token))
+                .filter_map(|(token, postings)| match_score(query_token, token).map(|s| (s, postings)))
+                .collect();
+        }
+
+        self.tokens.iter()
+            .filter_map(|(token, postings)| match_score(query_token, token).map(|s| (s, postings)))
+            .collect()
+    }
+}
+
+/// 1 for an exact match, a little less for a prefix the shorter it is, and less again for a
+/// fuzzy match the more edits it takes.
+fn match_score(query_token: &str, token: &str) -> Option<f64> {
+    if query_token == token {
+        return Some(1.0);
+    }
+    let query_length = query_token.chars().count();
+    let length = token.chars().count();
+    if token.starts_with(query_token) {
+        return Some(0.5 + 0.4 * query_length as f64 / length as f64);
+    }
+
+    let allowed = match query_length {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    };
+    if allowed == 0 || (query_length as isize - length as isize).abs() > allowed as isize {
+        return None;
+    }
+    match levenshtein(query_token, token) {
+        d if d <= allowed => Some(0.4 - 0.1 * d as f64),
+        _ => None,
+    }
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::storage::{delete_graph, load_turtle_into_new_graph, parse_turtle, rewrite_graph};
+    use crate::api::storage::test::init_store;
+
+    const ONTOLOGY: &str = r#"
+        @prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .
+        @prefix owl: <http://www.w3.org/2002/07/owl#> .
+        @prefix obo: <http://purl.obolibrary.org/obo/> .
+        @prefix oboInOwl: <http://www.geneontology.org/formats/oboInOwl#> .
+
+        obo:CL_0000540 a owl:Class ; rdfs:label "neuron" ; oboInOwl:hasExactSynonym "nerve cell" .
+        obo:CL_0000125 a owl:Class ; rdfs:label "glial cell" ; obo:IAO_0000115 "A non-neuronal cell of the nervous system." .
+        obo:GO_0007049 a owl:Class ; rdfs:label "cell cycle" .
+    "#;
+
+    fn index() -> (Store, tempfile::TempDir, SearchIndex, NamedNode) {
+        let (s, f) = init_store();
+        let graph = NamedNode::new_unchecked("http://example.com/cl");
+        load_turtle_into_new_graph(&s, graph.clone(), GraphType::Ontology, ONTOLOGY.as_bytes().to_vec());
+        let index = SearchIndex::build(&s);
+        (s, f, index, graph)
+    }
+
+    fn ids(hits: &[SearchHit]) -> Vec<&str> {
+        hits.iter().map(|h| h.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(vec!["non", "neuronal", "cell"], tokenize("Non-neuronal  cell."));
+    }
+
+    #[test]
+    fn test_match_score() {
+        let exact = match_score("cell", "cell").unwrap();
+        let prefix = match_score("neur", "neuron").unwrap();
+        let fuzzy = match_score("nuron", "neuron").unwrap();
+        assert!(exact > prefix && prefix > fuzzy && fuzzy > 0.0);
+
+        assert_eq!(None, match_score("cel", "cat"));
+        assert_eq!(None, match_score("nueron", "neuron"));
+        assert_eq!(2, levenshtein("nueron", "neuron"));
+        assert_eq!(1, levenshtein("neuron", "neurons"));
+    }
+
+    #[test]
+    fn test_search_ranks_labels_first() {
+        let (s, _f, index, _) = index();
+
+        let hits = index.search(&s, "neuron", None, None, 10);
+        assert_eq!(vec!["http://purl.obolibrary.org/obo/CL_0000540", "http://purl.obolibrary.org/obo/CL_0000125"], ids(&hits));
+
+        let hits = index.search(&s, "cell cyc", None, None, 10);
+        assert_eq!("http://purl.obolibrary.org/obo/GO_0007049", hits[0].id);
+
+        let hits = index.search(&s, "neuronn", None, None, 10);
+        assert_eq!("http://purl.obolibrary.org/obo/CL_0000540", hits[0].id);
+    }
+
+    #[test]
+    fn test_search_filters_and_reindexing() {
+        let (s, _f, index, graph) = index();
+        let other = NamedNode::new_unchecked("http://example.com/other");
+
+        assert_eq!(3, index.search(&s, "cell", Some(&vec![graph.clone()].into_iter().collect()), None, 10).len());
+        assert!(index.search(&s, "cell", Some(&vec![other].into_iter().collect()), None, 10).is_empty());
+
+        let class = NamedNode::new_unchecked("http://www.w3.org/2002/07/owl#Class");
+        assert_eq!(3, index.search(&s, "cell", None, Some(&class), 10).len());
+        assert_eq!(1, index.search(&s, "cell", None, None, 1).len());
+
+        delete_graph(&s, &graph).unwrap();
+        assert!(index.search(&s, "cell", None, None, 10).is_empty());
+    }
+
+    #[test]
+    fn test_index_follows_writes() {
+        let (s, _f, index, graph) = index();
+
+        let renamed = ONTOLOGY.replace("\"cell cycle\"", "\"mitotic cycle\"");
+        rewrite_graph(&s, &graph, parse_turtle(renamed.as_bytes()).unwrap(), None).unwrap();
+        assert_eq!(vec!["http://purl.obolibrary.org/obo/GO_0007049"], ids(&index.search(&s, "mitotic", None, None, 10)));
+
+        let other = NamedNode::new_unchecked("http://example.com/other");
+        load_turtle_into_new_graph(&s, other, GraphType::Model, b"<http://example.com/x> <http://www.w3.org/2000/01/rdf-schema#label> \"axon\" .".to_vec());
+        assert_eq!(vec!["http://example.com/x"], ids(&index.search(&s, "axon", None, None, 10)));
+    }
+}
@@ -0,0 +1,132 @@
+//! The SPARQL 1.1 Service Description of the query endpoint, worked out from `<Meta>` each time it
+//! is asked for, so it always lists the graphs there are.
+//!
+//! The named graphs are grouped into one `sd:GraphCollection` per `GraphType`. Inferred and
+//! Closure graphs hold entailments worked out ahead of time, and when the reasoner profile they
+//! were loaded with names an entailment regime, their description says so:
+//! ```turtle
+//! [] a sd:NamedGraph ;
+//!     sd:name <G-inferred> ;
+//!     sd:entailmentRegime ent:OWL-RDF-Based ;
+//!     sd:graph [ a sd:Graph, meta:Inferred ] .
+//! ```
+
+use std::collections::BTreeMap;
+
+use oxigraph::SledStore as Store;
+use oxigraph::model::{BlankNode, Literal, NamedNode, Term, Triple};
+use oxigraph::model::vocab::{rdf, rdfs};
+
+use crate::meta;
+use crate::api::GraphType;
+use crate::api::provenance;
+use crate::api::vocab::{formats, sd};
+
+const ENTAILMENT: &str = "http://www.w3.org/ns/entailment/";
+const OWL_PROFILE: &str = "http://www.w3.org/ns/owl-profile/";
+
+/// The Service Description of the endpoint at `endpoint`, listing `graphs`.
+pub fn describe(store: &Store, endpoint: &str, graphs: &[(NamedNode, GraphType)]) -> Vec<Triple> {
+    let service = BlankNode::default();
+    let mut triples = vec![
+        Triple::new(service.clone(), rdf::TYPE, sd::SERVICE),
+        Triple::new(service.clone(), sd::ENDPOINT, NamedNode::new_unchecked(endpoint)),
+        Triple::new(service.clone(), sd::SUPPORTED_LANGUAGE, sd::SPARQL11_QUERY),
+        Triple::new(service.clone(), sd::RESULT_FORMAT, formats::SPARQL_RESULTS_JSON),
+        Triple::new(service.clone(), sd::RESULT_FORMAT, formats::TURTLE),
+        Triple::new(service.clone(), sd::DEFAULT_ENTAILMENT_REGIME, NamedNode::new_unchecked(format!("{}Simple", ENTAILMENT))),
+    ];
+
+    let mut by_type: BTreeMap<&str, (GraphType, Vec<&NamedNode>)> = BTreeMap::new();
+    for (graph, graph_type) in graphs {
+        by_type.entry(graph_type.as_ref()).or_insert_with(|| (*graph_type, Vec::new())).1.push(graph);
+    }
+    let mut profiles = Vec::new();
+    for (label, (graph_type, members)) in by_type {
+        let collection = BlankNode::default();
+        triples.push(Triple::new(service.clone(), sd::AVAILABLE_GRAPHS, collection.clone()));
+        triples.push(Triple::new(collection.clone(), rdf::TYPE, sd::GRAPH_COLLECTION));
+        triples.push(Triple::new(collection.clone(), rdfs::LABEL, Literal::new_simple_literal(label)));
+
+        for graph in members {
+            let named = BlankNode::default();
+            let description = BlankNode::default();
+            let type_uri = NamedNode::from(graph_type.uri());
+            triples.push(Triple::new(collection.clone(), sd::NAMED_GRAPH, named.clone()));
+            triples.push(Triple::new(named.clone(), rdf::TYPE, sd::NAMED_GRAPH_CLASS));
+            triples.push(Triple::new(named.clone(), sd::NAME, graph.clone()));
+            triples.push(Triple::new(named.clone(), sd::GRAPH, description.clone()));
+            triples.push(Triple::new(description.clone(), rdf::TYPE, sd::GRAPH_CLASS));
+            triples.push(Triple::new(description, rdf::TYPE, type_uri));
+
+            if let Some((regime, profile)) = reasoner_of(store, graph).as_deref().and_then(entailment) {
+                triples.push(Triple::new(named, sd::ENTAILMENT_REGIME, regime));
+                profiles.extend(profile);
+            }
+        }
+    }
+
+    profiles.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    profiles.dedup();
+    for profile in profiles {
+        triples.push(Triple::new(service.clone(), sd::SUPPORTED_ENTAILMENT_PROFILE, profile));
+    }
+    triples
+}
+
+/// The reasoner profile the graph was last loaded with, as recorded in `<Provenance>`.
+fn reasoner_of(store: &Store, graph: &NamedNode) -> Option<String> {
+    let reasoner = NamedNode::from(meta::reasonerProfile);
+    provenance::provenance_of(store, graph.as_ref())
+        .into_iter()
+        .filter(|t| t.predicate == reasoner)
+        .filter_map(|t| match t.object {
+            Term::Literal(l) => Some(l.value().to_owned()),
+            _ => None,
+        })
+        .last()
+}
+
+/// The entailment regime, and OWL profile if there is one, that a reasoner profile stands for.
+/// Full regime IRIs are taken as they are.
+fn entailment(profile: &str) -> Option<(NamedNode, Option<NamedNode>)> {
+    if profile.starts_with(ENTAILMENT) {
+        return NamedNode::new(profile).ok().map(|regime| (regime, None));
+    }
+    let regime = |local: &str| NamedNode::new_unchecked(format!("{}{}", ENTAILMENT, local));
+    let owl_profile = |local: &str| Some(NamedNode::new_unchecked(format!("{}{}", OWL_PROFILE, local)));
+    match profile.to_ascii_lowercase().as_str() {
+        "rdf" => Some((regime("RDF"), None)),
+        "rdfs" => Some((regime("RDFS"), None)),
+        "owl-rl" | "rl" => Some((regime("OWL-RDF-Based"), owl_profile("RL"))),
+        "owl-el" | "el" => Some((regime("OWL-Direct"), owl_profile("EL"))),
+        "owl-ql" | "ql" => Some((regime("OWL-Direct"), owl_profile("QL"))),
+        "owl" | "owl-dl" | "dl" => Some((regime("OWL-Direct"), owl_profile("DL"))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::storage::test::init_store;
+
+    #[test]
+    fn test_describe() {
+        let (s, _f) = init_store();
+        let hello = NamedNode::new_unchecked("http://www.purl.org/dougli1sqrd/models/janus-oxide/hello");
+        let triples = describe(&s, "http://localhost:8000/sparql", &[(hello.clone(), GraphType::Model)]);
+
+        assert!(triples.iter().any(|t| t.predicate.as_ref() == sd::ENDPOINT && t.object == Term::from(NamedNode::new_unchecked("http://localhost:8000/sparql"))));
+        assert!(triples.iter().any(|t| t.predicate.as_ref() == sd::NAME && t.object == Term::from(hello.clone())));
+        assert!(triples.iter().any(|t| t.predicate.as_ref() == rdfs::LABEL && t.object == Term::from(Literal::new_simple_literal("Model"))));
+        assert!(!triples.iter().any(|t| t.predicate.as_ref() == sd::ENTAILMENT_REGIME));
+    }
+
+    #[test]
+    fn test_entailment() {
+        assert_eq!(Some((NamedNode::new_unchecked("http://www.w3.org/ns/entailment/RDFS"), None)), entailment("RDFS"));
+        assert_eq!(Some(NamedNode::new_unchecked("http://www.w3.org/ns/owl-profile/EL")), entailment("owl-el").and_then(|(_, p)| p));
+        assert_eq!(None, entailment("relation-graph"));
+    }
+}
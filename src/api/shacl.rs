@@ -0,0 +1,1053 @@
+//! SHACL Core validation (https://www.w3.org/TR/shacl/).
+//!
+//! A data graph is validated against the union of the graphs registered in `<Meta>` as `Shapes`,
+//! giving a `sh:ValidationReport`. All of SHACL Core is covered, but not SPARQL-based
+//! constraints. As the spec asks, `sh:class` and `sh:targetClass` follow
+//! `rdfs:subClassOf` in the data graph only, so validating a Model together with its Inferred
+//! graph is the way to take inferred types into account.
+
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+
+use regex::Regex;
+
+use oxigraph::SledStore as Store;
+use oxigraph::model::{BlankNode, Literal, NamedNode, NamedNodeRef, NamedOrBlankNode, Term, Triple};
+use oxigraph::model::vocab::{rdf, rdfs, xsd};
+
+use crate::api::GraphType;
+use crate::api::storage::{graphs_of_type, graph_triples, triples_as_ttl_string};
+use crate::meta;
+
+const SH: &str = "http://www.w3.org/ns/shacl#";
+
+/// Nested shapes (`sh:node`, `sh:and`, ...) can refer back to themselves, which SHACL leaves
+/// undefined. A shape met again for a focus node it is already being checked against is taken to
+/// hold there, so cycles in the data end the recursion. Chains of distinct focus nodes don't, so
+/// we stop following them this deep, and report the focus node as not validated.
+const MAX_DEPTH: usize = 32;
+
+fn sh(local: &str) -> NamedNode {
+    NamedNode::new_unchecked(format!("{}{}", SH, local))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ValidationResult {
+    pub focus: Term,
+    pub path: Option<Term>,
+    pub value: Option<Term>,
+    pub source_shape: Term,
+    pub component: NamedNode,
+    pub severity: Term,
+    pub messages: Vec<Term>,
+}
+
+#[derive(Debug)]
+pub struct Report {
+    pub results: Vec<ValidationResult>,
+    /// The triples describing non-IRI `sh:resultPath`s, copied over from the shapes graph.
+    paths: Vec<Triple>,
+}
+
+impl Report {
+    pub fn conforms(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    /// The report as a `sh:ValidationReport` graph.
+    pub fn to_triples(&self) -> Vec<Triple> {
+        let report = BlankNode::default();
+        let mut triples = vec![
+            Triple::new(report.clone(), rdf::TYPE, sh("ValidationReport")),
+            Triple::new(report.clone(), sh("conforms"), Literal::new_typed_literal(self.conforms().to_string(), xsd::BOOLEAN)),
+        ];
+
+        for result in self.results.iter() {
+            let node = BlankNode::default();
+            triples.push(Triple::new(report.clone(), sh("result"), node.clone()));
+            triples.push(Triple::new(node.clone(), rdf::TYPE, sh("ValidationResult")));
+            triples.push(Triple::new(node.clone(), sh("focusNode"), result.focus.clone()));
+            if let Some(path) = &result.path {
+                triples.push(Triple::new(node.clone(), sh("resultPath"), path.clone()));
+            }
+            if let Some(value) = &result.value {
+                triples.push(Triple::new(node.clone(), sh("value"), value.clone()));
+            }
+            triples.push(Triple::new(node.clone(), sh("sourceShape"), result.source_shape.clone()));
+            triples.push(Triple::new(node.clone(), sh("sourceConstraintComponent"), result.component.clone()));
+            triples.push(Triple::new(node.clone(), sh("resultSeverity"), result.severity.clone()));
+            for message in result.messages.iter() {
+                triples.push(Triple::new(node.clone(), sh("resultMessage"), message.clone()));
+            }
+        }
+
+        triples.extend(self.paths.iter().cloned());
+        triples
+    }
+
+    pub fn to_turtle(&self) -> Result<String, String> {
+        triples_as_ttl_string(self.to_triples())
+    }
+}
+
+/// The union of every graph registered in `<Meta>` as `Shapes`.
+pub fn registered_shapes(store: &Store) -> Vec<Triple> {
+    graphs_of_type(store, GraphType::Shapes)
+        .into_iter()
+        .flat_map(|g| graph_triples(store, g.as_ref()))
+        .collect()
+}
+
+/// The graph `<M>`'s Inferred graph `<I(M)>`, if it has one.
+pub fn inferred_graph(store: &Store, graph: NamedNodeRef) -> Option<NamedNode> {
+    let has_inferences_at = NamedNode::from(meta::hasInferencesAt);
+    store.quads_for_pattern(Some(graph.into()), Some(has_inferences_at.as_ref()), None, Some(meta::meta_graph_uri()))
+        .filter_map(Result::ok)
+        .find_map(|q| match q.object {
+            Term::NamedNode(n) => Some(n),
+            _ => None,
+        })
+}
+
+/// Validates a stored graph, together with its Inferred graph if `with_inferred`, against the
+/// registered shapes.
+pub fn validate_graph(store: &Store, graph: &NamedNode, with_inferred: bool) -> Report {
+    let mut data = graph_triples(store, graph.as_ref());
+    if with_inferred {
+        if let Some(inferred) = inferred_graph(store, graph.as_ref()) {
+            data.extend(graph_triples(store, inferred.as_ref()));
+        }
+    }
+    validate(&data, &registered_shapes(store))
+}
+
+pub fn validate(data: &[Triple], shapes: &[Triple]) -> Report {
+    let data = Index::new(data);
+    let shapes = Index::new(shapes);
+    let validator = Validator {
+        data: &data,
+        shapes: &shapes,
+        validated: RefCell::new(HashMap::new()),
+        in_progress: RefCell::new(HashSet::new()),
+        too_deep: RefCell::new(HashMap::new()),
+    };
+
+    let mut results = Vec::new();
+    for shape in validator.shapes_with_targets() {
+        for focus in validator.focus_nodes(&shape) {
+            results.extend(validator.validate_shape(&shape, &focus, 0));
+        }
+    }
+    // Only reported from here, as shapes given up on below a `sh:not` would count as passing
+    results.extend(validator.too_deep.into_inner().into_iter().map(|(_, result)| result));
+
+    let mut seen = HashSet::new();
+    results.retain(|r| seen.insert(r.clone()));
+
+    let paths = results.iter()
+        .filter_map(|r| match &r.path {
+            Some(p @ Term::BlankNode(_)) => Some(shapes.describe(p)),
+            _ => None,
+        })
+        .flatten()
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+
+    Report { results, paths }
+}
+
+/// A graph indexed by subject and by object.
+struct Index {
+    outgoing: HashMap<Term, Vec<(NamedNode, Term)>>,
+    incoming: HashMap<Term, Vec<(Term, NamedNode)>>,
+}
+
+impl Index {
+    fn new(triples: &[Triple]) -> Index {
+        let mut outgoing: HashMap<Term, Vec<(NamedNode, Term)>> = HashMap::new();
+        let mut incoming: HashMap<Term, Vec<(Term, NamedNode)>> = HashMap::new();
+        for t in triples {
+            let subject = Term::from(t.subject.clone());
+            outgoing.entry(subject.clone()).or_insert_with(Vec::new).push((t.predicate.clone(), t.object.clone()));
+            incoming.entry(t.object.clone()).or_insert_with(Vec::new).push((subject, t.predicate.clone()));
+        }
+        Index { outgoing, incoming }
+    }
+
+    fn objects(&self, subject: &Term, predicate: &NamedNode) -> Vec<Term> {
+        self.outgoing.get(subject)
+            .map(|po| po.iter().filter(|(p, _)| p == predicate).map(|(_, o)| o.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    fn object(&self, subject: &Term, predicate: &NamedNode) -> Option<Term> {
+        self.objects(subject, predicate).into_iter().next()
+    }
+
+    fn subjects(&self, predicate: &NamedNode, object: &Term) -> Vec<Term> {
+        self.incoming.get(object)
+            .map(|sp| sp.iter().filter(|(_, p)| p == predicate).map(|(s, _)| s.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    fn subjects_with(&self, predicate: &NamedNode) -> Vec<Term> {
+        self.outgoing.iter()
+            .filter(|(_, po)| po.iter().any(|(p, _)| p == predicate))
+            .map(|(s, _)| s.clone())
+            .collect()
+    }
+
+    fn objects_with(&self, predicate: &NamedNode) -> Vec<Term> {
+        self.incoming.iter()
+            .filter(|(_, sp)| sp.iter().any(|(_, p)| p == predicate))
+            .map(|(o, _)| o.clone())
+            .collect()
+    }
+
+    fn properties(&self, subject: &Term) -> Vec<(NamedNode, Term)> {
+        self.outgoing.get(subject).cloned().unwrap_or_default()
+    }
+
+    /// The members of the RDF list starting at `head`.
+    fn list(&self, head: &Term) -> Vec<Term> {
+        let first = NamedNode::from(rdf::FIRST);
+        let rest = NamedNode::from(rdf::REST);
+        let nil = Term::from(NamedNode::from(rdf::NIL));
+
+        let mut members = Vec::new();
+        let mut current = head.clone();
+        while current != nil && members.len() < 10_000 {
+            match self.object(&current, &first) {
+                Some(member) => members.push(member),
+                None => break,
+            }
+            match self.object(&current, &rest) {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+        members
+    }
+
+    /// All triples reachable from a blank node through other blank nodes, e.g. a path expression.
+    fn describe(&self, node: &Term) -> Vec<Triple> {
+        let mut triples = Vec::new();
+        let mut todo = vec![node.clone()];
+        let mut done = HashSet::new();
+        while let Some(current) = todo.pop() {
+            if !done.insert(current.clone()) {
+                continue;
+            }
+            let subject = match as_subject(&current) {
+                Some(s) => s,
+                None => continue,
+            };
+            for (p, o) in self.properties(&current) {
+                if let Term::BlankNode(_) = o {
+                    todo.push(o.clone());
+                }
+                triples.push(Triple::new(subject.clone(), p, o));
+            }
+        }
+        triples
+    }
+}
+
+struct Validator<'a> {
+    data: &'a Index,
+    shapes: &'a Index,
+    /// The results of each shape for each focus node it has been checked against.
+    validated: RefCell<HashMap<(Term, Term), Vec<ValidationResult>>>,
+    /// The shapes and focus nodes being checked further up the recursion.
+    in_progress: RefCell<HashSet<(Term, Term)>>,
+    /// The shapes and focus nodes nested past `MAX_DEPTH`, wherever they were reached.
+    too_deep: RefCell<HashMap<(Term, Term), ValidationResult>>,
+}
+
+impl<'a> Validator<'a> {
+    /// Shapes with an explicit target, plus node shapes that are also classes (implicit targets).
+    fn shapes_with_targets(&self) -> Vec<Term> {
+        let mut shapes: HashSet<Term> = HashSet::new();
+        for target in ["targetNode", "targetClass", "targetSubjectsOf", "targetObjectsOf"].iter() {
+            shapes.extend(self.shapes.subjects_with(&sh(target)));
+        }
+
+        let class = Term::from(NamedNode::from(rdfs::CLASS));
+        let rdf_type = NamedNode::from(rdf::TYPE);
+        for shape_class in ["NodeShape", "PropertyShape"].iter() {
+            for shape in self.shapes.subjects(&rdf_type, &Term::from(sh(shape_class))) {
+                if self.shapes.objects(&shape, &rdf_type).contains(&class) {
+                    shapes.insert(shape);
+                }
+            }
+        }
+
+        shapes.into_iter().collect()
+    }
+
+    fn focus_nodes(&self, shape: &Term) -> Vec<Term> {
+        let mut focus: Vec<Term> = self.shapes.objects(shape, &sh("targetNode"));
+
+        let mut classes = self.shapes.objects(shape, &sh("targetClass"));
+        if self.shapes.objects(shape, &NamedNode::from(rdf::TYPE)).contains(&Term::from(NamedNode::from(rdfs::CLASS))) {
+            classes.push(shape.clone());
+        }
+        for class in classes {
+            focus.extend(self.instances_of(&class));
+        }
+        for predicate in self.shapes.objects(shape, &sh("targetSubjectsOf")) {
+            if let Term::NamedNode(p) = predicate {
+                focus.extend(self.data.subjects_with(&p));
+            }
+        }
+        for predicate in self.shapes.objects(shape, &sh("targetObjectsOf")) {
+            if let Term::NamedNode(p) = predicate {
+                focus.extend(self.data.objects_with(&p));
+            }
+        }
+
+        let mut seen = HashSet::new();
+        focus.retain(|f| seen.insert(f.clone()));
+        focus
+    }
+
+    /// `class` and all its subclasses, following `rdfs:subClassOf` in the data graph.
+    fn subclasses(&self, class: &Term) -> HashSet<Term> {
+        let sub_class_of = NamedNode::from(rdfs::SUB_CLASS_OF);
+        let mut classes = HashSet::new();
+        let mut todo = vec![class.clone()];
+        while let Some(c) = todo.pop() {
+            if classes.insert(c.clone()) {
+                todo.extend(self.data.subjects(&sub_class_of, &c));
+            }
+        }
+        classes
+    }
+
+    fn instances_of(&self, class: &Term) -> Vec<Term> {
+        let rdf_type = NamedNode::from(rdf::TYPE);
+        self.subclasses(class)
+            .iter()
+            .flat_map(|c| self.data.subjects(&rdf_type, c))
+            .collect()
+    }
+
+    fn is_instance(&self, node: &Term, class: &Term) -> bool {
+        let classes = self.subclasses(class);
+        self.data.objects(node, &NamedNode::from(rdf::TYPE)).iter().any(|t| classes.contains(t))
+    }
+
+    fn conforms(&self, shape: &Term, node: &Term, depth: usize) -> bool {
+        self.validate_shape(shape, node, depth + 1).is_empty()
+    }
+
+    fn is_true(&self, shape: &Term, parameter: &str) -> bool {
+        match self.shapes.object(shape, &sh(parameter)) {
+            Some(Term::Literal(l)) => l.value() == "true" || l.value() == "1",
+            _ => false,
+        }
+    }
+
+    /// The `sh:qualifiedValueShape`s of the property shapes next to `shape` in any shape that
+    /// has it as a `sh:property`, other than `qualified` itself.
+    fn sibling_shapes(&self, shape: &Term, qualified: &Term) -> Vec<Term> {
+        let property = sh("property");
+        let mut siblings = Vec::new();
+        for parent in self.shapes.subjects(&property, shape) {
+            for sibling in self.shapes.objects(&parent, &property) {
+                for q in self.shapes.objects(&sibling, &sh("qualifiedValueShape")) {
+                    if q != *qualified && !siblings.contains(&q) {
+                        siblings.push(q);
+                    }
+                }
+            }
+        }
+        siblings
+    }
+
+    fn integer_parameter(&self, shape: &Term, parameter: &str) -> Vec<usize> {
+        self.shapes.objects(shape, &sh(parameter))
+            .into_iter()
+            .filter_map(|o| match o {
+                Term::Literal(l) => l.value().parse().ok(),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The value nodes reached from `focus` by a SHACL property path.
+    fn eval_path(&self, path: &Term, focus: &Term) -> Vec<Term> {
+        self.eval_path_directed(path, focus, false)
+    }
+
+    fn eval_path_directed(&self, path: &Term, node: &Term, inverse: bool) -> Vec<Term> {
+        let step = |p: &NamedNode, n: &Term| if inverse { self.data.subjects(p, n) } else { self.data.objects(n, p) };
+
+        let mut values = match path {
+            Term::NamedNode(p) => step(p, node),
+            Term::Literal(_) => Vec::new(),
+            Term::BlankNode(_) => {
+                if let Some(inner) = self.shapes.object(path, &sh("inversePath")) {
+                    self.eval_path_directed(&inner, node, !inverse)
+                } else if let Some(list) = self.shapes.object(path, &sh("alternativePath")) {
+                    self.shapes.list(&list).iter().flat_map(|p| self.eval_path_directed(p, node, inverse)).collect()
+                } else if let Some(inner) = self.shapes.object(path, &sh("zeroOrMorePath")) {
+                    self.closure(&inner, node, inverse, true)
+                } else if let Some(inner) = self.shapes.object(path, &sh("oneOrMorePath")) {
+                    self.closure(&inner, node, inverse, false)
+                } else if let Some(inner) = self.shapes.object(path, &sh("zeroOrOnePath")) {
+                    let mut values = vec![node.clone()];
+                    values.extend(self.eval_path_directed(&inner, node, inverse));
+                    values
+                } else {
+                    // A sequence path is a list of paths
+                    let mut steps = self.shapes.list(path);
+                    if inverse {
+                        steps.reverse();
+                    }
+                    steps.iter().fold(vec![node.clone()], |nodes, p| {
+                        nodes.iter().flat_map(|n| self.eval_path_directed(p, n, inverse)).collect()
+                    })
+                }
+            }
+        };
+
+        let mut seen = HashSet::new();
+        values.retain(|v| seen.insert(v.clone()));
+        values
+    }
+
+    fn closure(&self, path: &Term, node: &Term, inverse: bool, include_self: bool) -> Vec<Term> {
+        let mut reached = Vec::new();
+        let mut seen = HashSet::new();
+        if include_self {
+            seen.insert(node.clone());
+            reached.push(node.clone());
+        }
+        let mut todo = vec![node.clone()];
+        while let Some(current) = todo.pop() {
+            for next in self.eval_path_directed(path, &current, inverse) {
+                if seen.insert(next.clone()) {
+                    reached.push(next.clone());
+                    todo.push(next);
+                }
+            }
+        }
+        reached
+    }
+
+    fn validate_shape(&self, shape: &Term, focus: &Term, depth: usize) -> Vec<ValidationResult> {
+        let key = (shape.clone(), focus.clone());
+        if let Some(results) = self.validated.borrow().get(&key) {
+            return results.clone();
+        }
+        if self.in_progress.borrow().contains(&key) {
+            return Vec::new();
+        }
+        if depth > MAX_DEPTH {
+            self.too_deep.borrow_mut().entry(key).or_insert_with(|| ValidationResult {
+                focus: focus.clone(),
+                path: None,
+                value: Some(focus.clone()),
+                source_shape: shape.clone(),
+                component: sh("NodeConstraintComponent"),
+                severity: sh("Violation").into(),
+                messages: vec![Literal::new_simple_literal(format!("Shapes nested more than {} deep, as recursive shapes are, can't be validated", MAX_DEPTH)).into()],
+            });
+            return Vec::new();
+        }
+
+        self.in_progress.borrow_mut().insert(key.clone());
+        let results = self.check_shape(shape, focus, depth);
+        self.in_progress.borrow_mut().remove(&key);
+        self.validated.borrow_mut().insert(key, results.clone());
+        results
+    }
+
+    fn check_shape(&self, shape: &Term, focus: &Term, depth: usize) -> Vec<ValidationResult> {
+        if self.is_true(shape, "deactivated") {
+            return Vec::new();
+        }
+
+        let path = self.shapes.object(shape, &sh("path"));
+        let values = match &path {
+            Some(p) => self.eval_path(p, focus),
+            None => vec![focus.clone()],
+        };
+
+        let severity = self.shapes.object(shape, &sh("severity")).unwrap_or_else(|| sh("Violation").into());
+        let messages = self.shapes.objects(shape, &sh("message"));
+        let mut results = Vec::new();
+        let mut fail = |component: &str, value: Option<Term>, result_path: Option<Term>| {
+            results.push(ValidationResult {
+                focus: focus.clone(),
+                path: result_path.or_else(|| path.clone()),
+                value,
+                source_shape: shape.clone(),
+                component: sh(&format!("{}ConstraintComponent", component)),
+                severity: severity.clone(),
+                messages: messages.clone(),
+            })
+        };
+
+        // Constraints on each value node
+        for class in self.shapes.objects(shape, &sh("class")) {
+            for v in values.iter().filter(|v| !self.is_instance(v, &class)) {
+                fail("Class", Some(v.clone()), None);
+            }
+        }
+        for datatype in self.shapes.objects(shape, &sh("datatype")) {
+            for v in values.iter() {
+                let ok = match v {
+                    Term::Literal(l) => Term::from(NamedNode::from(l.datatype())) == datatype && well_formed(l),
+                    _ => false,
+                };
+                if !ok {
+                    fail("Datatype", Some(v.clone()), None);
+                }
+            }
+        }
+        for kind in self.shapes.objects(shape, &sh("nodeKind")) {
+            for v in values.iter().filter(|v| !node_kind_matches(&kind, v)) {
+                fail("NodeKind", Some(v.clone()), None);
+            }
+        }
+        for &(parameter, component, allowed) in [
+            ("minExclusive", "MinExclusive", &[Ordering::Greater][..]),
+            ("minInclusive", "MinInclusive", &[Ordering::Greater, Ordering::Equal][..]),
+            ("maxExclusive", "MaxExclusive", &[Ordering::Less][..]),
+            ("maxInclusive", "MaxInclusive", &[Ordering::Less, Ordering::Equal][..]),
+        ].iter() {
+            for bound in self.shapes.objects(shape, &sh(parameter)) {
+                for v in values.iter() {
+                    match compare(v, &bound) {
+                        Some(o) if allowed.contains(&o) => {}
+                        _ => fail(component, Some(v.clone()), None),
+                    }
+                }
+            }
+        }
+        for min in self.integer_parameter(shape, "minLength") {
+            for v in values.iter().filter(|v| string_value(v).map_or(true, |s| s.chars().count() < min)) {
+                fail("MinLength", Some(v.clone()), None);
+            }
+        }
+        for max in self.integer_parameter(shape, "maxLength") {
+            for v in values.iter().filter(|v| string_value(v).map_or(true, |s| s.chars().count() > max)) {
+                fail("MaxLength", Some(v.clone()), None);
+            }
+        }
+        for pattern in self.shapes.objects(shape, &sh("pattern")) {
+            let flags = match self.shapes.object(shape, &sh("flags")) {
+                Some(Term::Literal(l)) => l.value().to_owned(),
+                _ => String::new(),
+            };
+            let source = match &pattern {
+                Term::Literal(l) => l.value().to_owned(),
+                _ => continue,
+            };
+            let regex = match Regex::new(&if flags.is_empty() { source } else { format!("(?{}){}", flags, source) }) {
+                Ok(r) => r,
+                Err(_) => continue,
+            };
+            for v in values.iter().filter(|v| string_value(v).map_or(true, |s| !regex.is_match(&s))) {
+                fail("Pattern", Some(v.clone()), None);
+            }
+        }
+        for languages in self.shapes.objects(shape, &sh("languageIn")) {
+            let tags: Vec<String> = self.shapes.list(&languages).iter()
+                .filter_map(|t| match t {
+                    Term::Literal(l) => Some(l.value().to_lowercase()),
+                    _ => None,
+                })
+                .collect();
+            for v in values.iter() {
+                let ok = match v {
+                    Term::Literal(l) => l.language().map_or(false, |lang| {
+                        let lang = lang.to_lowercase();
+                        tags.iter().any(|t| lang == *t || lang.starts_with(&format!("{}-", t)))
+                    }),
+                    _ => false,
+                };
+                if !ok {
+                    fail("LanguageIn", Some(v.clone()), None);
+                }
+            }
+        }
+        for members in self.shapes.objects(shape, &sh("in")) {
+            let members = self.shapes.list(&members);
+            for v in values.iter().filter(|v| !members.contains(v)) {
+                fail("In", Some(v.clone()), None);
+            }
+        }
+
+        // Shape-based constraints
+        for node in self.shapes.objects(shape, &sh("node")) {
+            for v in values.iter().filter(|v| !self.conforms(&node, v, depth)) {
+                fail("Node", Some(v.clone()), None);
+            }
+        }
+        for not in self.shapes.objects(shape, &sh("not")) {
+            for v in values.iter().filter(|v| self.conforms(&not, v, depth)) {
+                fail("Not", Some(v.clone()), None);
+            }
+        }
+        for &(parameter, component) in [("and", "And"), ("or", "Or"), ("xone", "Xone")].iter() {
+            for list in self.shapes.objects(shape, &sh(parameter)) {
+                let members = self.shapes.list(&list);
+                for v in values.iter() {
+                    let conforming = members.iter().filter(|m| self.conforms(m, v, depth)).count();
+                    let ok = match component {
+                        "And" => conforming == members.len(),
+                        "Or" => conforming > 0,
+                        _ => conforming == 1,
+                    };
+                    if !ok {
+                        fail(component, Some(v.clone()), None);
+                    }
+                }
+            }
+        }
+
+        for qualified in self.shapes.objects(shape, &sh("qualifiedValueShape")) {
+            let siblings = if self.is_true(shape, "qualifiedValueShapesDisjoint") {
+                self.sibling_shapes(shape, &qualified)
+            } else {
+                Vec::new()
+            };
+            let count = values.iter()
+                .filter(|v| self.conforms(&qualified, v, depth) && !siblings.iter().any(|s| self.conforms(s, v, depth)))
+                .count();
+            for min in self.integer_parameter(shape, "qualifiedMinCount") {
+                if count < min {
+                    fail("QualifiedMinCount", None, None);
+                }
+            }
+            for max in self.integer_parameter(shape, "qualifiedMaxCount") {
+                if count > max {
+                    fail("QualifiedMaxCount", None, None);
+                }
+            }
+        }
+
+        // Constraints on the value nodes as a whole
+        for min in self.integer_parameter(shape, "minCount") {
+            if values.len() < min {
+                fail("MinCount", None, None);
+            }
+        }
+        for max in self.integer_parameter(shape, "maxCount") {
+            if values.len() > max {
+                fail("MaxCount", None, None);
+            }
+        }
+        for value in self.shapes.objects(shape, &sh("hasValue")) {
+            if !values.contains(&value) {
+                fail("HasValue", None, None);
+            }
+        }
+        if self.is_true(shape, "uniqueLang") {
+            let mut counts: HashMap<String, usize> = HashMap::new();
+            for v in values.iter() {
+                if let Term::Literal(l) = v {
+                    if let Some(lang) = l.language() {
+                        *counts.entry(lang.to_lowercase()).or_insert(0) += 1;
+                    }
+                }
+            }
+            for _ in counts.values().filter(|c| **c > 1) {
+                fail("UniqueLang", None, None);
+            }
+        }
+        for predicate in self.shapes.objects(shape, &sh("equals")) {
+            if let Term::NamedNode(p) = predicate {
+                let others = self.data.objects(focus, &p);
+                for v in values.iter().filter(|v| !others.contains(v)) {
+                    fail("Equals", Some(v.clone()), None);
+                }
+                for o in others.iter().filter(|o| !values.contains(o)) {
+                    fail("Equals", Some(o.clone()), None);
+                }
+            }
+        }
+        for predicate in self.shapes.objects(shape, &sh("disjoint")) {
+            if let Term::NamedNode(p) = predicate {
+                let others = self.data.objects(focus, &p);
+                for v in values.iter().filter(|v| others.contains(v)) {
+                    fail("Disjoint", Some(v.clone()), None);
+                }
+            }
+        }
+        for &(parameter, component, allowed) in [
+            ("lessThan", "LessThan", &[Ordering::Less][..]),
+            ("lessThanOrEquals", "LessThanOrEquals", &[Ordering::Less, Ordering::Equal][..]),
+        ].iter() {
+            for predicate in self.shapes.objects(shape, &sh(parameter)) {
+                if let Term::NamedNode(p) = predicate {
+                    let others = self.data.objects(focus, &p);
+                    for v in values.iter() {
+                        let ok = others.iter().all(|o| compare(v, o).map_or(false, |c| allowed.contains(&c)));
+                        if !ok {
+                            fail(component, Some(v.clone()), None);
+                        }
+                    }
+                }
+            }
+        }
+        if path.is_none() && self.is_true(shape, "closed") {
+            let mut allowed: HashSet<Term> = self.shapes.objects(shape, &sh("property"))
+                .iter()
+                .filter_map(|p| self.shapes.object(p, &sh("path")))
+                .filter(|p| matches!(p, Term::NamedNode(_)))
+                .collect();
+            if let Some(ignored) = self.shapes.object(shape, &sh("ignoredProperties")) {
+                allowed.extend(self.shapes.list(&ignored));
+            }
+            for (p, o) in self.data.properties(focus) {
+                if !allowed.contains(&Term::from(p.clone())) {
+                    fail("Closed", Some(o), Some(p.into()));
+                }
+            }
+        }
+
+        // Property shapes apply to each value node
+        for property in self.shapes.objects(shape, &sh("property")) {
+            for v in values.iter() {
+                results.extend(self.validate_shape(&property, v, depth + 1));
+            }
+        }
+
+        results
+    }
+}
+
+fn node_kind_matches(kind: &Term, value: &Term) -> bool {
+    let local = match kind {
+        Term::NamedNode(k) => k.as_str().trim_start_matches(SH),
+        _ => return false,
+    };
+    match value {
+        Term::NamedNode(_) => ["IRI", "BlankNodeOrIRI", "IRIOrLiteral"].contains(&local),
+        Term::BlankNode(_) => ["BlankNode", "BlankNodeOrIRI", "BlankNodeOrLiteral"].contains(&local),
+        Term::Literal(_) => ["Literal", "BlankNodeOrLiteral", "IRIOrLiteral"].contains(&local),
+    }
+}
+
+/// The string SHACL length and pattern constraints look at: the IRI or the lexical form.
+fn string_value(term: &Term) -> Option<String> {
+    match term {
+        Term::NamedNode(n) => Some(n.as_str().to_owned()),
+        Term::Literal(l) => Some(l.value().to_owned()),
+        Term::BlankNode(_) => None,
+    }
+}
+
+/// Whether the lexical form of a literal is valid for its datatype, for the XSD datatypes
+/// usually met in data. Literals of other datatypes are taken to be well formed.
+fn well_formed(literal: &Literal) -> bool {
+    let value = literal.value();
+    let local = match literal.datatype().as_str().strip_prefix("http://www.w3.org/2001/XMLSchema#") {
+        Some(local) => local,
+        None => return true,
+    };
+    let integer = || {
+        let digits = value.strip_prefix(&['+', '-'][..]).unwrap_or(value);
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        value.parse::<i128>().ok()
+    };
+    let in_range = |min: i128, max: i128| integer().map_or(false, |i| min <= i && i <= max);
+    let decimal = || {
+        let digits = value.strip_prefix(&['+', '-'][..]).unwrap_or(value);
+        digits.chars().any(|c| c.is_ascii_digit())
+            && digits.chars().all(|c| c.is_ascii_digit() || c == '.')
+            && digits.matches('.').count() <= 1
+    };
+    let floating = || {
+        ["INF", "+INF", "-INF", "NaN"].contains(&value)
+            || (value.chars().all(|c| c.is_ascii_digit() || "+-.eE".contains(c)) && value.parse::<f64>().is_ok())
+    };
+
+    match local {
+        "integer" => integer().is_some(),
+        "long" => in_range(i64::MIN as i128, i64::MAX as i128),
+        "int" => in_range(i32::MIN as i128, i32::MAX as i128),
+        "short" => in_range(i16::MIN as i128, i16::MAX as i128),
+        "byte" => in_range(i8::MIN as i128, i8::MAX as i128),
+        "nonNegativeInteger" => in_range(0, i128::MAX),
+        "positiveInteger" => in_range(1, i128::MAX),
+        "nonPositiveInteger" => in_range(i128::MIN, 0),
+        "negativeInteger" => in_range(i128::MIN, -1),
+        "unsignedLong" => in_range(0, u64::MAX as i128),
+        "unsignedInt" => in_range(0, u32::MAX as i128),
+        "unsignedShort" => in_range(0, u16::MAX as i128),
+        "unsignedByte" => in_range(0, u8::MAX as i128),
+        "decimal" => decimal(),
+        "double" | "float" => floating(),
+        "boolean" => ["true", "false", "1", "0"].contains(&value),
+        "date" => NaiveDate::parse_from_str(without_timezone(value), "%Y-%m-%d").is_ok(),
+        "dateTime" => NaiveDateTime::parse_from_str(without_timezone(value), "%Y-%m-%dT%H:%M:%S%.f").is_ok(),
+        "time" => NaiveTime::parse_from_str(without_timezone(value), "%H:%M:%S%.f").is_ok(),
+        _ => true,
+    }
+}
+
+/// The date or time without its timezone, `Z` or an offset like `+01:00`.
+fn without_timezone(value: &str) -> &str {
+    if let Some(local) = value.strip_suffix('Z') {
+        return local;
+    }
+    let bytes = value.as_bytes();
+    let n = bytes.len();
+    if n > 6 && (bytes[n - 6] == b'+' || bytes[n - 6] == b'-') && bytes[n - 3] == b':' {
+        &value[..n - 6]
+    } else {
+        value
+    }
+}
+
+fn is_numeric(literal: &Literal) -> bool {
+    const NUMERIC: [&str; 17] = [
+        "integer", "decimal", "double", "float", "long", "int", "short", "byte",
+        "nonNegativeInteger", "positiveInteger", "negativeInteger", "nonPositiveInteger",
+        "unsignedLong", "unsignedInt", "unsignedShort", "unsignedByte", "numeric",
+    ];
+    literal.datatype().as_str()
+        .strip_prefix("http://www.w3.org/2001/XMLSchema#")
+        .map_or(false, |local| NUMERIC.contains(&local))
+}
+
+/// Numbers compare as numbers; other literals only compare with literals of the same datatype,
+/// by lexical form, which orders the ISO 8601 dates and times of `xsd:date` and `xsd:dateTime`.
+fn compare(a: &Term, b: &Term) -> Option<Ordering> {
+    match (a, b) {
+        (Term::Literal(a), Term::Literal(b)) if is_numeric(a) && is_numeric(b) => {
+            let a: f64 = a.value().parse().ok()?;
+            let b: f64 = b.value().parse().ok()?;
+            a.partial_cmp(&b)
+        }
+        (Term::Literal(a), Term::Literal(b)) if a.datatype() == b.datatype() && a.language() == b.language() => {
+            Some(a.value().cmp(b.value()))
+        }
+        _ => None,
+    }
+}
+
+fn as_subject(term: &Term) -> Option<NamedOrBlankNode> {
+    match term {
+        Term::NamedNode(n) => Some(n.clone().into()),
+        Term::BlankNode(b) => Some(b.clone().into()),
+        Term::Literal(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use oxigraph::io::{GraphFormat, GraphParser};
+    use std::io::Cursor;
+
+    fn parse(ttl: &str) -> Vec<Triple> {
+        GraphParser::from_format(GraphFormat::Turtle)
+            .read_triples(Cursor::new(ttl.as_bytes())).unwrap()
+            .collect::<Result<Vec<_>, _>>().unwrap()
+    }
+
+    const SHAPES: &str = r#"
+        @prefix sh: <http://www.w3.org/ns/shacl#> .
+        @prefix xsd: <http://www.w3.org/2001/XMLSchema#> .
+        @prefix ex: <http://example.com/> .
+
+        ex:PersonShape a sh:NodeShape ;
+            sh:targetClass ex:Person ;
+            sh:property [
+                sh:path ex:name ;
+                sh:minCount 1 ;
+                sh:maxCount 1 ;
+                sh:datatype xsd:string ;
+            ] ;
+            sh:property [
+                sh:path ex:age ;
+                sh:minInclusive 0 ;
+            ] ;
+            sh:property [
+                sh:path ( ex:knows ex:name ) ;
+                sh:pattern "^[A-Z]" ;
+            ] .
+    "#;
+
+    #[test]
+    fn test_conforming_data() {
+        let data = parse(r#"
+            @prefix ex: <http://example.com/> .
+            ex:alice a ex:Person ; ex:name "Alice" ; ex:age 30 ; ex:knows ex:bob .
+            ex:bob ex:name "Bob" .
+        "#);
+
+        let report = validate(&data, &parse(SHAPES));
+        assert!(report.conforms(), "{:?}", report.results);
+    }
+
+    #[test]
+    fn test_violations() {
+        let data = parse(r#"
+            @prefix ex: <http://example.com/> .
+            @prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .
+            ex:Student rdfs:subClassOf ex:Person .
+            ex:alice a ex:Student ; ex:age -1 ; ex:knows ex:bob .
+            ex:bob ex:name "bob" .
+        "#);
+
+        let report = validate(&data, &parse(SHAPES));
+        assert!(!report.conforms());
+
+        let mut components: Vec<String> = report.results.iter()
+            .map(|r| r.component.as_str().trim_start_matches(SH).to_owned())
+            .collect();
+        components.sort();
+        assert_eq!(vec!["MinCountConstraintComponent", "MinInclusiveConstraintComponent", "PatternConstraintComponent"], components);
+
+        let alice = Term::from(NamedNode::new_unchecked("http://example.com/alice"));
+        assert!(report.results.iter().all(|r| r.focus == alice));
+
+        let report_graph = report.to_triples();
+        assert!(report_graph.iter().any(|t| t.predicate == sh("conforms")
+            && t.object == Literal::new_typed_literal("false", xsd::BOOLEAN).into()));
+    }
+
+    #[test]
+    fn test_closed_and_node_kind() {
+        let shapes = parse(r#"
+            @prefix sh: <http://www.w3.org/ns/shacl#> .
+            @prefix ex: <http://example.com/> .
+            @prefix rdf: <http://www.w3.org/1999/02/22-rdf-syntax-ns#> .
+            ex:Shape a sh:NodeShape ;
+                sh:targetNode ex:a ;
+                sh:closed true ;
+                sh:ignoredProperties ( rdf:type ) ;
+                sh:property [ sh:path ex:p ; sh:nodeKind sh:IRI ] .
+        "#);
+        let data = parse(r#"
+            @prefix ex: <http://example.com/> .
+            ex:a a ex:Thing ; ex:p "literal" ; ex:q ex:b .
+        "#);
+
+        let report = validate(&data, &shapes);
+        let mut components: Vec<String> = report.results.iter()
+            .map(|r| r.component.as_str().trim_start_matches(SH).to_owned())
+            .collect();
+        components.sort();
+        assert_eq!(vec!["ClosedConstraintComponent", "NodeKindConstraintComponent"], components);
+    }
+
+    fn components(report: &Report) -> Vec<String> {
+        let mut components: Vec<String> = report.results.iter()
+            .map(|r| r.component.as_str().trim_start_matches(SH).to_owned())
+            .collect();
+        components.sort();
+        components
+    }
+
+    #[test]
+    fn test_ill_typed_literals() {
+        let shapes = parse(r#"
+            @prefix sh: <http://www.w3.org/ns/shacl#> .
+            @prefix xsd: <http://www.w3.org/2001/XMLSchema#> .
+            @prefix ex: <http://example.com/> .
+            ex:Shape a sh:NodeShape ;
+                sh:targetSubjectsOf ex:n ;
+                sh:property [ sh:path ex:n ; sh:datatype xsd:integer ] ;
+                sh:property [ sh:path ex:d ; sh:datatype xsd:date ] .
+        "#);
+        let data = parse(r#"
+            @prefix xsd: <http://www.w3.org/2001/XMLSchema#> .
+            @prefix ex: <http://example.com/> .
+            ex:a ex:n "42"^^xsd:integer ; ex:d "2021-02-14Z"^^xsd:date .
+            ex:b ex:n "abc"^^xsd:integer ; ex:d "2021-02-30"^^xsd:date .
+        "#);
+
+        let report = validate(&data, &shapes);
+        assert_eq!(vec!["DatatypeConstraintComponent", "DatatypeConstraintComponent"], components(&report));
+        assert!(report.results.iter().all(|r| r.focus == Term::from(NamedNode::new_unchecked("http://example.com/b"))));
+    }
+
+    #[test]
+    fn test_qualified_value_shape() {
+        let shapes = parse(r#"
+            @prefix sh: <http://www.w3.org/ns/shacl#> .
+            @prefix ex: <http://example.com/> .
+            ex:HandShape a sh:NodeShape ;
+                sh:targetNode ex:hand ;
+                sh:property [
+                    sh:path ex:digit ;
+                    sh:qualifiedValueShape [ sh:class ex:Thumb ] ;
+                    sh:qualifiedValueShapesDisjoint true ;
+                    sh:qualifiedMinCount 1 ;
+                    sh:qualifiedMaxCount 1 ;
+                ] ;
+                sh:property [
+                    sh:path ex:digit ;
+                    sh:qualifiedValueShape [ sh:class ex:Finger ] ;
+                    sh:qualifiedValueShapesDisjoint true ;
+                    sh:qualifiedMinCount 4 ;
+                    sh:qualifiedMaxCount 4 ;
+                ] .
+        "#);
+        let data = parse(r#"
+            @prefix ex: <http://example.com/> .
+            ex:hand ex:digit ex:thumb, ex:f1, ex:f2, ex:f3, ex:both .
+            ex:thumb a ex:Thumb .
+            ex:f1 a ex:Finger . ex:f2 a ex:Finger . ex:f3 a ex:Finger .
+            ex:both a ex:Thumb, ex:Finger .
+        "#);
+
+        // ex:both conforms to both qualified shapes, so it counts for neither
+        assert_eq!(vec!["QualifiedMinCountConstraintComponent"], components(&validate(&data, &shapes)));
+    }
+
+    #[test]
+    fn test_recursive_shapes_over_cycles() {
+        let shapes = parse(r#"
+            @prefix sh: <http://www.w3.org/ns/shacl#> .
+            @prefix ex: <http://example.com/> .
+            ex:PersonShape a sh:NodeShape ;
+                sh:targetClass ex:Person ;
+                sh:property [ sh:path ex:knows ; sh:node ex:PersonShape ] ;
+                sh:property [ sh:path ex:likes ; sh:node ex:PersonShape ] .
+        "#);
+        // Every person knows and likes every other, which is 2^32 paths to follow without
+        // noticing the cycles
+        let people = ["a", "b", "c", "d"];
+        let data: String = people.iter()
+            .flat_map(|p| people.iter().map(move |q| format!("ex:{} a ex:Person ; ex:knows ex:{} ; ex:likes ex:{} .\n", p, q, q)))
+            .collect();
+        let data = parse(&format!("@prefix ex: <http://example.com/> .\n{}", data));
+
+        assert!(validate(&data, &shapes).conforms());
+    }
+
+    #[test]
+    fn test_recursive_shapes_are_reported() {
+        let shapes = parse(r#"
+            @prefix sh: <http://www.w3.org/ns/shacl#> .
+            @prefix ex: <http://example.com/> .
+            ex:ChainShape a sh:NodeShape ;
+                sh:property [ sh:path ex:next ; sh:node ex:ChainShape ] .
+            ex:NotChainShape a sh:NodeShape ;
+                sh:targetNode ex:n0 ;
+                sh:not ex:ChainShape .
+        "#);
+        let data: String = (0..=MAX_DEPTH + 1).map(|i| format!("ex:n{} ex:next ex:n{} .\n", i, i + 1)).collect();
+        let data = parse(&format!("@prefix ex: <http://example.com/> .\n{}", data));
+
+        let report = validate(&data, &shapes);
+        assert!(!report.conforms());
+        let too_deep: Vec<_> = report.results.iter().filter(|r| r.component == sh("NodeConstraintComponent") && !r.messages.is_empty()).collect();
+        assert_eq!(1, too_deep.len());
+    }
+}
@@ -0,0 +1,400 @@
+//! SPARQL queries over the store.
+//!
+//! Queries are evaluated by oxigraph. A query made by someone who may only read some graphs is
+//! evaluated over those graphs alone: its `FROM` and `FROM NAMED` graphs are cut down to them,
+//! and without `FROM NAMED`, `GRAPH ?g` only ranges over them.
+//!
+//! Queries are evaluated on the fixed set of `Limits::query_workers` threads, and turned away
+//! when those are all busy. A request stops waiting for its query once the query timeout is up,
+//! and is answered with a timeout. The query itself can't be interrupted inside oxigraph, which
+//! works out everything for `ORDER BY`, `GROUP BY`, `DISTINCT` and some joins before the first
+//! solution, so it keeps its thread until the next solution or triple is asked for, when the
+//! deadline is checked and it stops. Until then the thread isn't free for another query.
+//!
+//! Queries can filter by the full-text search index with `meta:textMatch(?x, "text")`, which holds
+//! for the resources `/search` finds for the text. oxigraph has no custom functions, so each call
+//! is rewritten into `?x IN (...)` listing those resources. The rewrite is done on the parsed
+//! query as oxigraph writes it back out, where prefixes and base IRIs are resolved, comments are
+//! gone and every string is escaped, so neither a redefined prefix nor a string that looks like a
+//! call can fool it. Text matching more than `MAX_TEXT_MATCHES` resources is refused.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+use std::rc::Rc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+
+use regex::{Captures, Regex};
+
+use oxigraph::SledStore as Store;
+use oxigraph::model::{GraphName, NamedNode, NamedOrBlankNode};
+use oxigraph::sparql::{EvaluationError, Query, QueryOptions, QueryResults, QueryResultsFormat, QuerySolutionIter};
+
+use crate::api::limits::Limits;
+use crate::api::search::SearchIndex;
+use crate::api::storage::triples_as_ttl_string;
+use crate::meta;
+
+/// At most this many resources are listed for one `meta:textMatch`.
+pub const MAX_TEXT_MATCHES: usize = 1_000;
+
+lazy_static! {
+    /// A call of `meta:textMatch` with a variable and a plain string, as oxigraph writes it out.
+    static ref TEXT_MATCH: Regex = Regex::new(&format!(
+        r#"<{}>\s*\(\s*([?$]\w+)\s*,\s*"((?:[^"\\]|\\.)*)"\s*\)"#,
+        regex::escape(NamedNode::from(meta::textMatch).as_str()),
+    )).unwrap();
+}
+
+/// A query's results, serialized: solutions and booleans as SPARQL JSON results, graphs from
+/// `CONSTRUCT` and `DESCRIBE` as Turtle.
+#[derive(Debug, PartialEq)]
+pub enum Answer {
+    Solutions(String),
+    Graph(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum QueryError {
+    /// The query couldn't be parsed or evaluated.
+    Failed(String),
+    /// The query was still running when its time was up.
+    TimedOut(Duration),
+    /// Every query thread was busy.
+    Busy,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueryError::Failed(e) => write!(f, "{}", e),
+            QueryError::TimedOut(timeout) => write!(f, "Query took longer than {}s", timeout.as_secs_f64()),
+            QueryError::Busy => write!(f, "Too many queries are running, try again later"),
+        }
+    }
+}
+
+/// Evaluates `query` on one of the query threads of `limits`, over only the `readable` graphs if
+/// given, waiting no longer than the query timeout.
+pub fn query(store: &Store, index: &SearchIndex, query: &str, readable: Option<&HashSet<NamedNode>>, limits: &Limits) -> Result<Answer, QueryError> {
+    let query = rewrite_text_matches(query, |text| index.matching_resources(store, text, readable)).map_err(QueryError::Failed)?;
+    let timeout = limits.query_timeout;
+    let deadline = timeout.map(|timeout| (Instant::now() + timeout, timeout));
+
+    // The query is parsed on the worker too, as a parsed query can't be sent between threads
+    let (sender, receiver) = mpsc::channel();
+    let store = store.clone();
+    let readable = readable.cloned();
+    let running = limits.query_workers.try_run(move || {
+        // Nobody is listening any more if the query timed out
+        let _ = sender.send(evaluate(&store, &query, readable.as_ref(), deadline));
+    });
+    if !running {
+        return Err(QueryError::Busy);
+    }
+
+    let failed = || Err(QueryError::Failed("The query failed unexpectedly".to_owned()));
+    match timeout {
+        Some(timeout) => match receiver.recv_timeout(timeout) {
+            Ok(answer) => answer,
+            Err(RecvTimeoutError::Timeout) => Err(QueryError::TimedOut(timeout)),
+            Err(RecvTimeoutError::Disconnected) => failed(),
+        },
+        None => receiver.recv().unwrap_or_else(|_| failed()),
+    }
+}
+
+/// Evaluates `query` and serializes its results, stopping if it is still going at the
+/// `deadline` given with its timeout.
+fn evaluate(store: &Store, query: &str, readable: Option<&HashSet<NamedNode>>, deadline: Option<(Instant, Duration)>) -> Result<Answer, QueryError> {
+    let failed = |e: &dyn fmt::Display| QueryError::Failed(e.to_string());
+    let mut query = Query::parse(query, None).map_err(|e| failed(&e))?;
+    if let Some(readable) = readable {
+        restrict(&mut query, readable);
+    }
+
+    let is_late = move || deadline.map_or(false, |(d, _)| Instant::now() >= d);
+    let late_or_failed = |e: &dyn fmt::Display| match deadline {
+        Some((_, timeout)) if is_late() => QueryError::TimedOut(timeout),
+        _ => failed(e),
+    };
+
+    let results = store.query(query, QueryOptions::default()).map_err(|e| late_or_failed(&e))?;
+    if is_late() {
+        return Err(late_or_failed(&"timed out"));
+    }
+    match results {
+        QueryResults::Graph(triples) => {
+            let mut collected = Vec::new();
+            for triple in triples {
+                if is_late() {
+                    return Err(late_or_failed(&"timed out"));
+                }
+                collected.push(triple.map_err(|e| late_or_failed(&e))?);
+            }
+            triples_as_ttl_string(collected).map(Answer::Graph).map_err(|e| failed(&e))
+        }
+        QueryResults::Solutions(solutions) => {
+            let variables = Rc::new(solutions.variables().to_vec());
+            let names = variables.clone();
+            let cancellable = solutions.map(move |solution| {
+                if is_late() {
+                    return Err(EvaluationError::from(io::Error::new(io::ErrorKind::TimedOut, "timed out")));
+                }
+                solution.map(|s| names.iter().map(|v| s.get(v.as_str()).cloned()).collect())
+            });
+
+            let mut buffer = Vec::new();
+            QueryResults::Solutions(QuerySolutionIter::new(variables, Box::new(cancellable)))
+                .write(&mut buffer, QueryResultsFormat::Json)
+                .map_err(|e| late_or_failed(&e))?;
+            String::from_utf8(buffer).map(Answer::Solutions).map_err(|e| failed(&e))
+        }
+        results => {
+            let mut buffer = Vec::new();
+            results.write(&mut buffer, QueryResultsFormat::Json).map_err(|e| failed(&e))?;
+            String::from_utf8(buffer).map(Answer::Solutions).map_err(|e| failed(&e))
+        }
+    }
+}
+
+/// Rewrites each `meta:textMatch(?x, "text")` in `query` into a test of `?x` against the
+/// resources `matching` gives for the text. Other function calls are left alone. Fails if the
+/// query doesn't parse, or the text matches more than `MAX_TEXT_MATCHES` resources.
+fn rewrite_text_matches(query: &str, matching: impl Fn(&str) -> Vec<NamedNode>) -> Result<String, String> {
+    let written = Query::parse(query, None).map_err(|e| e.to_string())?.to_string();
+    if !TEXT_MATCH.is_match(&written) {
+        return Ok(query.to_owned());
+    }
+
+    let mut too_many = None;
+    let rewritten = TEXT_MATCH.replace_all(&written, |c: &Captures| {
+        let text = unescape(&c[2]);
+        let resources = matching(&text);
+        if resources.len() > MAX_TEXT_MATCHES {
+            too_many = Some(format!("textMatch for \"{}\" matches more than {} resources", text, MAX_TEXT_MATCHES));
+            return String::new();
+        }
+        let resources: Vec<String> = resources.iter().map(NamedNode::to_string).collect();
+        if resources.is_empty() {
+            "false".to_owned()
+        } else {
+            format!("({} IN ({}))", &c[1], resources.join(", "))
+        }
+    }).into_owned();
+
+    match too_many {
+        Some(e) => Err(e),
+        None => Ok(rewritten),
+    }
+}
+
+/// The value of a SPARQL string literal from between its quotes.
+fn unescape(literal: &str) -> String {
+    let mut value = String::with_capacity(literal.len());
+    let mut chars = literal.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => value.push('\n'),
+            Some('t') => value.push('\t'),
+            Some('r') => value.push('\r'),
+            Some(escaped) => value.push(escaped),
+            None => {}
+        }
+    }
+    value
+}
+
+/// Cuts the dataset of `query` down to the `readable` graphs. The store's default graph isn't
+/// registered in `<Meta>` and only admins may read it, as with `/store`, so without a `FROM` the
+/// default graph is empty.
+fn restrict(query: &mut Query, readable: &HashSet<NamedNode>) {
+    let is_readable = |g: &GraphName| match g {
+        GraphName::NamedNode(n) => readable.contains(n),
+        GraphName::DefaultGraph | GraphName::BlankNode(_) => false,
+    };
+    let default_graph: Vec<GraphName> = match query.dataset().default_graph_graphs() {
+        Some(graphs) => graphs.iter().filter(|g| is_readable(g)).cloned().collect(),
+        None => Vec::new(),
+    };
+    let named_graphs: Vec<NamedOrBlankNode> = match query.dataset().available_named_graphs() {
+        Some(graphs) => graphs.iter()
+            .filter(|g| match g {
+                NamedOrBlankNode::NamedNode(n) => readable.contains(n),
+                NamedOrBlankNode::BlankNode(_) => false,
+            })
+            .cloned()
+            .collect(),
+        None => readable.iter().cloned().map(NamedOrBlankNode::from).collect(),
+    };
+
+    let dataset = query.dataset_mut();
+    dataset.set_default_graph(default_graph);
+    dataset.set_available_named_graphs(named_graphs);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use oxigraph::model::{Literal, Quad, Triple};
+    use oxigraph::model::vocab::{rdf, rdfs};
+    use crate::api::GraphType;
+    use crate::api::storage::load_triples_into_new_graph;
+    use crate::api::storage::test::init_store;
+
+    #[test]
+    fn test_restricted_query() {
+        let (s, _f) = init_store();
+        let open = NamedNode::new_unchecked("http://example.com/open");
+        let closed = NamedNode::new_unchecked("http://example.com/closed");
+        for graph in [&open, &closed].iter() {
+            let triple = Triple::new(NamedNode::new_unchecked(format!("{}#thing", graph.as_str())), rdf::TYPE, NamedNode::new_unchecked("http://example.com/Thing"));
+            load_triples_into_new_graph(&s, (*graph).clone(), GraphType::Model, vec![triple]);
+        }
+        let select = "SELECT ?s WHERE { GRAPH ?g { ?s a <http://example.com/Thing> } }";
+
+        let everything = query(&s, &SearchIndex::default(), select, None, &Limits::default()).unwrap();
+        let readable: HashSet<NamedNode> = vec![open.clone()].into_iter().collect();
+        let restricted = query(&s, &SearchIndex::default(), select, Some(&readable), &Limits::default()).unwrap();
+        match (everything, restricted) {
+            (Answer::Solutions(everything), Answer::Solutions(restricted)) => {
+                assert!(everything.contains("http://example.com/closed#thing"));
+                assert!(restricted.contains("http://example.com/open#thing"));
+                assert!(!restricted.contains("http://example.com/closed#thing"));
+            }
+            _ => panic!("SELECT should have solutions"),
+        }
+
+        let from = "SELECT ?s FROM <http://example.com/closed> WHERE { ?s ?p ?o }";
+        match query(&s, &SearchIndex::default(), from, Some(&readable), &Limits::default()).unwrap() {
+            Answer::Solutions(restricted) => assert!(!restricted.contains("http://example.com/closed#thing")),
+            _ => panic!("SELECT should have solutions"),
+        }
+
+        let construct = "CONSTRUCT { ?s a ?c } WHERE { GRAPH <http://example.com/open> { ?s a ?c } }";
+        assert!(matches!(query(&s, &SearchIndex::default(), construct, Some(&readable), &Limits::default()).unwrap(), Answer::Graph(_)));
+    }
+
+    #[test]
+    fn test_default_graph_is_not_readable() {
+        let (s, _f) = init_store();
+        let secret = Quad::new(NamedNode::new_unchecked("http://example.com/secret"), rdf::TYPE, NamedNode::new_unchecked("http://example.com/Thing"), GraphName::DefaultGraph);
+        s.insert(secret.as_ref()).unwrap();
+        let select = "SELECT ?s WHERE { ?s ?p ?o }";
+        let readable: HashSet<NamedNode> = HashSet::new();
+
+        match query(&s, &SearchIndex::default(), select, None, &Limits::default()).unwrap() {
+            Answer::Solutions(everything) => assert!(everything.contains("http://example.com/secret")),
+            _ => panic!("SELECT should have solutions"),
+        }
+        match query(&s, &SearchIndex::default(), select, Some(&readable), &Limits::default()).unwrap() {
+            Answer::Solutions(restricted) => assert!(!restricted.contains("http://example.com/secret")),
+            _ => panic!("SELECT should have solutions"),
+        }
+    }
+
+    #[test]
+    fn test_timeout() {
+        let (s, _f) = init_store();
+        let select = "SELECT ?s WHERE { GRAPH ?g { ?s ?p ?o } }";
+
+        let limits = |timeout: Option<Duration>| Limits::new(0, 0, timeout, 1);
+
+        assert!(query(&s, &SearchIndex::default(), select, None, &limits(Some(Duration::from_secs(60)))).is_ok());
+        assert_eq!(Err(QueryError::TimedOut(Duration::from_secs(0))), query(&s, &SearchIndex::default(), select, None, &limits(Some(Duration::from_secs(0)))));
+        assert!(matches!(query(&s, &SearchIndex::default(), "SELECT nonsense", None, &limits(None)), Err(QueryError::Failed(_))));
+        assert_eq!(Err(QueryError::Busy), query(&s, &SearchIndex::default(), select, None, &Limits::new(0, 0, None, 0)));
+    }
+
+    #[test]
+    fn test_timeout_before_first_solution() {
+        let (s, _f) = init_store();
+        let graph = NamedNode::new_unchecked("http://example.com/numbers");
+        let triples = (0..200)
+            .map(|i| Triple::new(NamedNode::new_unchecked(format!("http://example.com/{}", i)), rdf::VALUE, Literal::from(i)))
+            .collect();
+        load_triples_into_new_graph(&s, graph, GraphType::Model, triples);
+
+        // A count over a three-way cross product of the graph: eight million solutions to go
+        // through before there is a single result.
+        let expensive = r#"
+            SELECT (COUNT(*) AS ?n) WHERE {
+                GRAPH <http://example.com/numbers> { ?a ?p ?b }
+                GRAPH <http://example.com/numbers> { ?c ?q ?d }
+                GRAPH <http://example.com/numbers> { ?e ?r ?f }
+            }"#;
+        let timeout = Duration::from_millis(100);
+        let limits = Limits::new(0, 0, Some(timeout), 1);
+        let started = Instant::now();
+        assert_eq!(Err(QueryError::TimedOut(timeout)), query(&s, &SearchIndex::default(), expensive, None, &limits));
+        assert!(started.elapsed() < Duration::from_secs(2), "took {:?}", started.elapsed());
+
+        // Its thread is still counting, so there is none for another query
+        assert_eq!(Err(QueryError::Busy), query(&s, &SearchIndex::default(), "ASK {}", None, &limits));
+    }
+
+    #[test]
+    fn test_text_match() {
+        let (s, _f) = init_store();
+        let graph = NamedNode::new_unchecked("http://example.com/go");
+        let term = |id: &str| NamedNode::new_unchecked(format!("http://example.com/{}", id));
+        load_triples_into_new_graph(&s, graph, GraphType::Model, vec![
+            Triple::new(term("cycle"), rdfs::LABEL, Literal::new_simple_literal("cell cycle")),
+            Triple::new(term("death"), rdfs::LABEL, Literal::new_simple_literal("apoptosis")),
+        ]);
+        let index = SearchIndex::build(&s);
+
+        let select = r#"
+            PREFIX janus: <http://www.purl.org/dougli1sqrd/models/janus-oxide/Meta/>
+            SELECT ?s WHERE { GRAPH ?g { ?s ?p ?label } FILTER(janus:textMatch(?s, "Cell")) }"#;
+        match query(&s, &index, select, None, &Limits::default()).unwrap() {
+            Answer::Solutions(results) => {
+                assert!(results.contains("http://example.com/cycle"));
+                assert!(!results.contains("http://example.com/death"));
+            }
+            _ => panic!("SELECT should have solutions"),
+        }
+
+        let nothing = r#"SELECT ?s WHERE { ?s ?p ?o FILTER(<http://www.purl.org/dougli1sqrd/models/janus-oxide/Meta/textMatch>(?s, 'mitosis')) }"#;
+        let rewritten = rewrite_text_matches(nothing, |_| Vec::new()).unwrap();
+        assert!(rewritten.contains("false") && !rewritten.contains("textMatch"));
+        let other = r#"SELECT ?s WHERE { ?s ?p ?o FILTER(<http://example.com/f>(?s, "x")) }"#;
+        assert_eq!(other, rewrite_text_matches(other, |_| vec![term("cycle")]).unwrap());
+
+        let too_many = |_: &str| (0..=MAX_TEXT_MATCHES).map(|i| term(&i.to_string())).collect();
+        assert!(rewrite_text_matches(nothing, too_many).is_err());
+    }
+
+    #[test]
+    fn test_text_match_follows_the_parsed_query() {
+        let cycle = |_: &str| vec![NamedNode::new_unchecked("http://example.com/cycle")];
+
+        // The prefix is redefined before it is used, and the call in the comment isn't one
+        let redefined = r#"
+            PREFIX janus: <http://example.com/not-janus/>
+            PREFIX janus: <http://www.purl.org/dougli1sqrd/models/janus-oxide/Meta/>
+            # janus:textMatch(?s, "comment")
+            SELECT ?s WHERE { ?s ?p ?o FILTER(janus:textMatch(?s, "cell")) }"#;
+        let rewritten = rewrite_text_matches(redefined, cycle).unwrap();
+        assert!(rewritten.contains("<http://example.com/cycle>") && !rewritten.contains("textMatch"));
+
+        // A relative IRI resolved against the base
+        let based = r#"
+            BASE <http://www.purl.org/dougli1sqrd/models/janus-oxide/Meta/>
+            SELECT ?s WHERE { ?s ?p ?o FILTER(<textMatch>(?s, "cell")) }"#;
+        assert!(!rewrite_text_matches(based, cycle).unwrap().contains("textMatch"));
+
+        // A string that reads like a call is only a string
+        let quoted = r#"
+            PREFIX janus: <http://www.purl.org/dougli1sqrd/models/janus-oxide/Meta/>
+            SELECT ?s WHERE { ?s ?p "janus:textMatch(?s, \"cell\")" }"#;
+        assert_eq!(quoted, rewrite_text_matches(quoted, cycle).unwrap());
+    }
+}
@@ -0,0 +1,491 @@
+//! RDF-star quoted triples, `<< :s :p :o >>`, on top of a store that only knows plain RDF.
+//!
+//! On upload, every quoted triple in Turtle-star or N-Triples-star is replaced by a node that
+//! reifies it, so that
+//!
+//! ```turtle
+//! << :alice :knows :bob >> :source :survey .
+//! ```
+//!
+//! is stored as
+//!
+//! ```turtle
+//! <quoted/…> :source :survey .
+//! <quoted/…> rdf:subject :alice ; rdf:predicate :knows ; rdf:object :bob .
+//! ```
+//!
+//! The node is named in `meta::QUOTED_TRIPLE_NAMESPACE` after a hash of the triple it quotes, so
+//! the same quoted triple gets the same node however its terms are written, upload after upload.
+//! A quoted triple with a blank node in it only gets the same node within one upload, since the
+//! blank node is only the same there.
+//!
+//! The rewrite leaves the terms of a quoted triple as they were written and reifies it right after
+//! the statement it's in, so the Turtle parser resolves them against the prefixes and base in
+//! force there. The nodes are only named once the document is parsed.
+//!
+//! On export as Turtle-star or N-Triples-star, the nodes in that namespace that reify exactly one
+//! triple, and that are used in some other triple, are written back as quoted triples.
+//! Reifications written by hand are left as they are.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use oxigraph::model::{BlankNode, NamedNode, NamedOrBlankNode, Term, Triple};
+use oxigraph::model::vocab::rdf;
+
+use crate::meta;
+use crate::api::canon::{literal_string, ntriples_line, sha256_hex};
+
+/// Whether `document` might hold quoted triples. A `<<` inside a literal is a false positive,
+/// which only costs a rewrite that changes nothing.
+pub fn has_quoted_triples(document: &str) -> bool {
+    document.contains("<<")
+}
+
+/// Turtle-star rewritten as plain Turtle, in which each quoted triple is a placeholder node.
+pub struct Unstarred {
+    pub turtle: String,
+    /// The prefix of the placeholders, which are numbered from there.
+    placeholders: String,
+}
+
+impl Unstarred {
+    /// The parsed `turtle`, with each placeholder replaced by the node named for the triple it
+    /// quotes. A quoted triple that isn't a triple, like one with a literal subject, is an error.
+    pub fn name_quoted(&self, triples: Vec<Triple>) -> Result<Vec<Triple>, String> {
+        let mut parts: BTreeMap<usize, (Option<NamedOrBlankNode>, Option<NamedNode>, Option<Term>)> = BTreeMap::new();
+        for t in triples.iter() {
+            let number = match &t.subject {
+                NamedOrBlankNode::NamedNode(n) => n.as_str().strip_prefix(self.placeholders.as_str()).and_then(|n| n.parse().ok()),
+                NamedOrBlankNode::BlankNode(_) => None,
+            };
+            let entry = match number {
+                Some(number) => parts.entry(number).or_default(),
+                None => continue,
+            };
+            let predicate = t.predicate.as_ref();
+            if predicate == rdf::SUBJECT {
+                entry.0 = match &t.object {
+                    Term::NamedNode(n) => Some(n.clone().into()),
+                    Term::BlankNode(b) => Some(b.clone().into()),
+                    Term::Literal(_) => None,
+                };
+            } else if predicate == rdf::PREDICATE {
+                entry.1 = match &t.object {
+                    Term::NamedNode(n) => Some(n.clone()),
+                    _ => None,
+                };
+            } else if predicate == rdf::OBJECT {
+                entry.2 = Some(t.object.clone());
+            }
+        }
+
+        // A quoted triple inside another is numbered, and so named, before it
+        let mut names: HashMap<NamedNode, NamedNode> = HashMap::new();
+        for (number, parts) in parts {
+            let placeholder = NamedNode::new_unchecked(format!("{}{}", self.placeholders, number));
+            let triple = match parts {
+                (Some(s), Some(p), Some(o)) => Triple::new(rename_subject(s, &names), p, rename_object(o, &names)),
+                _ => return Err(format!("Quoted triple {} is not a valid triple", number + 1)),
+            };
+            let line = ntriples_line(&triple, &|b: &BlankNode| format!("_:{}", b.as_str()));
+            let name = NamedNode::new_unchecked(format!("{}{}", meta::QUOTED_TRIPLE_NAMESPACE, sha256_hex(&line)));
+            names.insert(placeholder, name);
+        }
+
+        let mut seen = HashSet::new();
+        Ok(triples.into_iter()
+            .map(|t| Triple::new(rename_subject(t.subject, &names), t.predicate, rename_object(t.object, &names)))
+            .filter(|t| seen.insert(t.clone()))
+            .collect())
+    }
+}
+
+fn rename_subject(subject: NamedOrBlankNode, names: &HashMap<NamedNode, NamedNode>) -> NamedOrBlankNode {
+    match subject {
+        NamedOrBlankNode::NamedNode(n) => names.get(&n).cloned().unwrap_or(n).into(),
+        b => b,
+    }
+}
+
+fn rename_object(object: Term, names: &HashMap<NamedNode, NamedNode>) -> Term {
+    match object {
+        Term::NamedNode(n) => names.get(&n).cloned().unwrap_or(n).into(),
+        o => o,
+    }
+}
+
+/// Rewrites Turtle-star (or N-Triples-star) into plain Turtle, reifying each quoted triple with a
+/// placeholder node until `Unstarred::name_quoted` names it. Quoted triples that aren't exactly
+/// three terms are left as they are for the Turtle parser to report.
+pub fn unstar_turtle(document: &str) -> Unstarred {
+    let mut rewriter = Rewriter {
+        // Unique to this rewrite, so that no placeholder can be in the document already
+        placeholders: format!("{}pending/{}/", meta::QUOTED_TRIPLE_NAMESPACE, BlankNode::default().as_str()),
+        quoted: 0,
+        pending: Vec::new(),
+    };
+    let (mut turtle, _) = rewriter.rewrite(document, 0, false);
+    rewriter.flush(&mut turtle);
+    Unstarred { turtle, placeholders: rewriter.placeholders }
+}
+
+struct Rewriter {
+    placeholders: String,
+    quoted: usize,
+    /// The reifications of the quoted triples in the statement being read.
+    pending: Vec<String>,
+}
+
+impl Rewriter {
+    /// Copies `document` from `start`, replacing quoted triples. When `nested`, stops after the
+    /// `>>` closing the quoted triple being read, returning the position after it.
+    fn rewrite(&mut self, document: &str, start: usize, nested: bool) -> (String, usize) {
+        let bytes = document.as_bytes();
+        let mut out = String::new();
+        let mut i = start;
+        while i < bytes.len() {
+            let rest = &document[i..];
+            if rest.starts_with("<<") {
+                let (inner, end) = self.rewrite(document, i + 2, true);
+                out.push(' ');
+                out.push_str(&self.quote(&inner));
+                out.push(' ');
+                i = end;
+            } else if nested && rest.starts_with(">>") {
+                return (out, i + 2);
+            } else if !nested && is_end_of_statement(rest) {
+                out.push('.');
+                self.flush(&mut out);
+                i += 1;
+            } else if rest.starts_with("\"\"\"") || rest.starts_with("'''") {
+                let end = skip_string(document, i, &rest[..3]);
+                out.push_str(&document[i..end]);
+                i = end;
+            } else if rest.starts_with('"') || rest.starts_with('\'') {
+                let end = skip_string(document, i, &rest[..1]);
+                out.push_str(&document[i..end]);
+                i = end;
+            } else if rest.starts_with('<') {
+                let end = rest.find('>').map_or(bytes.len(), |e| i + e + 1);
+                out.push_str(&document[i..end]);
+                i = end;
+            } else if rest.starts_with('#') {
+                let end = rest.find('\n').map_or(bytes.len(), |e| i + e);
+                out.push_str(&document[i..end]);
+                i = end;
+            } else {
+                let c = rest.chars().next().unwrap();
+                out.push(c);
+                i += c.len_utf8();
+            }
+        }
+        (out, i)
+    }
+
+    /// The placeholder standing for the quoted triple `inner`, whose reification is written
+    /// after the statement it's in.
+    fn quote(&mut self, inner: &str) -> String {
+        let terms = split_terms(inner);
+        if terms.len() != 3 {
+            return format!("<<{}>>", inner);
+        }
+
+        let placeholder = format!("<{}{}>", self.placeholders, self.quoted);
+        self.quoted += 1;
+        let predicate = if terms[1] == "a" { format!("<{}>", rdf::TYPE.as_str()) } else { terms[1].clone() };
+        self.pending.push(format!(
+            "{} <{}> {} ; <{}> {} ; <{}> {} .",
+            placeholder, rdf::SUBJECT.as_str(), terms[0], rdf::PREDICATE.as_str(), predicate, rdf::OBJECT.as_str(), terms[2]));
+        placeholder
+    }
+
+    /// Writes out the pending reifications.
+    fn flush(&mut self, out: &mut String) {
+        for statement in self.pending.drain(..) {
+            out.push('\n');
+            out.push_str(&statement);
+        }
+    }
+}
+
+/// Whether `rest` starts with the `.` ending a statement. A name can't end with a `.` and a
+/// number can't either, so a `.` followed by a space, a comment or nothing ends one.
+fn is_end_of_statement(rest: &str) -> bool {
+    rest.starts_with('.') && rest[1..].chars().next().map_or(true, |c| c.is_whitespace() || c == '#')
+}
+
+/// The end of the string literal starting at `start` with the quote `quote`.
+fn skip_string(document: &str, start: usize, quote: &str) -> usize {
+    let mut i = start + quote.len();
+    while i < document.len() {
+        let rest = &document[i..];
+        if rest.starts_with('\\') {
+            i += 1 + rest[1..].chars().next().map_or(0, char::len_utf8);
+        } else if rest.starts_with(quote) {
+            return i + quote.len();
+        } else {
+            i += rest.chars().next().unwrap().len_utf8();
+        }
+    }
+    document.len()
+}
+
+/// Splits the inside of a quoted triple into its terms. A literal keeps its language tag or
+/// datatype.
+fn split_terms(inner: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut i = 0;
+    while i < inner.len() {
+        let rest = &inner[i..];
+        let c = rest.chars().next().unwrap();
+        if c.is_whitespace() {
+            i += c.len_utf8();
+            continue;
+        }
+
+        let mut end = if rest.starts_with("\"\"\"") || rest.starts_with("'''") {
+            skip_string(inner, i, &rest[..3])
+        } else if c == '"' || c == '\'' {
+            skip_string(inner, i, &rest[..1])
+        } else if c == '<' {
+            rest.find('>').map_or(inner.len(), |e| i + e + 1)
+        } else {
+            rest.find(char::is_whitespace).map_or(inner.len(), |e| i + e)
+        };
+
+        // Language tag or datatype
+        if c == '"' || c == '\'' {
+            let suffix = &inner[end..];
+            if suffix.starts_with('@') {
+                end = suffix.find(char::is_whitespace).map_or(inner.len(), |e| end + e);
+            } else if suffix.starts_with("^^<") {
+                end = suffix.find('>').map_or(inner.len(), |e| end + e + 1);
+            } else if suffix.starts_with("^^") {
+                end = suffix.find(char::is_whitespace).map_or(inner.len(), |e| end + e);
+            }
+        }
+
+        terms.push(inner[i..end].to_owned());
+        i = end;
+    }
+    terms
+}
+
+/// N-Triples-star: N-Triples with the reifications made on upload written back as quoted triples.
+pub fn to_ntriples_star(triples: &[Triple]) -> String {
+    let quoted = quoted_triples(triples);
+    let mut lines: Vec<String> = asserted(triples, &quoted)
+        .map(|t| format!("{} <{}> {} .\n", subject_string(&t.subject, &quoted, 0), t.predicate.as_str(), object_string(&t.object, &quoted, 0)))
+        .collect();
+    lines.sort();
+    lines.concat()
+}
+
+/// Turtle-star: the triples `to_ntriples_star` writes, with each subject's written once.
+pub fn to_turtle_star(triples: &[Triple]) -> String {
+    let quoted = quoted_triples(triples);
+    let mut subjects: BTreeMap<String, BTreeMap<String, Vec<String>>> = BTreeMap::new();
+    for t in asserted(triples, &quoted) {
+        let predicate = if t.predicate.as_ref() == rdf::TYPE { "a".to_owned() } else { format!("<{}>", t.predicate.as_str()) };
+        subjects.entry(subject_string(&t.subject, &quoted, 0))
+            .or_default()
+            .entry(predicate)
+            .or_default()
+            .push(object_string(&t.object, &quoted, 0));
+    }
+
+    let mut turtle = String::new();
+    for (subject, predicates) in subjects {
+        let predicates: Vec<String> = predicates.into_iter()
+            .map(|(predicate, mut objects)| {
+                objects.sort();
+                format!("{} {}", predicate, objects.join(" , "))
+            })
+            .collect();
+        turtle.push_str(&format!("{} {} .\n", subject, predicates.join(" ;\n    ")));
+    }
+    turtle
+}
+
+/// The triples that aren't one of the reifications being written back as quoted triples.
+fn asserted<'a>(triples: &'a [Triple], quoted: &'a HashMap<NamedNode, Triple>) -> impl Iterator<Item = &'a Triple> {
+    triples.iter().filter(move |t| match &t.subject {
+        NamedOrBlankNode::NamedNode(n) => !(quoted.contains_key(n) && is_reification(t)),
+        NamedOrBlankNode::BlankNode(_) => true,
+    })
+}
+
+/// Whether `triple` is one of the triples reifying its subject.
+fn is_reification(triple: &Triple) -> bool {
+    let predicate = triple.predicate.as_ref();
+    predicate == rdf::SUBJECT || predicate == rdf::PREDICATE || predicate == rdf::OBJECT
+}
+
+#[derive(Default)]
+struct Reification {
+    subjects: Vec<NamedOrBlankNode>,
+    predicates: Vec<NamedNode>,
+    objects: Vec<Term>,
+    malformed: bool,
+    used: bool,
+}
+
+/// The nodes in `triples` named for a quoted triple that reify exactly one triple and are used in
+/// some other triple, with the triple each one stands for.
+fn quoted_triples(triples: &[Triple]) -> HashMap<NamedNode, Triple> {
+    let mut reifications: HashMap<&NamedNode, Reification> = HashMap::new();
+
+    for t in triples {
+        match &t.subject {
+            NamedOrBlankNode::NamedNode(n) if n.as_str().starts_with(meta::QUOTED_TRIPLE_NAMESPACE) => {
+                let entry = reifications.entry(n).or_insert_with(Reification::default);
+                let predicate = t.predicate.as_ref();
+                if predicate == rdf::SUBJECT {
+                    match &t.object {
+                        Term::NamedNode(n) => entry.subjects.push(n.clone().into()),
+                        Term::BlankNode(n) => entry.subjects.push(n.clone().into()),
+                        Term::Literal(_) => entry.malformed = true,
+                    }
+                } else if predicate == rdf::PREDICATE {
+                    match &t.object {
+                        Term::NamedNode(n) => entry.predicates.push(n.clone()),
+                        _ => entry.malformed = true,
+                    }
+                } else if predicate == rdf::OBJECT {
+                    entry.objects.push(t.object.clone());
+                } else {
+                    entry.used = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    for t in triples {
+        if let Term::NamedNode(n) = &t.object {
+            if let Some(entry) = reifications.get_mut(n) {
+                entry.used = true;
+            }
+        }
+    }
+
+    reifications.into_iter()
+        .filter(|(_, r)| r.used && !r.malformed && r.subjects.len() == 1 && r.predicates.len() == 1 && r.objects.len() == 1)
+        .map(|(n, mut r)| (n.clone(), Triple::new(r.subjects.remove(0), r.predicates.remove(0), r.objects.remove(0))))
+        .collect()
+}
+
+/// A subject, or the quoted triple it stands for.
+fn subject_string(subject: &NamedOrBlankNode, quoted: &HashMap<NamedNode, Triple>, depth: usize) -> String {
+    match subject {
+        NamedOrBlankNode::NamedNode(n) => node_string(n, quoted, depth),
+        NamedOrBlankNode::BlankNode(b) => format!("_:{}", b.as_str()),
+    }
+}
+
+/// An object, or the quoted triple it stands for.
+fn object_string(object: &Term, quoted: &HashMap<NamedNode, Triple>, depth: usize) -> String {
+    match object {
+        Term::NamedNode(n) => node_string(n, quoted, depth),
+        Term::BlankNode(b) => format!("_:{}", b.as_str()),
+        Term::Literal(l) => literal_string(l),
+    }
+}
+
+/// A named node, or the quoted triple it stands for. Quoted triples are only expanded so deep, in
+/// case a reification refers back to itself.
+fn node_string(node: &NamedNode, quoted: &HashMap<NamedNode, Triple>, depth: usize) -> String {
+    match quoted.get(node) {
+        Some(triple) if depth < 16 => format!(
+            "<< {} <{}> {} >>",
+            subject_string(&triple.subject, quoted, depth + 1),
+            triple.predicate.as_str(),
+            object_string(&triple.object, quoted, depth + 1)),
+        _ => format!("<{}>", node.as_str()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::storage::parse_turtle;
+
+    const STAR: &str = r#"
+        @prefix : <http://example.com/> .
+        # a comment with << >> in it
+        << :alice :knows :bob >> :source :survey ; :says "a >> b" .
+        :claim :about << << :alice :knows :bob >> :certainty "0.9"^^<http://www.w3.org/2001/XMLSchema#decimal> >> .
+        :alice a :Person .
+    "#;
+
+    fn quoting_nodes(triples: &[Triple]) -> HashSet<NamedOrBlankNode> {
+        triples.iter()
+            .filter(|t| t.predicate.as_ref() == rdf::SUBJECT)
+            .map(|t| t.subject.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_unstar() {
+        let triples = parse_turtle(STAR.as_bytes()).unwrap();
+
+        // 4 asserted, 2 reifications of 3 triples each
+        assert_eq!(4 + 6, triples.len());
+        let nodes = quoting_nodes(&triples);
+        assert_eq!(2, nodes.len());
+        assert!(nodes.iter().all(|n| n.to_string().starts_with(&format!("<{}", meta::QUOTED_TRIPLE_NAMESPACE))));
+        assert!(!nodes.iter().any(|n| n.to_string().contains("pending")));
+    }
+
+    #[test]
+    fn test_terms_resolve_where_quoted() {
+        let triples = parse_turtle(br#"
+            @prefix : <http://example.com/> .
+            << :a :p :b >> :q "written with a prefix" .
+            @prefix : <http://example.org/> .
+            @base <http://example.com/> .
+            << <a> <p> <b> >> :q "written with a base" .
+            << <http://example.com/a> <http://example.com/p> <http://example.com/b> >> :q "written in full" .
+            :c :q :d .
+        "#).unwrap();
+
+        let nodes = quoting_nodes(&triples);
+        assert_eq!(1, nodes.len());
+        assert!(triples.iter().any(|t| t.predicate.as_ref() == rdf::SUBJECT && t.object.to_string() == "<http://example.com/a>"));
+        assert!(triples.iter().any(|t| t.subject.to_string() == "<http://example.org/c>"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let triples = parse_turtle(STAR.as_bytes()).unwrap();
+        let star = to_ntriples_star(&triples);
+
+        assert!(star.contains("<< <http://example.com/alice> <http://example.com/knows> <http://example.com/bob> >> <http://example.com/source> <http://example.com/survey> .\n"));
+        assert!(star.contains("<http://example.com/claim> <http://example.com/about> << << <http://example.com/alice>"));
+        assert!(!star.contains(rdf::SUBJECT.as_str()));
+
+        let again = parse_turtle(star.as_bytes()).unwrap();
+        assert_eq!(triples.iter().cloned().collect::<HashSet<Triple>>(), again.into_iter().collect());
+
+        let turtle = to_turtle_star(&triples);
+        assert!(turtle.contains("<http://example.com/alice> a <http://example.com/Person> .\n"));
+        let again = parse_turtle(turtle.as_bytes()).unwrap();
+        assert_eq!(triples.into_iter().collect::<HashSet<Triple>>(), again.into_iter().collect());
+    }
+
+    #[test]
+    fn test_plain_reification_is_kept() {
+        let triples = parse_turtle(br#"
+            _:s a <http://www.w3.org/1999/02/22-rdf-syntax-ns#Statement> ;
+                <http://www.w3.org/1999/02/22-rdf-syntax-ns#subject> <http://example.com/a> ;
+                <http://www.w3.org/1999/02/22-rdf-syntax-ns#predicate> <http://example.com/p> ;
+                <http://www.w3.org/1999/02/22-rdf-syntax-ns#object> <http://example.com/b> .
+            <http://example.com/claim> <http://example.com/about> _:s .
+            <http://example.com/s> <http://www.w3.org/1999/02/22-rdf-syntax-ns#subject> <http://example.com/a> ;
+                <http://www.w3.org/1999/02/22-rdf-syntax-ns#predicate> <http://example.com/p> ;
+                <http://www.w3.org/1999/02/22-rdf-syntax-ns#object> <http://example.com/b> .
+            <http://example.com/claim> <http://example.com/about> <http://example.com/s> .
+        "#).unwrap();
+        assert_eq!(triples.len(), to_ntriples_star(&triples).lines().count());
+        assert!(!to_ntriples_star(&triples).contains("<<"));
+    }
+}
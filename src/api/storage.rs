@@ -1,18 +1,23 @@
 use std::io::{Cursor, BufReader};
 use std::convert::{Infallible};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path;
 use std::fs::File;
 
 use oxigraph::SledStore as Store;
-use oxigraph::store::sled::{SledConflictableTransactionError, SledQuadIter, SledTransaction};
-use oxigraph::model::{GraphNameRef, NamedNode, NamedNodeRef, Quad, Triple, Term, NamedOrBlankNode};
+use oxigraph::store::sled::{SledConflictableTransactionError, SledQuadIter, SledTransaction, SledTransactionError};
+use oxigraph::model::{GraphNameRef, Literal, NamedNode, NamedNodeRef, Quad, Triple, Term, NamedOrBlankNode};
 use oxigraph::io::{GraphFormat, GraphSerializer, GraphParser};
 
 use sophia_api::term::SimpleIri;
 
 use crate::meta;
-use crate::api::{GraphType, GraphList, GraphData};
+use crate::api::{GraphType, GraphList, GraphData, CONTEXT, unbracket};
+use crate::api::alias;
+use crate::api::canon;
+use crate::api::history;
+use crate::api::provenance::{self, Origin};
+use crate::api::star;
 
 /// Load a Vec of bytes representing Turtle formatted triples into a named graph, `graph_uri`.
 /// The type of the RDF data: (Model, Ontology, Inference, or Closure) needs to be also specified.
@@ -24,36 +29,202 @@ use crate::api::{GraphType, GraphList, GraphData};
 /// 1) Report errors
 /// 2) Return the number of triples *loaded*, not parsed
 /// 
-/// The new graph is also added as an entry in the metadata graph
+/// The new graph is also added as an entry in the metadata graph, along with the hash of its
+/// content, and the load is recorded as the graph's first revision (see `history::prepare_write`).
 pub fn load_turtle_into_new_graph(store: &Store, graph_uri: NamedNode, graph_type: GraphType, triples: Vec<u8>) -> usize {
-    let metadata_entry = meta::graph_metadata_entry(graph_uri.clone(), graph_type);
+    let r: Vec<_> = parse_turtle(&triples).unwrap();
+    
+    load_triples_into_new_graph(store, graph_uri, graph_type, r)
+}
 
-    let parser = GraphParser::from_format(GraphFormat::Turtle);
+/// Load already parsed triples into a new named graph, `graph_uri`, with the same `<Meta>` and
+/// `<History>` bookkeeping as `load_turtle_into_new_graph`. Returned is the number of triples given.
+pub fn load_triples_into_new_graph(store: &Store, graph_uri: NamedNode, graph_type: GraphType, r: Vec<Triple>) -> usize {
+    let number_parsed = r.len();
+    if let Err(e) = load_new_graph(store, graph_uri, graph_type, r, None) {
+        println!("Broke when inserting! {}", e);
+    }
+    number_parsed
+}
+
+/// Loads triples into a new named graph as `load_triples_into_new_graph` does, and with `origin`,
+/// records the load in `<Provenance>` in the same transaction. Returned is the number of triples
+/// given, or why nothing of the load was written.
+pub fn load_new_graph(store: &Store, graph_uri: NamedNode, graph_type: GraphType, r: Vec<Triple>, origin: Option<&Origin>) -> Result<usize, String> {
+    let metadata_entry = meta::graph_metadata_entry(graph_uri.clone(), graph_type);
 
-    let r: Vec<_> = parser.read_triples(Cursor::new(triples)).unwrap()
-        .collect::<Result<Vec<_>,_>>().unwrap();
-    
     let number_parsed = r.len();
     println!("Parsed {} triples", number_parsed);
 
-    let _ = store.transaction(|transaction: SledTransaction| {
-        let _ = transaction.insert(metadata_entry.as_ref());
-        let results: Result<Vec<_>, _> = r.clone().into_iter().map(|triple| {
-            transaction.insert(triple.in_graph(graph_uri.as_ref()).as_ref())
-        }).collect();
+    let lock = history::lock(&graph_uri);
+    let revision = history::prepare_write(store, &lock, &r, &[]);
+    let (hash_entry, stale_hash) = content_hash_entry(store, &graph_uri, &r);
+    let provenance = origin.map(|origin| provenance::load_record(store, &graph_uri, graph_type, origin, number_parsed, 0, Some(revision.revision.clone())));
+
+    let remove: Vec<Quad> = stale_hash.into_iter().chain(revision.remove).collect();
+    let insert: Vec<Quad> = std::iter::once(metadata_entry)
+        .chain(hash_entry)
+        .chain(revision.insert)
+        .chain(r.into_iter().map(|triple| triple.in_graph(graph_uri.clone())))
+        .chain(provenance.into_iter().flatten())
+        .collect();
+    apply_changes(store, &remove, &insert)?;
+    Ok(number_parsed)
+}
 
-        let _ = match results {
-            Ok(_) => {},
-            Err(e) => {
-                println!("Broke when inserting! {}", e);
-            }
-        };
+/// The SHA-256 of the canonical N-Triples of a graph. Graphs that differ only in the labels of
+/// their blank nodes have the same hash. Graphs too hard to canonicalize have none.
+pub fn content_hash(triples: &[Triple]) -> Result<String, String> {
+    canon::canonical_ntriples(triples).map(|ntriples| canon::sha256_hex(&ntriples))
+}
+
+/// The `<Meta>` quad recording the content hash of `triples` as that of `graph`, with the stale
+/// hashes it replaces. Uploads too hard to canonicalize are refused before they get here, so a
+/// graph without a hash only loses its old one.
+pub fn content_hash_entry(store: &Store, graph: &NamedNode, triples: &[Triple]) -> (Option<Quad>, Vec<Quad>) {
+    let hash = content_hash(triples).ok();
+    let (entry, stale) = meta_value_entry(store, graph, meta::contentHash, Literal::new_simple_literal(hash.clone().unwrap_or_default()));
+    (hash.map(|_| entry), stale)
+}
+
+/// The content hash recorded in `<Meta>` for a graph the last time it was written.
+pub fn recorded_content_hash(store: &Store, graph_uri: NamedNodeRef) -> Option<String> {
+    let predicate = NamedNode::from(meta::contentHash);
+    store.quads_for_pattern(Some(graph_uri.into()), Some(predicate.as_ref()), None, Some(meta::meta_graph_uri()))
+        .filter_map(Result::ok)
+        .find_map(|q| match q.object {
+            Term::Literal(l) => Some(l.value().to_owned()),
+            _ => None,
+        })
+}
+
+/// Whether the Turtle in `triples` has exactly the content already recorded for `graph_uri`,
+/// so that loading it again would change nothing. Unparseable Turtle is never unchanged, and
+/// Turtle too hard to canonicalize is an error.
+pub fn is_unchanged_upload(store: &Store, graph_uri: NamedNodeRef, triples: &[u8]) -> Result<bool, String> {
+    match parse_turtle(triples) {
+        Ok(parsed) => Ok(recorded_content_hash(store, graph_uri) == Some(content_hash(&parsed)?)),
+        Err(_) => Ok(false),
+    }
+}
+
+/// Parse bytes in any graph format oxigraph reads, going through `parse_turtle` for Turtle so
+/// that Turtle-star is accepted.
+pub fn parse_graph(data: &[u8], format: GraphFormat) -> Result<Vec<Triple>, std::io::Error> {
+    match format {
+        GraphFormat::Turtle => parse_turtle(data),
+        format => GraphParser::from_format(format)
+            .read_triples(Cursor::new(data))
+            .and_then(|reader| reader.collect::<Result<Vec<_>, _>>()),
+    }
+}
+
+/// Parse Turtle bytes into triples, failing on the first syntax error. Turtle-star is accepted
+/// too, with its quoted triples reified (see `star::unstar_turtle`).
+pub fn parse_turtle(triples: &[u8]) -> Result<Vec<Triple>, std::io::Error> {
+    let unstarred = match std::str::from_utf8(triples) {
+        Ok(document) if star::has_quoted_triples(document) => Some(star::unstar_turtle(document)),
+        _ => None,
+    };
+    let turtle = unstarred.as_ref().map_or(triples, |unstarred| unstarred.turtle.as_bytes());
+
+    let parsed = GraphParser::from_format(GraphFormat::Turtle)
+        .read_triples(Cursor::new(turtle))
+        .and_then(|reader| reader.collect::<Result<Vec<_>, _>>())?;
+    match unstarred {
+        Some(unstarred) => unstarred.name_quoted(parsed).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+        None => Ok(parsed),
+    }
+}
+
+/// The `<Meta>` quad giving `graph` a new value for `predicate`, along with the quads holding the
+/// values it replaces, which should be removed in the same transaction.
+pub fn meta_value_entry(store: &Store, graph: &NamedNode, predicate: SimpleIri, value: impl Into<Term>) -> (Quad, Vec<Quad>) {
+    let predicate = NamedNode::from(predicate);
+    let stale = store.quads_for_pattern(Some(graph.as_ref().into()), Some(predicate.as_ref()), None, Some(meta::meta_graph_uri()))
+        .filter_map(Result::ok)
+        .collect();
+
+    (Quad::new(graph.clone(), predicate, value, meta::meta_graph_uri()), stale)
+}
 
+/// Removes and then inserts quads, all in one transaction. This is how writes that were worked out
+/// ahead of time, like a `history::RevisionWrite`, get applied.
+pub fn apply_changes(store: &Store, remove: &[Quad], insert: &[Quad]) -> Result<(), String> {
+    store.transaction(|transaction: SledTransaction| {
+        for quad in remove.iter() {
+            transaction.remove(quad.as_ref())?;
+        }
+        for quad in insert.iter() {
+            transaction.insert(quad.as_ref())?;
+        }
         Ok(()) as Result<(), SledConflictableTransactionError<Infallible>>
-    });
+    }).map_err(|e| match e {
+        SledTransactionError::Storage(s) => s.to_string(),
+        SledTransactionError::Abort(never) => match never {},
+    })
+}
 
-    number_parsed
+/// Makes a registered graph hold exactly `after`, as one new revision with its new content hash,
+/// all in one transaction. With the graph's type and an `origin`, the write is recorded in
+/// `<Provenance>` in that transaction too. Returned is how many triples were added and removed.
+pub fn rewrite_graph(store: &Store, graph: &NamedNode, after: Vec<Triple>, origin: Option<(GraphType, &Origin)>) -> Result<(usize, usize), String> {
+    let lock = history::lock(graph);
+    let before = graph_triples(store, graph.as_ref());
+    // Blank nodes are renamed on every parse, so the same content would otherwise come back as
+    // all its blank node triples removed and added again
+    let after: HashSet<Triple> = canon::align_blank_nodes(&before, &after)?.into_iter().collect();
+    let before: HashSet<Triple> = before.into_iter().collect();
+    let added: Vec<Triple> = after.difference(&before).cloned().collect();
+    let removed: Vec<Triple> = before.difference(&after).cloned().collect();
+    if added.is_empty() && removed.is_empty() {
+        return Ok((0, 0));
+    }
 
+    let after: Vec<Triple> = after.into_iter().collect();
+    let revision = history::prepare_write(store, &lock, &added, &removed);
+    let (hash, stale_hash) = content_hash_entry(store, graph, &after);
+    let provenance = origin.map(|(graph_type, origin)| provenance::load_record(store, graph, graph_type, origin, added.len(), removed.len(), Some(revision.revision.clone())));
+
+    let remove: Vec<Quad> = removed.iter()
+        .map(|t| t.clone().in_graph(graph.clone()))
+        .chain(revision.remove)
+        .chain(stale_hash)
+        .collect();
+    let insert: Vec<Quad> = added.iter()
+        .map(|t| t.clone().in_graph(graph.clone()))
+        .chain(revision.insert)
+        .chain(hash)
+        .chain(provenance.into_iter().flatten())
+        .collect();
+    apply_changes(store, &remove, &insert)?;
+    Ok((added.len(), removed.len()))
+}
+
+/// Takes a graph out of the store, along with its `<Meta>` entry and whatever else in `<Meta>`
+/// links to it, like the `hasInferencesAt` of the graph it holds inferences for. Its revisions
+/// stay in `<History>`. Returned is how many triples it held.
+pub fn delete_graph(store: &Store, graph: &NamedNode) -> Result<usize, String> {
+    let triples: Vec<Quad> = store.quads_for_pattern(None, None, None, Some(GraphNameRef::NamedNode(graph.as_ref())))
+        .filter_map(Result::ok)
+        .collect();
+    let entry: Vec<Quad> = store.quads_for_pattern(Some(graph.as_ref().into()), None, None, Some(meta::meta_graph_uri()))
+        .filter_map(Result::ok)
+        .collect();
+    let links: Vec<Quad> = store.quads_for_pattern(None, None, Some(graph.as_ref().into()), Some(meta::meta_graph_uri()))
+        .filter_map(Result::ok)
+        .collect();
+    let count = triples.len();
+    apply_changes(store, &triples.into_iter().chain(entry).chain(links).collect::<Vec<_>>(), &[])?;
+    Ok(count)
+}
+
+/// All the triples in a named graph.
+pub fn graph_triples(store: &Store, graph_uri: NamedNodeRef) -> Vec<Triple> {
+    store.quads_for_pattern(None, None, None, Some(GraphNameRef::NamedNode(graph_uri)))
+        .filter_map(Result::ok)
+        .map(Triple::from)
+        .collect()
 }
 
 /// Read out the triples in a named graph as Turtle.
@@ -61,14 +232,28 @@ pub fn read_graph_as_ttl_string(store: &Store, graph_uri: NamedNode) -> Result<S
     let quads_iter = store.quads_for_pattern(None, None, None,
         Some(GraphNameRef::NamedNode(graph_uri.as_ref())));
 
+    triples_as_ttl_string(quads_iter.map(|q| Triple::from(q.unwrap())))
+}
+
+/// Read out the triples in a named graph as canonical N-Triples: blank nodes relabelled with
+/// RDFC-1.0 and lines sorted, so the output is the same for every load of the same content.
+pub fn read_graph_as_canonical_ntriples(store: &Store, graph_uri: NamedNodeRef) -> Result<String, String> {
+    canon::canonical_ntriples(&graph_triples(store, graph_uri))
+}
+
+/// Write out any collection of triples as Turtle, for instance an older revision of a graph.
+pub fn triples_as_ttl_string(triples: impl IntoIterator<Item = Triple>) -> Result<String, String> {
+    serialize_triples(triples, GraphFormat::Turtle)
+}
+
+/// Write out any collection of triples in `format`.
+pub fn serialize_triples(triples: impl IntoIterator<Item = Triple>, format: GraphFormat) -> Result<String, String> {
     let mut buffer = Vec::new();
-    let mut writer = GraphSerializer::from_format(GraphFormat::Turtle).triple_writer(&mut buffer).unwrap();
-    quads_iter.map(|q| Triple::from(q.unwrap()))
-        .fold(&mut writer, |w, triple| {
-            let _ = w.write(triple.as_ref()); // Ignore error cause we're bad
-            w
-    });
-    let _ = writer.finish();
+    let mut writer = GraphSerializer::from_format(format).triple_writer(&mut buffer).map_err(|e| e.to_string())?;
+    for triple in triples {
+        writer.write(triple.as_ref()).map_err(|e| e.to_string())?;
+    }
+    writer.finish().map_err(|e| e.to_string())?;
 
     String::from_utf8(buffer).map_err(|e| e.to_string())
 }
@@ -95,19 +280,63 @@ pub fn accounted_graph_list(store: &Store) -> GraphList {
                 _ => GraphType::Unknown,
             };
 
+            let alias = literal_for(&po_list, meta::alias);
+            let path = match &graph_name {
+                NamedOrBlankNode::NamedNode(n) => alias::graph_path(n.as_ref(), alias.as_deref()),
+                NamedOrBlankNode::BlankNode(_) => String::new(),
+            };
             GraphData {
                 id: graph_name.to_string(),
                 graph_type: g,
+                revision: literal_for(&po_list, meta::currentRevision).and_then(|r| r.parse().ok()),
+                modified: literal_for(&po_list, meta::modified),
+                content_hash: literal_for(&po_list, meta::contentHash),
+                alias,
+                path,
             }
         })
         .collect();
 
     GraphList {
-        context: "http://www.purl.org/dougli1sqrd/models/janus-oxide/meta/context.json".into(),
+        context: CONTEXT.to_owned(),
         graphs,
+        total: None,
+        next: None,
     }
 }
 
+/// The names of the Meta-registered graphs of one type.
+pub fn graphs_of_type(store: &Store, graph_type: GraphType) -> Vec<NamedNode> {
+    accounted_graph_list(store)
+        .graphs
+        .into_iter()
+        .filter(|g| g.graph_type == graph_type)
+        .filter_map(|g| NamedNode::new(unbracket(&g.id)).ok())
+        .collect()
+}
+
+/// A name for a new graph under `base`, for graphs created without one, that no graph in the
+/// store has yet.
+pub fn new_graph_uri(store: &Store, base: &str) -> NamedNode {
+    let taken: HashSet<String> = accounted_graph_list(store).graphs.into_iter().map(|g| g.id).collect();
+    let mut n = chrono::Utc::now().timestamp_millis();
+    loop {
+        let graph = NamedNode::new_unchecked(format!("{}/store/graphs/{}", base.trim_end_matches('/'), n));
+        if !taken.contains(&graph.to_string()) && store.quads_for_pattern(None, None, None, Some(graph.as_ref().into())).next().is_none() {
+            return graph;
+        }
+        n += 1;
+    }
+}
+
+/// The lexical value of the first literal object of `predicate` in a list of (predicate, object) tuples.
+fn literal_for(po_list: &[(NamedNode, Term)], predicate: SimpleIri) -> Option<String> {
+    let predicate = NamedNode::from(predicate);
+    po_list.iter().find_map(|(p, o)| match o {
+        Term::Literal(l) if *p == predicate => Some(l.value().to_owned()),
+        _ => None,
+    })
+}
 
 ///
 /// Takes an iterator of Quads and and groups them by shared Subject, to produce a map of entries
@@ -143,11 +372,33 @@ fn map_by_subject(iter: SledQuadIter) -> HashMap<NamedOrBlankNode, Vec<(NamedNod
 /// :hello a meta:Model <http://www.purl.org/dougli1sqrd/models/janus-oxide/Meta> .
 /// ```
 /// 
-/// So there should be three actual named graphs after init:
+/// The example triple is recorded as the first revision of `:hello`, which puts its changeset in
+/// `History`.
+/// 
+/// So there should be four actual named graphs after init:
 /// 1) `MetaOnt`, where the metadata ontology is stored
 /// 2) `Meta`, whre the graph metadata will be placed as more graphs are added
-/// 3) `:hello`, as an example and containing a single example triple.
+/// 3) `History`, where the revisions of each graph are kept
+/// 4) `:hello`, as an example and containing a single example triple.
 pub fn init(store: &Store) {
+    let example_graph = NamedNode::new("http://www.purl.org/dougli1sqrd/models/janus-oxide/hello").unwrap();
+    let example_triple = Triple::new(
+        NamedNode::from(SimpleIri::new(example_graph.as_str(), Some("world")).unwrap()),
+        oxigraph::model::vocab::rdf::TYPE,
+        NamedNode::from(SimpleIri::new(example_graph.as_str(), Some("greeting")).unwrap()),
+    );
+    // The example graph is only seeded once: on later starts it already has a revision, and
+    // writing it again would add another one to its history.
+    let lock = history::lock(&example_graph);
+    let seeded = history::current_revision(store, example_graph.as_ref()).is_some();
+    let example = if seeded {
+        None
+    } else {
+        let revision = history::prepare_write(store, &lock, &[example_triple.clone()], &[]);
+        let (hash, stale_hash) = content_hash_entry(store, &example_graph, &[example_triple.clone()]);
+        Some((revision, hash, stale_hash))
+    };
+
     let _ = store.transaction(|transaction: SledTransaction| {
         let meta_ont_path = path::Path::new("metadata/meta_ont.ttl");
         let meta_ont = File::open(meta_ont_path).unwrap();
@@ -159,30 +410,32 @@ pub fn init(store: &Store) {
             None,
         );
 
-        let example_graph = NamedNode::new("http://www.purl.org/dougli1sqrd/models/janus-oxide/hello").unwrap();
-        let example_triple = Quad::new(
-            NamedNode::from(SimpleIri::new(example_graph.as_str(), Some("world")).unwrap()),
-            oxigraph::model::vocab::rdf::TYPE,
-            NamedNode::from(SimpleIri::new(example_graph.as_str(), Some("greeting")).unwrap()),
-            example_graph.clone()
-        );
-        let example_metadata = meta::graph_metadata_entry(example_graph, GraphType::Model);
+        if let Some((example_revision, example_hash, stale_hash)) = &example {
+            let example_quad = example_triple.clone().in_graph(example_graph.clone());
+            let example_metadata = meta::graph_metadata_entry(example_graph.clone(), GraphType::Model);
 
-        println!("Inserting {}", example_triple);
-        println!("Inserting {}", example_metadata);
-        let _ = transaction.insert(example_triple.as_ref());
-        let _ = transaction.insert(example_metadata.as_ref());
+            println!("Inserting {}", example_quad);
+            println!("Inserting {}", example_metadata);
+            for quad in example_revision.remove.iter().chain(stale_hash.iter()) {
+                let _ = transaction.remove(quad.as_ref());
+            }
+            let _ = transaction.insert(example_quad.as_ref());
+            let _ = transaction.insert(example_metadata.as_ref());
+            for quad in example_revision.insert.iter().chain(example_hash.iter()) {
+                let _ = transaction.insert(quad.as_ref());
+            }
+        }
 
         Ok(()) as Result<(), SledConflictableTransactionError<Infallible>>
     });
 }
 
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
     use super::*;
 
     // Makes a new store at a temporary directory. The Temp Dir handle is also returned.
-    fn make_temp_store() -> (Store, tempfile::TempDir) {
+    pub(crate) fn make_temp_store() -> (Store, tempfile::TempDir) {
         let tempdir = tempfile::tempdir().expect("Could not creat temporary file");
         let fpath = &tempdir.path().join("foo.txt");
         let store = Store::open(fpath).expect("Couldn't open SledStore");
@@ -190,7 +443,7 @@ mod test {
     }
 
     /// Runs init() on the store and then returns it
-    fn init_store() -> (Store, tempfile::TempDir) {
+    pub(crate) fn init_store() -> (Store, tempfile::TempDir) {
         let (s, f) = make_temp_store();
         init(&s);
         (s, f)
@@ -220,11 +473,25 @@ mod test {
         let mut expected = HashSet::new();
         expected.insert(NamedOrBlankNode::NamedNode(NamedNode::new_unchecked("http://www.purl.org/dougli1sqrd/models/janus-oxide/Meta")));
         expected.insert(NamedOrBlankNode::NamedNode(NamedNode::new_unchecked("http://www.purl.org/dougli1sqrd/models/janus-oxide/MetaOnt")));
+        expected.insert(NamedOrBlankNode::NamedNode(NamedNode::new_unchecked("http://www.purl.org/dougli1sqrd/models/janus-oxide/History")));
         expected.insert(NamedOrBlankNode::NamedNode(NamedNode::new_unchecked("http://www.purl.org/dougli1sqrd/models/janus-oxide/hello")));
 
         assert_eq!(g, expected);
     }
 
+    #[test]
+    fn test_init_twice() {
+        let (s, _f) = init_store();
+        init(&s);
+
+        let hello = NamedNode::new_unchecked("http://www.purl.org/dougli1sqrd/models/janus-oxide/hello");
+        assert_eq!(1, history::list_revisions(&s, hello.as_ref()).len());
+        for predicate in vec![NamedNode::from(meta::currentRevision), NamedNode::from(meta::modified), NamedNode::from(meta::contentHash)] {
+            let values = s.quads_for_pattern(Some(hello.as_ref().into()), Some(predicate.as_ref()), None, Some(meta::meta_graph_uri()));
+            assert_eq!(1, values.count());
+        }
+    }
+
     #[test]
     fn test_load_turtle() {
         let (s, _f): (Store, _) = init_store();
@@ -249,4 +516,125 @@ mod test {
         let graph_node = NamedNodeRef::new_unchecked("http://www.example.com");
         assert_eq!(vec![Quad::new(sub, pred, obj, graph_node)], quad_in_graph);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_load_records_first_revision() {
+        let (s, _f): (Store, _) = init_store();
+
+        let triple = "<http://www.example.com/A> <http://www.example.com/is> <http://www.example.com/B> .".as_bytes();
+        let graph = NamedNode::new_unchecked("http://www.example.com");
+
+        load_turtle_into_new_graph(&s, graph.clone(), GraphType::Model, triple.to_vec());
+
+        let revision = history::current_revision(&s, graph.as_ref()).expect("Load should record a revision");
+        assert_eq!(1, revision.number);
+
+        let accounted = accounted_graph_list(&s);
+        let found = accounted.graphs.iter().find(|g| g.id == "<http://www.example.com>").unwrap();
+        assert_eq!(Some(1), found.revision);
+
+        let versions = history::list_revisions(&s, graph.as_ref());
+        assert_eq!(1, versions.len());
+        assert_eq!(1, versions[0].added);
+        assert_eq!(0, versions[0].removed);
+
+        let expected = Triple::new(
+            NamedNode::new_unchecked("http://www.example.com/A"),
+            NamedNode::new_unchecked("http://www.example.com/is"),
+            NamedNode::new_unchecked("http://www.example.com/B"));
+        assert_eq!(Some(vec![expected]), history::graph_at_revision(&s, graph.as_ref(), 1));
+        assert_eq!(None, history::graph_at_revision(&s, graph.as_ref(), 2));
+    }
+
+    #[test]
+    fn test_reupload_is_unchanged() {
+        let (s, _f): (Store, _) = init_store();
+
+        let graph = NamedNode::new_unchecked("http://www.example.com");
+        let first = "<http://www.example.com/A> <http://www.example.com/has> [ <http://www.example.com/is> \"B\" ] .".as_bytes();
+        let relabelled = "<http://www.example.com/A> <http://www.example.com/has> _:other .\n_:other <http://www.example.com/is> \"B\" .".as_bytes();
+        let different = "<http://www.example.com/A> <http://www.example.com/has> [ <http://www.example.com/is> \"C\" ] .".as_bytes();
+
+        assert_eq!(Ok(false), is_unchanged_upload(&s, graph.as_ref(), first));
+
+        load_turtle_into_new_graph(&s, graph.clone(), GraphType::Model, first.to_vec());
+
+        assert_eq!(content_hash(&graph_triples(&s, graph.as_ref())).ok(), recorded_content_hash(&s, graph.as_ref()));
+        assert_eq!(Ok(true), is_unchanged_upload(&s, graph.as_ref(), first));
+        assert_eq!(Ok(true), is_unchanged_upload(&s, graph.as_ref(), relabelled));
+        assert_eq!(Ok(false), is_unchanged_upload(&s, graph.as_ref(), different));
+    }
+
+    #[test]
+    fn test_rewrite_and_delete_graph() {
+        let (s, _f): (Store, _) = init_store();
+        let graph = NamedNode::new_unchecked("http://www.example.com");
+        let a = parse_graph(b"<http://www.example.com/A> <http://www.example.com/is> <http://www.example.com/B> .", GraphFormat::NTriples).unwrap();
+        let b = parse_turtle(b"<http://www.example.com/A> <http://www.example.com/is> <http://www.example.com/C> .").unwrap();
+        load_triples_into_new_graph(&s, graph.clone(), GraphType::Model, a.clone());
+
+        assert_eq!(Ok((1, 1)), rewrite_graph(&s, &graph, b.clone(), None));
+        assert_eq!(b, graph_triples(&s, graph.as_ref()));
+        assert_eq!(2, history::current_revision(&s, graph.as_ref()).unwrap().number);
+        assert_eq!(content_hash(&b).ok(), recorded_content_hash(&s, graph.as_ref()));
+        assert_eq!(Ok((0, 0)), rewrite_graph(&s, &graph, b, None));
+
+        let model = NamedNode::new_unchecked("http://www.example.com/model");
+        let link = Quad::new(model.clone(), NamedNode::from(meta::hasInferencesAt), graph.clone(), meta::meta_graph_uri());
+        s.insert(&link).unwrap();
+        assert_eq!(Ok(1), delete_graph(&s, &graph));
+        assert!(graph_triples(&s, graph.as_ref()).is_empty());
+        assert!(!accounted_graph_list(&s).graphs.iter().any(|g| g.id == graph.to_string()));
+        assert!(!s.contains(&link).unwrap());
+    }
+
+    #[test]
+    fn test_rewrite_keeps_blank_nodes() {
+        let (s, _f): (Store, _) = init_store();
+        let graph = NamedNode::new_unchecked("http://www.example.com");
+        let ttl = b"<http://www.example.com/A> <http://www.example.com/is> [ <http://www.example.com/named> \"B\" ] .";
+        load_triples_into_new_graph(&s, graph.clone(), GraphType::Model, parse_turtle(ttl).unwrap());
+        let stored = graph_triples(&s, graph.as_ref());
+
+        // Every parse names the blank node afresh
+        assert_eq!(Ok((0, 0)), rewrite_graph(&s, &graph, parse_turtle(ttl).unwrap(), None));
+        assert_eq!(stored, graph_triples(&s, graph.as_ref()));
+        assert_eq!(1, history::current_revision(&s, graph.as_ref()).unwrap().number);
+
+        let changed = parse_turtle(b"<http://www.example.com/A> <http://www.example.com/is> [ <http://www.example.com/named> \"C\" ] .").unwrap();
+        assert_eq!(Ok((2, 2)), rewrite_graph(&s, &graph, changed, None));
+    }
+
+    #[test]
+    fn test_concurrent_rewrites_make_distinct_revisions() {
+        let (s, _f): (Store, _) = init_store();
+        let graph = NamedNode::new_unchecked("http://www.example.com");
+        load_triples_into_new_graph(&s, graph.clone(), GraphType::Model, Vec::new());
+
+        let writers: Vec<_> = (0..8).map(|i| {
+            let (s, graph) = (s.clone(), graph.clone());
+            std::thread::spawn(move || {
+                let triple = Triple::new(graph.clone(), graph.clone(), Literal::from(i as i64));
+                rewrite_graph(&s, &graph, vec![triple], None).unwrap();
+            })
+        }).collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let numbers: Vec<u64> = history::list_revisions(&s, graph.as_ref()).into_iter().map(|r| r.number).collect();
+        assert_eq!((1..=9).collect::<Vec<u64>>(), numbers);
+        assert_eq!(9, history::current_revision(&s, graph.as_ref()).unwrap().number);
+    }
+
+    #[test]
+    fn test_new_graph_uri() {
+        let (s, _f): (Store, _) = init_store();
+        let first = new_graph_uri(&s, "http://localhost:8000/");
+        assert!(first.as_str().starts_with("http://localhost:8000/store/graphs/"));
+
+        load_triples_into_new_graph(&s, first.clone(), GraphType::Model, Vec::new());
+        let second = new_graph_uri(&s, "http://localhost:8000");
+        assert_ne!(first, second);
+    }
+}
@@ -0,0 +1,492 @@
+//! Lookup of ontology terms: their labels, definitions, SKOS and oboInOwl annotations and their
+//! superclasses, read from the Ontology graphs registered in `<Meta>`.
+//!
+//! Indirect superclasses, and ancestors and descendants along other relations, come from the
+//! Closure graphs when there are any, since that's what they are precomputed for. Without a
+//! Closure graph, the relation is followed through the Ontology graphs instead.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use oxigraph::SledStore as Store;
+use oxigraph::model::{NamedNode, NamedNodeRef, NamedOrBlankNode, Term};
+use oxigraph::model::vocab::rdfs;
+
+use serde::Serialize;
+
+use crate::api::GraphType;
+use crate::api::storage::graphs_of_type;
+use crate::api::vocab::{obo, obo_in_owl, owl, skos};
+
+/// A term as JSON-LD, compacted with the Meta context. Annotations other than the label and
+/// definition are keyed by their compact IRI, e.g. `skos:altLabel` or `oboInOwl:hasExactSynonym`.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TermInfo {
+    pub context: String,
+    pub id: String,
+    pub label: Vec<String>,
+    pub definition: Vec<String>,
+    #[serde(flatten)]
+    pub annotations: BTreeMap<String, Vec<String>>,
+    #[serde(rename = "subClassOf")]
+    pub parents: Vec<String>,
+    pub ancestors: Vec<String>,
+    #[serde(rename = "definedBy")]
+    pub graphs: Vec<String>,
+}
+
+/// Everything the Ontology graphs say about `term`, or `None` if none of them mention it as a subject.
+/// With `within`, only those graphs are read.
+pub fn lookup(store: &Store, term: &NamedNode, context: String, within: Option<&HashSet<NamedNode>>) -> Option<TermInfo> {
+    let mut info = TermInfo {
+        context,
+        id: term.as_str().to_owned(),
+        label: Vec::new(),
+        definition: Vec::new(),
+        annotations: BTreeMap::new(),
+        parents: Vec::new(),
+        ancestors: Vec::new(),
+        graphs: Vec::new(),
+    };
+
+    for graph in graphs_within(store, GraphType::Ontology, within) {
+        let mut mentioned = false;
+        for quad in store.quads_for_pattern(Some(term.as_ref().into()), None, None, Some(graph.as_ref().into())).filter_map(Result::ok) {
+            mentioned = true;
+            let value = match &quad.object {
+                Term::NamedNode(n) => n.as_str().to_owned(),
+                Term::Literal(l) => l.value().to_owned(),
+                // Blank nodes are OWL restrictions and axiom annotations, which we don't describe
+                Term::BlankNode(_) => continue,
+            };
+
+            let predicate = quad.predicate.as_ref();
+            let values = if predicate == rdfs::LABEL {
+                &mut info.label
+            } else if predicate == obo::DEFINITION {
+                &mut info.definition
+            } else if predicate == rdfs::SUB_CLASS_OF {
+                &mut info.parents
+            } else if let Some(key) = compact(predicate) {
+                info.annotations.entry(key).or_insert_with(Vec::new)
+            } else {
+                continue;
+            };
+            if !values.contains(&value) {
+                values.push(value);
+            }
+        }
+        if mentioned {
+            info.graphs.push(graph.as_str().to_owned());
+        }
+    }
+
+    if info.graphs.is_empty() {
+        return None;
+    }
+
+    info.ancestors = ancestors(store, term, within).iter().map(|a| a.as_str().to_owned()).collect();
+    Some(info)
+}
+
+/// The direct and indirect superclasses of `term`, from the Closure graphs if there are any.
+pub fn ancestors(store: &Store, term: &NamedNode, within: Option<&HashSet<NamedNode>>) -> Vec<NamedNode> {
+    let relation = Relation(NamedNode::from(rdfs::SUB_CLASS_OF));
+    related(store, term, &relation, Direction::Ancestors, None, within).terms
+}
+
+/// A relation terms can be traversed along: `rdfs:subClassOf`, or an object property such as
+/// `part_of`, which ontologies usually state through `owl:someValuesFrom` restrictions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relation(pub NamedNode);
+
+/// Short names for relations commonly traversed in OBO ontologies. More can be configured, and
+/// any other relation can be given by its IRI.
+const RELATION_NAMES: [(&str, &str); 6] = [
+    ("subClassOf", "http://www.w3.org/2000/01/rdf-schema#subClassOf"),
+    ("is_a", "http://www.w3.org/2000/01/rdf-schema#subClassOf"),
+    ("part_of", "http://purl.obolibrary.org/obo/BFO_0000050"),
+    ("has_part", "http://purl.obolibrary.org/obo/BFO_0000051"),
+    ("occurs_in", "http://purl.obolibrary.org/obo/BFO_0000066"),
+    ("regulates", "http://purl.obolibrary.org/obo/RO_0002211"),
+];
+
+/// The short names relations can be given by, ignoring case, shared between requests as Rocket
+/// managed state.
+pub struct Relations {
+    names: HashMap<String, NamedNode>,
+}
+
+impl Default for Relations {
+    fn default() -> Relations {
+        Relations {
+            names: RELATION_NAMES.iter()
+                .map(|(name, iri)| (name.to_lowercase(), NamedNode::new_unchecked(*iri)))
+                .collect(),
+        }
+    }
+}
+
+impl Relations {
+    /// The built-in names, with `configured` as `(name, IRI)` pairs added to them or replacing them.
+    pub fn with<'a>(configured: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Relations, String> {
+        let mut relations = Relations::default();
+        for (name, iri) in configured {
+            let iri = NamedNode::new(iri).map_err(|e| format!("Relation {} is not an IRI: {}", name, e))?;
+            relations.names.insert(name.to_lowercase(), iri);
+        }
+        Ok(relations)
+    }
+
+    /// The relation called `name`.
+    pub fn get(&self, name: &str) -> Option<Relation> {
+        self.names.get(&name.to_lowercase()).cloned().map(Relation)
+    }
+
+    /// The relation `name` names, or why it names none.
+    pub fn relation(&self, name: RelationName) -> Result<Relation, String> {
+        match name {
+            RelationName::Iri(iri) => Ok(Relation(iri)),
+            RelationName::Name(name) => self.get(&name).ok_or_else(|| format!("{} is not a known relation", name)),
+        }
+    }
+}
+
+/// A relation as a request names it: by its IRI, or by a short name still to be looked up.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RelationName {
+    Iri(NamedNode),
+    Name(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Ancestors,
+    Descendants,
+}
+
+/// Terms reached from one term along a relation, and whether they were read from a Closure
+/// graph or found by following the relation through the Ontology graphs.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Traversal {
+    pub context: String,
+    pub id: String,
+    pub relation: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub depth: Option<usize>,
+    pub source: String,
+    pub terms: Vec<String>,
+}
+
+pub struct Related {
+    pub terms: Vec<NamedNode>,
+    pub from_closure: bool,
+}
+
+/// The ancestors or descendants of `term` along `relation`, at most `depth` steps away.
+///
+/// A Closure graph that has any triples of `relation` answers directly. Closures don't record how
+/// far apart two terms are, though, so with a `depth` the relation is always followed step by step.
+/// With `within`, only those graphs are read.
+///
+/// Followed step by step, a relation other than `rdfs:subClassOf` also goes through
+/// `rdfs:subClassOf` on either side: what a superclass is `part_of` its subclasses are too, and
+/// whatever is `part_of` a class is `part_of` its superclasses. Only terms reached along
+/// `relation` at least once are related, and every step counts towards `depth`.
+pub fn related(store: &Store, term: &NamedNode, relation: &Relation, direction: Direction, depth: Option<usize>, within: Option<&HashSet<NamedNode>>) -> Related {
+    let predicate = relation.0.as_ref();
+    let closures: Vec<NamedNode> = graphs_within(store, GraphType::Closure, within)
+        .into_iter()
+        .filter(|c| store.quads_for_pattern(None, Some(predicate), None, Some(c.as_ref().into())).next().is_some())
+        .collect();
+
+    if depth.is_none() && !closures.is_empty() {
+        let terms = match direction {
+            Direction::Ancestors => objects_in(store, &closures, term, predicate),
+            Direction::Descendants => subjects_in(store, &closures, predicate, term),
+        };
+        return Related {
+            terms: terms.into_iter().filter(|t| t != term).collect(),
+            from_closure: true,
+        };
+    }
+
+    let ontologies = graphs_within(store, GraphType::Ontology, within);
+    let through_subclasses = predicate != rdfs::SUB_CLASS_OF;
+    let neighbours = |current: &NamedNode, relation: NamedNodeRef| match direction {
+        Direction::Ancestors => parents_along(store, &ontologies, current, relation),
+        Direction::Descendants => children_along(store, &ontologies, current, relation),
+    };
+
+    // Each term reached, with whether it was reached along `relation` or only through subclasses
+    let mut seen: HashSet<(NamedNode, bool)> = HashSet::new();
+    let mut terms = Vec::new();
+    let mut frontier = vec![(term.clone(), !through_subclasses)];
+    let mut steps = 0;
+    while !frontier.is_empty() && depth.map_or(true, |d| steps < d) {
+        let mut next = Vec::new();
+        for (current, along) in frontier {
+            let mut reached: Vec<(NamedNode, bool)> = neighbours(&current, predicate).into_iter().map(|n| (n, true)).collect();
+            if through_subclasses {
+                reached.extend(neighbours(&current, rdfs::SUB_CLASS_OF).into_iter().map(|n| (n, along)));
+            }
+            for (neighbour, along) in reached {
+                if neighbour == *term || !seen.insert((neighbour.clone(), along)) {
+                    continue;
+                }
+                if along && !terms.contains(&neighbour) {
+                    terms.push(neighbour.clone());
+                }
+                next.push((neighbour, along));
+            }
+        }
+        frontier = next;
+        steps += 1;
+    }
+
+    Related { terms, from_closure: false }
+}
+
+/// The Meta-registered graphs of one type, keeping to those `within` if given.
+fn graphs_within(store: &Store, graph_type: GraphType, within: Option<&HashSet<NamedNode>>) -> Vec<NamedNode> {
+    graphs_of_type(store, graph_type)
+        .into_iter()
+        .filter(|g| within.map_or(true, |w| w.contains(g)))
+        .collect()
+}
+
+/// The terms `term` is directly related to by `relation`: `term relation ?parent`, or for
+/// properties other than `rdfs:subClassOf` also
+/// `term rdfs:subClassOf [ owl:onProperty relation ; owl:someValuesFrom ?parent ]`.
+fn parents_along(store: &Store, graphs: &[NamedNode], term: &NamedNode, relation: NamedNodeRef) -> Vec<NamedNode> {
+    let mut parents = objects_in(store, graphs, term, relation);
+    if relation == rdfs::SUB_CLASS_OF {
+        return parents;
+    }
+
+    for graph in graphs {
+        let restrictions = store.quads_for_pattern(Some(term.as_ref().into()), Some(rdfs::SUB_CLASS_OF), None, Some(graph.as_ref().into()))
+            .filter_map(Result::ok)
+            .filter_map(|q| match q.object {
+                Term::BlankNode(b) => Some(b),
+                _ => None,
+            });
+        for restriction in restrictions {
+            let on_relation = store.quads_for_pattern(Some(restriction.as_ref().into()), Some(owl::ON_PROPERTY), Some(relation.into()), Some(graph.as_ref().into()))
+                .next()
+                .is_some();
+            if !on_relation {
+                continue;
+            }
+            for quad in store.quads_for_pattern(Some(restriction.as_ref().into()), Some(owl::SOME_VALUES_FROM), None, Some(graph.as_ref().into())).filter_map(Result::ok) {
+                if let Term::NamedNode(parent) = quad.object {
+                    if !parents.contains(&parent) {
+                        parents.push(parent);
+                    }
+                }
+            }
+        }
+    }
+    parents
+}
+
+/// The inverse of `parents_along`.
+fn children_along(store: &Store, graphs: &[NamedNode], term: &NamedNode, relation: NamedNodeRef) -> Vec<NamedNode> {
+    let mut children = subjects_in(store, graphs, relation, term);
+    if relation == rdfs::SUB_CLASS_OF {
+        return children;
+    }
+
+    for graph in graphs {
+        let restrictions = store.quads_for_pattern(None, Some(owl::SOME_VALUES_FROM), Some(term.as_ref().into()), Some(graph.as_ref().into()))
+            .filter_map(Result::ok)
+            .filter_map(|q| match q.subject {
+                NamedOrBlankNode::BlankNode(b) => Some(b),
+                _ => None,
+            });
+        for restriction in restrictions {
+            let on_relation = store.quads_for_pattern(Some(restriction.as_ref().into()), Some(owl::ON_PROPERTY), Some(relation.into()), Some(graph.as_ref().into()))
+                .next()
+                .is_some();
+            if !on_relation {
+                continue;
+            }
+            for quad in store.quads_for_pattern(None, Some(rdfs::SUB_CLASS_OF), Some(restriction.as_ref().into()), Some(graph.as_ref().into())).filter_map(Result::ok) {
+                if let NamedOrBlankNode::NamedNode(child) = quad.subject {
+                    if !children.contains(&child) {
+                        children.push(child);
+                    }
+                }
+            }
+        }
+    }
+    children
+}
+
+/// The IRI objects of `subject predicate ?o` across `graphs`, without duplicates.
+fn objects_in(store: &Store, graphs: &[NamedNode], subject: &NamedNode, predicate: NamedNodeRef) -> Vec<NamedNode> {
+    let mut objects = Vec::new();
+    for graph in graphs {
+        for quad in store.quads_for_pattern(Some(subject.as_ref().into()), Some(predicate), None, Some(graph.as_ref().into())).filter_map(Result::ok) {
+            if let Term::NamedNode(o) = quad.object {
+                if !objects.contains(&o) {
+                    objects.push(o);
+                }
+            }
+        }
+    }
+    objects
+}
+
+/// The IRI subjects of `?s predicate object` across `graphs`, without duplicates.
+fn subjects_in(store: &Store, graphs: &[NamedNode], predicate: NamedNodeRef, object: &NamedNode) -> Vec<NamedNode> {
+    let mut subjects = Vec::new();
+    for graph in graphs {
+        for quad in store.quads_for_pattern(None, Some(predicate), Some(object.as_ref().into()), Some(graph.as_ref().into())).filter_map(Result::ok) {
+            if let NamedOrBlankNode::NamedNode(s) = quad.subject {
+                if !subjects.contains(&s) {
+                    subjects.push(s);
+                }
+            }
+        }
+    }
+    subjects
+}
+
+/// The compact IRI of a SKOS or oboInOwl annotation property, as the Meta context defines the prefixes.
+fn compact(predicate: NamedNodeRef) -> Option<String> {
+    let iri = predicate.as_str();
+    if iri.starts_with(skos::NAMESPACE) {
+        Some(format!("skos:{}", &iri[skos::NAMESPACE.len()..]))
+    } else if iri.starts_with(obo_in_owl::NAMESPACE) {
+        Some(format!("oboInOwl:{}", &iri[obo_in_owl::NAMESPACE.len()..]))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::storage::load_turtle_into_new_graph;
+    use crate::api::storage::test::init_store;
+
+    const ONTOLOGY: &str = r#"
+        @prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .
+        @prefix owl: <http://www.w3.org/2002/07/owl#> .
+        @prefix obo: <http://purl.obolibrary.org/obo/> .
+        @prefix oboInOwl: <http://www.geneontology.org/formats/oboInOwl#> .
+        @prefix skos: <http://www.w3.org/2004/02/skos/core#> .
+
+        obo:GO_0008150 a owl:Class ; rdfs:label "biological_process" .
+        obo:GO_0009987 a owl:Class ;
+            rdfs:label "cellular process" ;
+            rdfs:subClassOf obo:GO_0008150 .
+        obo:GO_0007049 a owl:Class ;
+            rdfs:label "cell cycle" ;
+            obo:IAO_0000115 "The progression of biochemical and morphological phases and events that occur in a cell during successive cell replication or nuclear replication events." ;
+            oboInOwl:hasExactSynonym "cell-division cycle" ;
+            skos:exactMatch <http://example.com/cell-cycle> ;
+            rdfs:subClassOf obo:GO_0009987 ;
+            rdfs:subClassOf [ a owl:Restriction ] .
+    "#;
+
+    #[test]
+    fn test_lookup() {
+        let (s, _f) = init_store();
+        load_turtle_into_new_graph(&s, NamedNode::new_unchecked("http://example.com/go"), GraphType::Ontology, ONTOLOGY.as_bytes().to_vec());
+
+        let term = NamedNode::new_unchecked("http://purl.obolibrary.org/obo/GO_0007049");
+        let info = lookup(&s, &term, "context".into(), None).unwrap();
+
+        assert_eq!(vec!["cell cycle"], info.label);
+        assert_eq!(1, info.definition.len());
+        assert_eq!(Some(&vec!["cell-division cycle".to_owned()]), info.annotations.get("oboInOwl:hasExactSynonym"));
+        assert_eq!(Some(&vec!["http://example.com/cell-cycle".to_owned()]), info.annotations.get("skos:exactMatch"));
+        assert_eq!(vec!["http://purl.obolibrary.org/obo/GO_0009987"], info.parents);
+        assert_eq!(vec!["http://purl.obolibrary.org/obo/GO_0009987", "http://purl.obolibrary.org/obo/GO_0008150"], info.ancestors);
+        assert_eq!(vec!["http://example.com/go"], info.graphs);
+
+        assert!(lookup(&s, &NamedNode::new_unchecked("http://example.com/nothing"), "context".into(), None).is_none());
+    }
+
+    #[test]
+    fn test_ancestors_from_closure() {
+        let (s, _f) = init_store();
+        load_turtle_into_new_graph(&s, NamedNode::new_unchecked("http://example.com/go"), GraphType::Ontology, ONTOLOGY.as_bytes().to_vec());
+        let closure = r#"
+            <http://purl.obolibrary.org/obo/GO_0007049> <http://www.w3.org/2000/01/rdf-schema#subClassOf> <http://purl.obolibrary.org/obo/GO_0007049> .
+            <http://purl.obolibrary.org/obo/GO_0007049> <http://www.w3.org/2000/01/rdf-schema#subClassOf> <http://purl.obolibrary.org/obo/GO_0008150> .
+        "#;
+        load_turtle_into_new_graph(&s, NamedNode::new_unchecked("http://example.com/go-closure"), GraphType::Closure, closure.as_bytes().to_vec());
+
+        let term = NamedNode::new_unchecked("http://purl.obolibrary.org/obo/GO_0007049");
+        assert_eq!(vec![NamedNode::new_unchecked("http://purl.obolibrary.org/obo/GO_0008150")], ancestors(&s, &term, None));
+
+        // A depth can't be answered from the closure
+        let subclass_of = Relations::default().get("is_a").unwrap();
+        let one_step = related(&s, &term, &subclass_of, Direction::Ancestors, Some(1), None);
+        assert!(!one_step.from_closure);
+        assert_eq!(vec![NamedNode::new_unchecked("http://purl.obolibrary.org/obo/GO_0009987")], one_step.terms);
+    }
+
+    #[test]
+    fn test_traverse_restrictions() {
+        let (s, _f) = init_store();
+        let ontology = r#"
+            @prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .
+            @prefix owl: <http://www.w3.org/2002/07/owl#> .
+            @prefix obo: <http://purl.obolibrary.org/obo/> .
+
+            obo:GO_0000776 rdfs:subClassOf [ a owl:Restriction ; owl:onProperty obo:BFO_0000050 ; owl:someValuesFrom obo:GO_0000775 ] .
+            obo:GO_0000775 rdfs:subClassOf [ a owl:Restriction ; owl:onProperty obo:BFO_0000050 ; owl:someValuesFrom obo:GO_0005694 ] .
+            obo:GO_0000777 obo:BFO_0000050 obo:GO_0000775 .
+        "#;
+        load_turtle_into_new_graph(&s, NamedNode::new_unchecked("http://example.com/go"), GraphType::Ontology, ontology.as_bytes().to_vec());
+
+        let go = |id: &str| NamedNode::new_unchecked(format!("http://purl.obolibrary.org/obo/{}", id));
+        let part_of = Relations::default().get("Part_Of").unwrap();
+
+        let ancestors = related(&s, &go("GO_0000776"), &part_of, Direction::Ancestors, None, None);
+        assert_eq!(vec![go("GO_0000775"), go("GO_0005694")], ancestors.terms);
+
+        let mut descendants = related(&s, &go("GO_0005694"), &part_of, Direction::Descendants, None, None).terms;
+        descendants.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        assert_eq!(vec![go("GO_0000775"), go("GO_0000776"), go("GO_0000777")], descendants);
+
+        let children = related(&s, &go("GO_0005694"), &part_of, Direction::Descendants, Some(1), None).terms;
+        assert_eq!(vec![go("GO_0000775")], children);
+
+        assert!(Relations::default().get("unknown").is_none());
+    }
+
+    #[test]
+    fn test_traverse_through_subclasses() {
+        let (s, _f) = init_store();
+        let ontology = r#"
+            @prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .
+            @prefix owl: <http://www.w3.org/2002/07/owl#> .
+            @prefix obo: <http://purl.obolibrary.org/obo/> .
+
+            obo:GO_0000777 rdfs:subClassOf obo:GO_0000776 .
+            obo:GO_0000776 rdfs:subClassOf [ a owl:Restriction ; owl:onProperty obo:BFO_0000050 ; owl:someValuesFrom obo:GO_0000775 ] .
+            obo:GO_0000775 rdfs:subClassOf obo:GO_0110165 .
+        "#;
+        load_turtle_into_new_graph(&s, NamedNode::new_unchecked("http://example.com/go"), GraphType::Ontology, ontology.as_bytes().to_vec());
+
+        let go = |id: &str| NamedNode::new_unchecked(format!("http://purl.obolibrary.org/obo/{}", id));
+        let part_of = Relations::default().get("part_of").unwrap();
+
+        // A subclass is part of what its superclass is part of, and of that one's superclasses
+        let ancestors = related(&s, &go("GO_0000777"), &part_of, Direction::Ancestors, None, None);
+        assert_eq!(vec![go("GO_0000775"), go("GO_0110165")], ancestors.terms);
+
+        let mut descendants = related(&s, &go("GO_0110165"), &part_of, Direction::Descendants, None, None).terms;
+        descendants.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        assert_eq!(vec![go("GO_0000776"), go("GO_0000777")], descendants);
+
+        let relations = Relations::with(vec![("located_in", "http://purl.obolibrary.org/obo/RO_0001025")]).unwrap();
+        assert_eq!(Some(Relation(go("RO_0001025"))), relations.get("Located_In"));
+        assert_eq!(Some(part_of), relations.get("part_of"));
+        assert!(Relations::with(vec![("bad", "not an iri")]).is_err());
+    }
+}
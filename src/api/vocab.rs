@@ -0,0 +1,117 @@
+//! Terms from vocabularies that oxigraph doesn't ship in `oxigraph::model::vocab`, in the same style.
+
+pub mod owl {
+    use oxigraph::model::NamedNodeRef;
+
+    pub const ONTOLOGY: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/2002/07/owl#Ontology");
+    pub const CLASS: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/2002/07/owl#Class");
+    pub const IMPORTS: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/2002/07/owl#imports");
+    pub const ON_PROPERTY: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/2002/07/owl#onProperty");
+    pub const SOME_VALUES_FROM: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/2002/07/owl#someValuesFrom");
+}
+
+pub mod sh {
+    use oxigraph::model::NamedNodeRef;
+
+    pub const NODE_SHAPE: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/shacl#NodeShape");
+    pub const PROPERTY_SHAPE: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/shacl#PropertyShape");
+}
+
+pub mod sd {
+    use oxigraph::model::NamedNodeRef;
+
+    pub const SERVICE: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/sparql-service-description#Service");
+    pub const GRAPH_COLLECTION: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/sparql-service-description#GraphCollection");
+    pub const NAMED_GRAPH_CLASS: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/sparql-service-description#NamedGraph");
+    pub const GRAPH_CLASS: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/sparql-service-description#Graph");
+    pub const SPARQL11_QUERY: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/sparql-service-description#SPARQL11Query");
+    pub const ENDPOINT: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/sparql-service-description#endpoint");
+    pub const SUPPORTED_LANGUAGE: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/sparql-service-description#supportedLanguage");
+    pub const RESULT_FORMAT: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/sparql-service-description#resultFormat");
+    pub const DEFAULT_ENTAILMENT_REGIME: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/sparql-service-description#defaultEntailmentRegime");
+    pub const ENTAILMENT_REGIME: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/sparql-service-description#entailmentRegime");
+    pub const SUPPORTED_ENTAILMENT_PROFILE: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/sparql-service-description#supportedEntailmentProfile");
+    pub const AVAILABLE_GRAPHS: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/sparql-service-description#availableGraphs");
+    pub const NAMED_GRAPH: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/sparql-service-description#namedGraph");
+    pub const NAME: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/sparql-service-description#name");
+    pub const GRAPH: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/sparql-service-description#graph");
+}
+
+pub mod formats {
+    use oxigraph::model::NamedNodeRef;
+
+    pub const SPARQL_RESULTS_JSON: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/formats/SPARQL_Results_JSON");
+    pub const TURTLE: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/formats/Turtle");
+}
+
+pub mod skos {
+    pub const NAMESPACE: &str = "http://www.w3.org/2004/02/skos/core#";
+}
+
+pub mod obo_in_owl {
+    pub const NAMESPACE: &str = "http://www.geneontology.org/formats/oboInOwl#";
+}
+
+pub mod obo {
+    use oxigraph::model::NamedNodeRef;
+
+    /// IAO:0000115, "definition", the annotation OBO ontologies carry text definitions in.
+    pub const DEFINITION: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://purl.obolibrary.org/obo/IAO_0000115");
+}
+
+pub mod prov {
+    use oxigraph::model::NamedNodeRef;
+
+    pub const ACTIVITY: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/prov#Activity");
+    pub const ENTITY: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/prov#Entity");
+    pub const AGENT: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/prov#Agent");
+    pub const GENERATED: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/prov#generated");
+    pub const WAS_GENERATED_BY: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/prov#wasGeneratedBy");
+    pub const USED: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/prov#used");
+    pub const WAS_ASSOCIATED_WITH: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/prov#wasAssociatedWith");
+    pub const STARTED_AT_TIME: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/prov#startedAtTime");
+    pub const ENDED_AT_TIME: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/prov#endedAtTime");
+    pub const SPECIALIZATION_OF: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/prov#specializationOf");
+    pub const AT_LOCATION: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/prov#atLocation");
+}
+
+pub mod void {
+    use oxigraph::model::NamedNodeRef;
+
+    pub const DATASET: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://rdfs.org/ns/void#Dataset");
+    pub const LINKSET: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://rdfs.org/ns/void#Linkset");
+    pub const TRIPLES: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://rdfs.org/ns/void#triples");
+    pub const ENTITIES: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://rdfs.org/ns/void#entities");
+    pub const CLASSES: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://rdfs.org/ns/void#classes");
+    pub const PROPERTIES: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://rdfs.org/ns/void#properties");
+    pub const DISTINCT_SUBJECTS: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://rdfs.org/ns/void#distinctSubjects");
+    pub const DISTINCT_OBJECTS: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://rdfs.org/ns/void#distinctObjects");
+    pub const CLASS: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://rdfs.org/ns/void#class");
+    pub const PROPERTY: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://rdfs.org/ns/void#property");
+    pub const CLASS_PARTITION: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://rdfs.org/ns/void#classPartition");
+    pub const PROPERTY_PARTITION: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://rdfs.org/ns/void#propertyPartition");
+    pub const SUBSET: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://rdfs.org/ns/void#subset");
+    pub const SUBJECTS_TARGET: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://rdfs.org/ns/void#subjectsTarget");
+    pub const OBJECTS_TARGET: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://rdfs.org/ns/void#objectsTarget");
+}
+
+pub mod hydra {
+    use oxigraph::model::NamedNodeRef;
+
+    pub const COLLECTION: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#Collection");
+    pub const PARTIAL_COLLECTION_VIEW: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#PartialCollectionView");
+    pub const IRI_TEMPLATE: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#IriTemplate");
+    pub const IRI_TEMPLATE_MAPPING: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#IriTemplateMapping");
+    pub const EXPLICIT_REPRESENTATION: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#ExplicitRepresentation");
+    pub const SEARCH: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#search");
+    pub const TEMPLATE: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#template");
+    pub const VARIABLE_REPRESENTATION: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#variableRepresentation");
+    pub const MAPPING: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#mapping");
+    pub const VARIABLE: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#variable");
+    pub const PROPERTY: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#property");
+    pub const TOTAL_ITEMS: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#totalItems");
+    pub const ITEMS_PER_PAGE: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#itemsPerPage");
+    pub const FIRST: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#first");
+    pub const NEXT: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#next");
+    pub const PREVIOUS: NamedNodeRef<'static> = NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#previous");
+}
@@ -0,0 +1,233 @@
+//! VoID descriptions of graphs: how many triples, distinct subjects, predicates and objects they
+//! have, the instances of each class, the triples of each property, and the links into other
+//! graphs.
+//!
+//! Working these out means reading the whole graph, so they are cached in `<Meta>` as JSON on the
+//! graph's entry, with the revision and content hash they were worked out for. The linksets also
+//! depend on what the other graphs hold, so the cache also records a digest of the revision and
+//! content hash of every registered graph. A write to any graph changes that, so the cache is only
+//! used until the next write to the store. Failing to cache the statistics is logged, and they are
+//! worked out again next time.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use oxigraph::SledStore as Store;
+use oxigraph::model::{BlankNode, GraphName, GraphNameRef, Literal, NamedNode, NamedOrBlankNode, Term, Triple};
+use oxigraph::model::vocab::{rdf, xsd};
+
+use serde::{Deserialize, Serialize};
+
+use crate::meta;
+use crate::api::canon::sha256_hex;
+use crate::api::storage::{apply_changes, meta_value_entry};
+use crate::api::vocab::void;
+
+/// The whole store, as the dataset every graph's description is a subset of.
+pub const STORE_DATASET: &str = "http://www.purl.org/dougli1sqrd/models/janus-oxide/Dataset";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct GraphStats {
+    /// The revision and content hash of every graph these were worked out for.
+    pub version: String,
+    pub triples: usize,
+    pub distinct_subjects: usize,
+    pub distinct_predicates: usize,
+    pub distinct_objects: usize,
+    /// Instances of each class.
+    pub classes: BTreeMap<String, usize>,
+    /// Triples of each property.
+    pub properties: BTreeMap<String, usize>,
+    /// Triples whose object is a subject in each other graph.
+    pub linksets: BTreeMap<String, usize>,
+}
+
+/// The statistics of `graph`, from the cache in `<Meta>` if it is still current.
+pub fn stats(store: &Store, graph: &NamedNode) -> GraphStats {
+    match cached_stats(store, graph) {
+        Some(stats) => stats,
+        None => {
+            let stats = compute(store, graph, version_of(store, graph));
+            let (entry, stale) = meta_value_entry(store, graph, meta::stats, Literal::new_simple_literal(serde_json::to_string(&stats).unwrap()));
+            if let Err(e) = apply_changes(store, &stale, &[entry]) {
+                log::warn!("Could not cache the stats of {}: {}", graph, e);
+            }
+            stats
+        }
+    }
+}
+
+/// The statistics of `graph` if the cache in `<Meta>` has them for what is in the graph now,
+/// without working them out otherwise.
+pub fn cached_stats(store: &Store, graph: &NamedNode) -> Option<GraphStats> {
+    let version = version_of(store, graph);
+    cached(store, graph).filter(|stats| stats.version == version)
+}
+
+fn cached(store: &Store, graph: &NamedNode) -> Option<GraphStats> {
+    store.quads_for_pattern(Some(graph.as_ref().into()), Some(NamedNode::from(meta::stats).as_ref()), None, Some(meta::meta_graph_uri()))
+        .filter_map(Result::ok)
+        .find_map(|q| match q.object {
+            Term::Literal(l) => serde_json::from_str(l.value()).ok(),
+            _ => None,
+        })
+}
+
+/// The revision and content hash of `graph`, followed by a digest of those of every registered
+/// graph, and when they were last written.
+fn version_of(store: &Store, graph: &NamedNode) -> String {
+    let mut versions: BTreeMap<String, [String; 3]> = BTreeMap::new();
+    let recorded = [meta::currentRevision, meta::contentHash, meta::modified];
+    for (i, predicate) in recorded.iter().enumerate() {
+        let predicate = NamedNode::from(*predicate);
+        for quad in store.quads_for_pattern(None, Some(predicate.as_ref()), None, Some(meta::meta_graph_uri())).filter_map(Result::ok) {
+            if let Term::Literal(value) = quad.object {
+                versions.entry(quad.subject.to_string()).or_default()[i] = value.value().to_owned();
+            }
+        }
+    }
+
+    let own = versions.get(&graph.to_string()).cloned().unwrap_or_default();
+    let store_version: String = versions.iter().map(|(g, [revision, hash, modified])| format!("{} {} {} {}\n", g, revision, hash, modified)).collect();
+    format!("{}:{}:{}", own[0], own[1], sha256_hex(&store_version))
+}
+
+fn compute(store: &Store, graph: &NamedNode, version: String) -> GraphStats {
+    let mut triples = 0;
+    let mut subjects = HashSet::new();
+    let mut objects = HashSet::new();
+    let mut properties: HashMap<NamedNode, usize> = HashMap::new();
+    let mut instances: HashMap<NamedNode, HashSet<NamedOrBlankNode>> = HashMap::new();
+    let mut linked: HashMap<NamedNode, usize> = HashMap::new();
+
+    for quad in store.quads_for_pattern(None, None, None, Some(GraphNameRef::NamedNode(graph.as_ref()))).filter_map(Result::ok) {
+        triples += 1;
+        *properties.entry(quad.predicate.clone()).or_insert(0) += 1;
+        if quad.predicate.as_ref() == rdf::TYPE {
+            if let Term::NamedNode(class) = &quad.object {
+                instances.entry(class.clone()).or_insert_with(HashSet::new).insert(quad.subject.clone());
+            }
+        }
+        if let Term::NamedNode(object) = &quad.object {
+            *linked.entry(object.clone()).or_insert(0) += 1;
+        }
+        subjects.insert(quad.subject);
+        objects.insert(quad.object);
+    }
+
+    let reserved = meta::reserved_graph_uris();
+    let mut linksets = BTreeMap::new();
+    for (object, count) in linked {
+        let targets: HashSet<NamedNode> = store.quads_for_pattern(Some(object.as_ref().into()), None, None, None)
+            .filter_map(Result::ok)
+            .filter_map(|q| match q.graph_name {
+                GraphName::NamedNode(target) if target != *graph && !reserved.contains(&GraphNameRef::NamedNode(target.as_ref())) => Some(target),
+                _ => None,
+            })
+            .collect();
+        for target in targets {
+            *linksets.entry(target.as_str().to_owned()).or_insert(0) += count;
+        }
+    }
+
+    GraphStats {
+        version,
+        triples,
+        distinct_subjects: subjects.len(),
+        distinct_predicates: properties.len(),
+        distinct_objects: objects.len(),
+        classes: instances.into_iter().map(|(c, i)| (c.as_str().to_owned(), i.len())).collect(),
+        properties: properties.into_iter().map(|(p, n)| (p.as_str().to_owned(), n)).collect(),
+        linksets,
+    }
+}
+
+/// The VoID description of `graph`.
+pub fn describe(graph: &NamedNode, stats: &GraphStats) -> Vec<Triple> {
+    let count = |n: usize| Literal::new_typed_literal(n.to_string(), xsd::INTEGER);
+    let mut triples = vec![
+        Triple::new(graph.clone(), rdf::TYPE, void::DATASET),
+        Triple::new(graph.clone(), void::TRIPLES, count(stats.triples)),
+        Triple::new(graph.clone(), void::DISTINCT_SUBJECTS, count(stats.distinct_subjects)),
+        Triple::new(graph.clone(), void::PROPERTIES, count(stats.distinct_predicates)),
+        Triple::new(graph.clone(), void::DISTINCT_OBJECTS, count(stats.distinct_objects)),
+        Triple::new(graph.clone(), void::CLASSES, count(stats.classes.len())),
+    ];
+    for (class, instances) in stats.classes.iter() {
+        let partition = BlankNode::default();
+        triples.push(Triple::new(graph.clone(), void::CLASS_PARTITION, partition.clone()));
+        triples.push(Triple::new(partition.clone(), void::CLASS, NamedNode::new_unchecked(class.as_str())));
+        triples.push(Triple::new(partition, void::ENTITIES, count(*instances)));
+    }
+    for (property, uses) in stats.properties.iter() {
+        let partition = BlankNode::default();
+        triples.push(Triple::new(graph.clone(), void::PROPERTY_PARTITION, partition.clone()));
+        triples.push(Triple::new(partition.clone(), void::PROPERTY, NamedNode::new_unchecked(property.as_str())));
+        triples.push(Triple::new(partition, void::TRIPLES, count(*uses)));
+    }
+    for (target, links) in stats.linksets.iter() {
+        let linkset = BlankNode::default();
+        triples.push(Triple::new(linkset.clone(), rdf::TYPE, void::LINKSET));
+        triples.push(Triple::new(linkset.clone(), void::SUBJECTS_TARGET, graph.clone()));
+        triples.push(Triple::new(linkset.clone(), void::OBJECTS_TARGET, NamedNode::new_unchecked(target.as_str())));
+        triples.push(Triple::new(linkset, void::TRIPLES, count(*links)));
+    }
+    triples
+}
+
+/// The VoID description of the whole store, made up of `graphs` as its subsets.
+pub fn describe_store(store: &Store, graphs: &[NamedNode]) -> Vec<Triple> {
+    let dataset = NamedNode::new_unchecked(STORE_DATASET);
+    let mut total = 0;
+    let mut triples = vec![Triple::new(dataset.clone(), rdf::TYPE, void::DATASET)];
+    for graph in graphs {
+        let stats = stats(store, graph);
+        total += stats.triples;
+        triples.push(Triple::new(dataset.clone(), void::SUBSET, graph.clone()));
+        triples.extend(describe(graph, &stats));
+    }
+    triples.push(Triple::new(dataset, void::TRIPLES, Literal::new_typed_literal(total.to_string(), xsd::INTEGER)));
+    triples
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::GraphType;
+    use crate::api::storage::load_triples_into_new_graph;
+    use crate::api::storage::test::init_store;
+
+    #[test]
+    fn test_stats() {
+        let (s, _f) = init_store();
+        let ex = |local: &str| NamedNode::new_unchecked(format!("http://example.com/{}", local));
+        let people = ex("people");
+        let places = ex("places");
+        load_triples_into_new_graph(&s, places.clone(), GraphType::Model, vec![Triple::new(ex("paris"), rdf::TYPE, ex("City"))]);
+        load_triples_into_new_graph(&s, people.clone(), GraphType::Model, vec![
+            Triple::new(ex("alice"), rdf::TYPE, ex("Person")),
+            Triple::new(ex("bob"), rdf::TYPE, ex("Person")),
+            Triple::new(ex("alice"), ex("livesIn"), ex("paris")),
+        ]);
+
+        let counted = stats(&s, &people);
+        assert_eq!(3, counted.triples);
+        assert_eq!(2, counted.distinct_subjects);
+        assert_eq!(2, counted.distinct_predicates);
+        assert_eq!(2, counted.distinct_objects);
+        assert_eq!(Some(&2), counted.classes.get("http://example.com/Person"));
+        assert_eq!(Some(&1), counted.properties.get("http://example.com/livesIn"));
+        assert_eq!(Some(&1), counted.linksets.get("http://example.com/places"));
+        assert_eq!(Some(counted), cached(&s, &people));
+
+        // A new graph about paris is linked to, though people itself hasn't changed
+        load_triples_into_new_graph(&s, ex("towns"), GraphType::Model, vec![Triple::new(ex("paris"), rdf::TYPE, ex("Town"))]);
+        assert_eq!(None, cached_stats(&s, &people));
+        assert_eq!(Some(&1), stats(&s, &people).linksets.get("http://example.com/towns"));
+
+        let description = describe_store(&s, &[people.clone(), places]);
+        assert!(description.contains(&Triple::new(NamedNode::new_unchecked(STORE_DATASET), void::TRIPLES, Literal::new_typed_literal("4", xsd::INTEGER))));
+
+        load_triples_into_new_graph(&s, people.clone(), GraphType::Model, vec![Triple::new(ex("carol"), rdf::TYPE, ex("Person"))]);
+        assert_eq!(3, stats(&s, &people).distinct_subjects);
+    }
+}
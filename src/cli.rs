@@ -0,0 +1,92 @@
+//! Commands run instead of the server, for when it is stopped:
+//!
+//! ```text
+//! janus-oxide dump <archive> [--data <dir>]
+//! janus-oxide restore <archive> [--data <dir>]
+//! janus-oxide fsck [--repair] [--data <dir>]
+//! ```
+//!
+//! The archive's format comes from its name, `.nq.gz` or `.trig.gz`. The store is the `data`
+//! directory unless `--data` says otherwise.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use oxigraph::SledStore as Store;
+
+use crate::api::backup::{self, ArchiveFormat};
+use crate::api::fsck;
+
+/// Runs the command in `args`, if there is one. `None` means the server should be launched.
+pub fn run(args: &[String]) -> Option<Result<(), String>> {
+    match args.first().map(String::as_str) {
+        Some("dump") => Some(dump(&args[1..])),
+        Some("restore") => Some(restore(&args[1..])),
+        Some("fsck") => Some(check(&args[1..])),
+        _ => None,
+    }
+}
+
+fn dump(args: &[String]) -> Result<(), String> {
+    let (archive, format, data) = archive_args(args)?;
+    let store = Store::open(data).map_err(|e| e.to_string())?;
+    let file = File::create(archive).map_err(|e| format!("{}: {}", archive, e))?;
+
+    let quads = backup::dump(&store, format, BufWriter::new(file))?;
+    println!("Dumped {} quads to {}", quads, archive);
+    Ok(())
+}
+
+fn restore(args: &[String]) -> Result<(), String> {
+    let (archive, format, data) = archive_args(args)?;
+    let file = File::open(archive).map_err(|e| format!("{}: {}", archive, e))?;
+    let store = Store::open(data).map_err(|e| e.to_string())?;
+
+    let report = backup::restore(&store, format, BufReader::new(file))?;
+    println!("Restored {} quads in {} registered graphs into {}", report.quads, report.graphs, data);
+    for problem in report.fsck.problems.iter() {
+        println!("{}", serde_json::to_string(problem).unwrap());
+    }
+    if !report.inconsistent.is_empty() {
+        Err(format!("These graphs don't match their recorded content hash: {}", report.inconsistent.join(", ")))
+    } else if !report.fsck.is_clean() {
+        Err(format!("{} problems found; janus-oxide fsck --repair --data {} fixes what it can", report.fsck.problems.len(), data))
+    } else {
+        Ok(())
+    }
+}
+
+fn check(args: &[String]) -> Result<(), String> {
+    let repair = args.first().map_or(false, |a| a == "--repair");
+    let data = data_arg(&args[if repair { 1 } else { 0 }..]).ok_or_else(|| "Expected [--repair] [--data <dir>]".to_owned())?;
+    let store = Store::open(data).map_err(|e| e.to_string())?;
+
+    let report = fsck::check(&store, repair)?;
+    for problem in report.problems.iter() {
+        let fixed = if report.repaired.contains(problem) { " (repaired)" } else { "" };
+        println!("{}{}", serde_json::to_string(problem).unwrap(), fixed);
+    }
+    if report.is_clean() {
+        Ok(())
+    } else {
+        Err(format!("{} problems left", report.problems.len() - report.repaired.len()))
+    }
+}
+
+/// The archive path, its format and the data directory.
+fn archive_args(args: &[String]) -> Result<(&str, ArchiveFormat, &str), String> {
+    let usage = || "Expected <archive>.nq.gz or <archive>.trig.gz [--data <dir>]".to_owned();
+    let archive = args.first().ok_or_else(usage)?;
+    let format = ArchiveFormat::from_file_name(archive).ok_or_else(usage)?;
+    let data = data_arg(&args[1..]).ok_or_else(usage)?;
+    Ok((archive, format, data))
+}
+
+/// The data directory, `data` unless given with `--data`.
+fn data_arg(args: &[String]) -> Option<&str> {
+    match args {
+        [] => Some("data"),
+        [flag, dir] if flag == "--data" => Some(dir.as_str()),
+        _ => None,
+    }
+}
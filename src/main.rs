@@ -3,30 +3,97 @@
 #[macro_use]
 extern crate rocket;
 
+use std::time::Duration;
+
 use oxigraph::SledStore as Store;
 
-fn prelaunch() -> Store {
+fn prelaunch() -> (Store, api::alias::Aliases, api::search::SearchIndex, api::autocomplete::Autocomplete) {
     let store = Store::open("data").unwrap();
     api::storage::init(&store);
-    store
+    let aliases = api::alias::Aliases::load(&store);
+    let search = api::search::SearchIndex::build(&store);
+    let autocomplete = api::autocomplete::Autocomplete::build(&store);
+    (store, aliases, search, autocomplete)
+}
+
+/// Rate limits and the query timeout, from the `write_requests_per_minute`,
+/// `query_requests_per_minute`, `query_timeout_secs`, `query_workers` and `trusted_proxies` extras
+/// of the Rocket config.
+fn limits(config: &rocket::Config) -> api::limits::Limits {
+    let defaults = api::limits::Limits::default();
+    let per_minute = |key: &str, default: u32| config.get_int(key).map(|n| n as u32).unwrap_or(default);
+    let query_timeout = match config.get_int("query_timeout_secs") {
+        Ok(0) => None,
+        Ok(secs) => Some(Duration::from_secs(secs as u64)),
+        Err(_) => defaults.query_timeout,
+    };
+    let mut limits = api::limits::Limits::new(
+        per_minute("write_requests_per_minute", defaults.writes.per_minute()),
+        per_minute("query_requests_per_minute", defaults.queries.per_minute()),
+        query_timeout,
+        config.get_int("query_workers").map(|n| n.max(1) as usize).unwrap_or_else(|_| defaults.query_workers.size()),
+    );
+    limits.trusted_proxies = config.get_slice("trusted_proxies")
+        .map(|proxies| proxies.iter().filter_map(|p| p.as_str()).filter_map(|p| p.parse().ok()).collect())
+        .unwrap_or_default();
+    limits
 }
 
-#[get("/")]
-fn index() -> &'static str {
-    "Hello, World!"
+/// The `import_catalog` extra of the Rocket config, if given.
+fn catalog_location(config: &rocket::Config) -> api::imports::CatalogLocation {
+    config.get_str("import_catalog")
+        .map(|path| api::imports::CatalogLocation(path.into()))
+        .unwrap_or_default()
+}
+
+/// The relation names of the `relations` table extra of the Rocket config, as `name = "IRI"`,
+/// on top of the built-in ones.
+fn relations(config: &rocket::Config) -> Result<api::term::Relations, String> {
+    match config.get_table("relations") {
+        Ok(table) => api::term::Relations::with(table.iter().filter_map(|(name, iri)| iri.as_str().map(|iri| (name.as_str(), iri)))),
+        Err(_) => Ok(api::term::Relations::default()),
+    }
 }
 
 mod api;
+mod cli;
 mod routes;
 pub mod meta;
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(result) = cli::run(&args) {
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     println!("Hello, world!");
 
-    let store = prelaunch();
+    let (store, aliases, search, autocomplete) = prelaunch();
+    let auth = api::auth::Auth::load(api::auth::auth_path()).unwrap();
+
+    let rocket = rocket::ignite();
+    let limits = limits(rocket.config());
+    let catalog = catalog_location(rocket.config());
+    let relations = relations(rocket.config()).unwrap();
 
-    rocket::ignite()
+    rocket
         .manage(store)
-        .mount("/", routes![index, routes::graphs, routes::get_graph, routes::add_new_graph_by_ttl])
+        .manage(auth)
+        .manage(aliases)
+        .manage(limits)
+        .manage(search)
+        .manage(autocomplete)
+        .manage(catalog)
+        .manage(relations)
+        .attach(rocket_contrib::templates::Template::fairing())
+        .mount("/", routes![routes::index, routes::graphs, routes::get_graph, routes::graph_triples_matching, routes::graph_versions, routes::graph_provenance, routes::graph_stats, routes::store_void, routes::graph_acl, routes::grant_access, routes::revoke_access, routes::set_alias, routes::remove_alias, routes::validate_graph, routes::get_term, routes::term_ancestors, routes::term_descendants, routes::search, routes::autocomplete, routes::add_new_graph_by_ttl,
+            routes::remove_ontology_source, routes::diff_graphs, routes::sparql_query, routes::sparql_query_by_post, routes::service_description, routes::service_description_as_jsonld, routes::dump_store, routes::check_store, routes::repair_store,
+            routes::adoptable_graphs, routes::adopt_graphs,
+            routes::gsp_get, routes::gsp_put, routes::gsp_post, routes::gsp_delete, routes::get_resource,
+            routes::browse_graph, routes::query_page, routes::triple_pattern_fragment])
         .launch();
 }
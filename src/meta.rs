@@ -28,6 +28,37 @@ pub fn meta_graph_uri() -> GraphNameRef<'static> {
     )
 }
 
+/// The graph holding the changesets that make up the revision history of every
+/// Meta-registered graph. Like `<Meta>` and `<MetaOnt>`, this cannot be written to directly.
+pub fn history_graph_uri() -> GraphNameRef<'static> {
+    GraphNameRef::NamedNode(
+        NamedNodeRef::new("http://www.purl.org/dougli1sqrd/models/janus-oxide/History").unwrap(),
+    )
+}
+
+/// The one graph all merged ontology data goes into, `<O>`. Each upload merged into it is kept
+/// as a `meta:OntologySource` graph as well, so that it can later be replaced or removed.
+pub fn ontology_graph_uri() -> NamedNodeRef<'static> {
+    NamedNodeRef::new("http://www.purl.org/dougli1sqrd/models/janus-oxide/O").unwrap()
+}
+
+/// The graph holding a PROV-O activity for every load into a Meta-registered graph. Each graph
+/// with provenance points here from `<Meta>` with `meta:hasProvenanceAt`.
+pub fn provenance_graph_uri() -> GraphNameRef<'static> {
+    GraphNameRef::NamedNode(
+        NamedNodeRef::new("http://www.purl.org/dougli1sqrd/models/janus-oxide/Provenance").unwrap(),
+    )
+}
+
+/// The namespace of the nodes standing for the quoted triples of Turtle-star uploads. Nothing
+/// else is named in it, so these nodes can be told apart from reifications written by hand.
+pub const QUOTED_TRIPLE_NAMESPACE: &str = "http://www.purl.org/dougli1sqrd/models/janus-oxide/quoted/";
+
+/// Graphs that Janus maintains itself and that may not be loaded into or read as user graphs.
+pub fn reserved_graph_uris() -> Vec<GraphNameRef<'static>> {
+    vec![meta_graph_uri(), meta_ontology_uri(), history_graph_uri(), provenance_graph_uri()]
+}
+
 namespace!(
     "http://www.purl.org/dougli1sqrd/models/janus-oxide/Meta/",
     // classes
@@ -36,10 +67,38 @@ namespace!(
     Closure,
     Model,
     Inferred,
+    Shapes,
     Unknown,
+    OntologySource,
+    Revision,
     // relations
     inferredFrom,
-    hasInferencesAt
+    hasInferencesAt,
+    revisionOf,
+    imports,
+    mergedInto,
+    added,
+    removed,
+    hasProvenanceAt,
+    claimedUploader,
+    // attributes
+    revisionNumber,
+    revisionTime,
+    currentRevision,
+    modified,
+    contentHash,
+    tripleCount,
+    triplesRemoved,
+    format,
+    reasonerProfile,
+    readableBy,
+    writableBy,
+    administeredBy,
+    alias,
+    stats,
+    countIsLowerBound,
+    // functions
+    textMatch
 );
 
 #[cfg(test)]
@@ -1,27 +1,71 @@
 use std::str;
+use std::collections::HashSet;
 use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
 
-use rocket::http::RawStr;
-use rocket::request::{FromFormValue, FromSegments};
-use rocket::response::status;
-use rocket::State;
+use rocket::data::{self, Data, FromDataSimple};
+use rocket::http::{ContentType, RawStr, Status};
+use rocket::request::{self, Form, FromFormValue, FromParam, FromRequest, FromSegments, Request};
+use rocket::response::{self, content, status, Responder, Stream};
+use rocket::{Outcome, State};
 use rocket_contrib::json;
+use rocket_contrib::templates::Template;
 use rocket::http::uri::Segments;
 
 use oxigraph::SledStore as Store;
-use oxigraph::model::{NamedNode};
+use oxigraph::io::GraphFormat;
+use oxigraph::model::{GraphName, GraphNameRef, NamedNode, Quad, Triple};
+use oxigraph::model::vocab::rdfs;
 
-use crate::api::{UriWrapper, GraphType, KnownGraphType, GraphData, GraphList};
-use crate::api::storage::{load_turtle_into_new_graph, read_graph_as_ttl_string, accounted_graph_list};
+use crate::api::{BaseUrl, UriWrapper, GraphType, KnownGraphType, GraphData, GraphList, ExportFormat, CONTEXT, unbracket};
+use crate::api::storage::{apply_changes, delete_graph, parse_graph, rewrite_graph, load_new_graph, new_graph_uri, read_graph_as_ttl_string, read_graph_as_canonical_ntriples, triples_as_ttl_string,
+    accounted_graph_list, content_hash, graph_triples, is_unchanged_upload, parse_turtle};
+use crate::api::alias::{self, AliasError, Aliases, GraphName};
+use crate::api::backup::{self, ArchiveFormat};
+use crate::api::adopt::{self, Proposal};
+use crate::api::fsck::{self, FsckReport};
+use crate::api::gsp::{self, StoreFormat};
+use crate::api::auth::{self, Access, Auth, GraphAccess, Principal};
+use crate::api::canon;
+use crate::api::imports;
+use crate::api::limits::{Limits, QueryQuota, Throttle, Upload, WriteQuota, DEFAULT_UPLOAD_LIMIT};
+use crate::api::ontology::{self, MergeReport};
+use crate::api::provenance::{self, Origin};
+use crate::api::resource::{self, ResourceFormat};
+use crate::api::history::{self, VersionList};
+use crate::api::diff::{self, DiffFormat};
+use crate::api::fragments::{self, TriplePattern};
+use crate::api::listing::{self, GraphQuery, GraphSort};
+use crate::api::autocomplete::{Autocomplete, Suggestions};
+use crate::api::browse;
+use crate::api::jsonld;
+use crate::api::service;
+use crate::api::search::{SearchIndex, SearchResults};
+use crate::api::shacl;
+use crate::api::sparql::{self, Answer, QueryError};
+use crate::api::star;
+use crate::api::void;
+use crate::api::term::{self, Direction, Relation, RelationName, Relations, TermInfo, Traversal};
 use crate::meta;
 
 
-impl<'u> FromSegments<'u> for UriWrapper {
+impl<'u> FromSegments<'u> for GraphName {
     type Error = &'u RawStr;
 
-    fn from_segments(param: Segments<'u>) -> Result<UriWrapper, Self::Error> {
+    fn from_segments(param: Segments<'u>) -> Result<GraphName, Self::Error> {
         let raw: &'u RawStr = RawStr::from_str(param.0);
-        decode_uri(raw)
+        decode_graph_name(raw)
+    }
+}
+
+/// A URI in a single path segment, with any `/` percent-encoded, so that more path can follow
+/// it, as in `/term/<iri>/ancestors`.
+impl<'u> FromParam<'u> for UriWrapper {
+    type Error = &'u RawStr;
+
+    fn from_param(param: &'u RawStr) -> Result<UriWrapper, Self::Error> {
+        decode_uri(param)
     }
 }
 
@@ -33,81 +77,1391 @@ impl<'u> FromFormValue<'u> for UriWrapper {
     }
 }
 
-impl<'v> FromFormValue<'v> for GraphType {
+/// A graph in a single path segment, as for [`UriWrapper`], or by its alias, as in
+/// `/graph/go-plus/versions`.
+impl<'u> FromParam<'u> for GraphName {
+    type Error = &'u RawStr;
+
+    fn from_param(param: &'u RawStr) -> Result<GraphName, Self::Error> {
+        decode_graph_name(param)
+    }
+}
+
+impl<'u> FromFormValue<'u> for GraphName {
+    type Error = &'u RawStr;
+
+    fn from_form_value(form_value: &'u RawStr) -> Result<GraphName, Self::Error> {
+        decode_graph_name(form_value)
+    }
+}
+
+/// Query values of the types named by strings, like `?format=turtle` or `?sort=-modified`, which
+/// are parsed with their `TryFrom<&str>`.
+macro_rules! from_form_value_by_name {
+    ($($t:ty),*) => {$(
+        impl<'v> FromFormValue<'v> for $t {
+            type Error = &'v RawStr;
+
+            fn from_form_value(form_value: &'v RawStr) -> Result<$t, &'v RawStr> {
+                str::from_utf8(form_value.as_bytes()).ok()
+                    .and_then(|s| <$t>::try_from(s).ok())
+                    .ok_or(form_value)
+            }
+        }
+    )*};
+}
+
+from_form_value_by_name!(GraphType, ExportFormat, ArchiveFormat, GraphSort, DiffFormat, Access);
+
+/// A relation by one of its short names, like `part_of`, or by its IRI in angle brackets.
+impl<'v> FromFormValue<'v> for RelationName {
     type Error = &'v RawStr;
 
-    fn from_form_value(form_value: &'v RawStr) -> Result<GraphType, &'v RawStr> {
-        let slice = str::from_utf8(form_value.as_bytes());
-        match slice {
-            Ok(s) => match GraphType::try_from(s) {
-                Ok(g) => Ok(g),
-                Err(_) => Err(form_value),
-            },
-            Err(_) => Err(form_value),
+    fn from_form_value(form_value: &'v RawStr) -> Result<RelationName, &'v RawStr> {
+        match decode_uri(form_value) {
+            Ok(uri) => Ok(RelationName::Iri(uri.0)),
+            Err(_) => form_value.url_decode().map(RelationName::Name).map_err(|_| form_value),
         }
     }
 }
 
-fn decode_uri(raw_uri: &RawStr) -> Result<UriWrapper, &RawStr> {
-    let decoded = raw_uri.percent_decode().unwrap();
-    if decoded.starts_with('<') && decoded.ends_with('>') {
-        let unbracketed = decoded.trim_start_matches('<').trim_end_matches('>');
-        match NamedNode::new(unbracketed) {
-            Ok(named) => Ok(UriWrapper(named)),
-            Err(_) => Err(raw_uri)
+/// What an upload says about where its data came from: `Content-Location` names the file or
+/// URL, `From` who the client says uploaded it, and `X-Reasoner-Profile` the reasoner that made an
+/// Inferred or Closure graph. The user comes from the credentials, not from any of these.
+impl<'a, 'r> FromRequest<'a, 'r> for Origin {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Origin, ()> {
+        let headers = request.headers();
+        Outcome::Success(Origin {
+            source: headers.get_one("Content-Location").map(str::to_owned),
+            user: request.guard::<Principal>().succeeded().and_then(|p| p.user),
+            uploader: headers.get_one("From").map(str::to_owned),
+            format: request.content_type().map(|c| c.media_type().to_string()),
+            reasoner: headers.get_one("X-Reasoner-Profile").map(str::to_owned),
+        })
+    }
+}
+
+/// The server as the request reached it: `Host`, with `https` if a proxy says so in
+/// `X-Forwarded-Proto`.
+impl<'a, 'r> FromRequest<'a, 'r> for BaseUrl {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<BaseUrl, ()> {
+        let headers = request.headers();
+        let scheme = headers.get_one("X-Forwarded-Proto").unwrap_or("http");
+        let host = headers.get_one("Host").unwrap_or("localhost:8000");
+        Outcome::Success(BaseUrl(format!("{}://{}", scheme, host)))
+    }
+}
+
+/// The preferred of HTML, Turtle and JSON-LD going by the `Accept` header, Turtle if it names
+/// none of them.
+impl<'a, 'r> FromRequest<'a, 'r> for ResourceFormat {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<ResourceFormat, ()> {
+        let mut accepted: Vec<_> = request.accept().map_or_else(Vec::new, |accept| accept.iter().cloned().collect());
+        accepted.sort_by(|a, b| b.weight_or(1.0).partial_cmp(&a.weight_or(1.0)).unwrap_or(std::cmp::Ordering::Equal));
+        let format = accepted.iter().map(|media| media.media_type()).find_map(|media| {
+            if (media.top() == "text" && media.sub() == "html") || (media.top() == "application" && media.sub() == "xhtml+xml") {
+                Some(ResourceFormat::Html)
+            } else if media.top() == "text" && media.sub() == "turtle" {
+                Some(ResourceFormat::Turtle)
+            } else if media.top() == "application" && media.sub() == "ld+json" {
+                Some(ResourceFormat::JsonLd)
+            } else {
+                None
+            }
+        });
+        Outcome::Success(format.unwrap_or(ResourceFormat::Turtle))
+    }
+}
+
+/// The preferred of Turtle, N-Triples, RDF/XML and JSON-LD going by the `Accept` header, Turtle
+/// if it names none of them.
+impl<'a, 'r> FromRequest<'a, 'r> for StoreFormat {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<StoreFormat, ()> {
+        let mut accepted: Vec<_> = request.accept().map_or_else(Vec::new, |accept| accept.iter().cloned().collect());
+        accepted.sort_by(|a, b| b.weight_or(1.0).partial_cmp(&a.weight_or(1.0)).unwrap_or(std::cmp::Ordering::Equal));
+        let format = accepted.iter().map(|media| media.media_type()).find_map(|media| StoreFormat::from_media_type(media.top(), media.sub()));
+        Outcome::Success(format.unwrap_or(StoreFormat::Turtle))
+    }
+}
+
+/// Who a request acts as, from its `X-API-Key` header or its `Authorization: Bearer` token.
+/// Credentials that don't check out are a `401`.
+impl<'a, 'r> FromRequest<'a, 'r> for Principal {
+    type Error = String;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Principal, String> {
+        let auth = match request.guard::<State<Auth>>().succeeded() {
+            Some(auth) => auth,
+            None => return Outcome::Failure((Status::InternalServerError, "Auth is not set up".to_owned())),
+        };
+        let headers = request.headers();
+        let bearer = headers.get_one("Authorization").and_then(|h| h.strip_prefix("Bearer ")).map(str::trim);
+        match auth.authenticate(headers.get_one("X-API-Key"), bearer) {
+            Ok(principal) => Outcome::Success(principal),
+            Err(e) => Outcome::Failure((Status::Unauthorized, e)),
         }
+    }
+}
+
+/// Reads a request body up to the limit for its format: the `limits` entry named after the
+/// media subtype, like `turtle` or `sparql-query`, or `DEFAULT_UPLOAD_LIMIT`. Anything bigger is a `413`.
+impl FromDataSimple for Upload {
+    type Error = String;
+
+    fn from_data(request: &Request, data: Data) -> data::Outcome<Upload, String> {
+        let format = request.content_type().map_or_else(|| "bytes".to_owned(), |c| c.sub().to_string());
+        let limit = request.limits().get(&format).unwrap_or(DEFAULT_UPLOAD_LIMIT);
+
+        let mut bytes = Vec::new();
+        match data.open().take(limit + 1).read_to_end(&mut bytes) {
+            Ok(read) if read as u64 > limit => Outcome::Failure((Status::PayloadTooLarge, format!("Uploads of {} are limited to {} bytes", format, limit))),
+            Ok(_) => Outcome::Success(Upload(bytes)),
+            Err(e) => Outcome::Failure((Status::BadRequest, e.to_string())),
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for WriteQuota {
+    type Error = String;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<WriteQuota, String> {
+        admit(request, |limits| &limits.writes).map(|_| WriteQuota)
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for QueryQuota {
+    type Error = String;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<QueryQuota, String> {
+        admit(request, |limits| &limits.queries).map(|_| QueryQuota)
+    }
+}
+
+/// Counts a request against one of its client's rate limits, turning it away with a `429` if it
+/// is over. Clients are told apart by user, or by address when they don't say who they are.
+fn admit<'a, 'r>(request: &'a Request<'r>, throttle: fn(&Limits) -> &Throttle) -> request::Outcome<(), String> {
+    let limits = match request.guard::<State<Limits>>().succeeded() {
+        Some(limits) => limits,
+        None => return Outcome::Failure((Status::InternalServerError, "Limits are not set up".to_owned())),
+    };
+    let client = match request.guard::<Principal>().succeeded().and_then(|p| p.user) {
+        Some(user) => user,
+        None => limits.client_address(request.remote().map(|a| a.ip()), request.real_ip())
+            .map_or_else(|| "unknown".to_owned(), |ip| ip.to_string()),
+    };
+    match throttle(&limits).admit(&client) {
+        Ok(()) => Outcome::Success(()),
+        Err(wait) => Outcome::Failure((Status::TooManyRequests, format!("Too many requests, try again in {}s", wait.as_secs() + 1))),
+    }
+}
+
+/// Refuses a request `principal` may not make of `graph`: `401` if it didn't say who it is, `403` if it did.
+fn denied(principal: &Principal, graph: &NamedNode) -> status::Custom<String> {
+    if principal.is_anonymous() {
+        status::Custom(Status::Unauthorized, format!("Credentials are needed for {}", graph))
     } else {
-        Err(raw_uri)
+        status::Custom(Status::Forbidden, format!("{} may not do that with {}", principal.user.as_deref().unwrap_or("Nobody"), graph))
+    }
+}
+
+/// Refuses a request that only admins may make: `401` without credentials, `403` with.
+fn admins_only(principal: &Principal) -> Result<(), status::Custom<String>> {
+    if principal.admin {
+        Ok(())
+    } else if principal.is_anonymous() {
+        Err(status::Custom(Status::Unauthorized, "Credentials are needed".to_owned()))
+    } else {
+        Err(status::Custom(Status::Forbidden, format!("{} is not an admin", principal.user.as_deref().unwrap_or("Nobody"))))
+    }
+}
+
+fn bad_request(message: String) -> status::Custom<String> {
+    status::Custom(Status::BadRequest, message)
+}
+
+/// Whether `graph` is registered in `<Meta>` and `principal` may read it. Graphs it may not read
+/// are reported as missing, rather than giving away that they exist.
+fn is_readable(store: &Store, principal: &Principal, graph: &NamedNode) -> bool {
+    accounted_graph_list(store).graphs.iter().any(|g: &GraphData| g.id == graph.to_string())
+        && principal.may(store, graph.as_ref(), Access::Read)
+}
+
+/// The graphs a query may look at: those `principal` may read, kept to `graph` if one is given.
+/// `None` is every graph.
+fn query_scope(store: &Store, principal: &Principal, graph: Option<NamedNode>) -> Option<HashSet<NamedNode>> {
+    let readable = principal.permitted(store, Access::Read);
+    match graph {
+        Some(graph) => {
+            let mut only = HashSet::new();
+            if readable.map_or(true, |r| r.contains(&graph)) {
+                only.insert(graph);
+            }
+            Some(only)
+        }
+        None => readable,
+    }
+}
+
+/// A URI as `<uri>`, percent-encoded.
+fn decode_uri(raw_uri: &RawStr) -> Result<UriWrapper, &RawStr> {
+    let decoded = raw_uri.percent_decode().map_err(|_| raw_uri)?;
+    match decoded.strip_prefix('<').and_then(|d| d.strip_suffix('>')) {
+        Some(unbracketed) => NamedNode::new(unbracketed).map(UriWrapper).map_err(|_| raw_uri),
+        None => Err(raw_uri),
+    }
+}
+
+/// A graph as `<uri>`, percent-encoded, or by its alias.
+fn decode_graph_name(raw_name: &RawStr) -> Result<GraphName, &RawStr> {
+    match decode_uri(raw_name) {
+        Ok(uri) => Ok(GraphName::Uri(uri.0)),
+        Err(_) => match raw_name.percent_decode() {
+            Ok(decoded) if alias::is_alias(&decoded) => Ok(GraphName::Alias(decoded.into_owned())),
+            _ => Err(raw_name),
+        },
+    }
+}
+
+/// The graph `name` names, as a route would have it from a `<uri>`.
+fn named_graph(aliases: &Aliases, name: GraphName) -> Result<UriWrapper, String> {
+    aliases.graph(name).map(UriWrapper)
+}
+
+#[derive(FromForm)]
+pub struct GraphListParams {
+    graph_type: Option<GraphType>,
+    q: Option<String>,
+    #[form(field = "modified-since")]
+    modified_since: Option<String>,
+    sort: Option<GraphSort>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    cursor: Option<String>,
+}
+
+impl GraphListParams {
+    /// The query string of these parameters, following `cursor` instead of any offset.
+    fn with_cursor(&self, cursor: &str) -> String {
+        let mut params = Vec::new();
+        if let Some(graph_type) = self.graph_type {
+            params.push(format!("graph_type={}", graph_type.as_ref()));
+        }
+        if let Some(q) = &self.q {
+            params.push(format!("q={}", alias::percent_encode(q)));
+        }
+        if let Some(since) = &self.modified_since {
+            params.push(format!("modified-since={}", alias::percent_encode(since)));
+        }
+        if let Some(sort) = self.sort {
+            params.push(format!("sort={}{}", if sort.descending { "-" } else { "" }, sort.key.as_ref().to_lowercase()));
+        }
+        if let Some(limit) = self.limit {
+            params.push(format!("limit={}", limit));
+        }
+        params.push(format!("cursor={}", cursor));
+        params.join("&")
     }
 }
 
-#[get("/graph?<graph_type>")]
-pub fn graphs(store: State<Store>, graph_type: Option<GraphType>) -> json::Json<GraphList> {
+/// The graphs registered in `<Meta>` that the requester may read. `?graph_type=`, `?q=` (part of
+/// the URI or alias) and `?modified-since=` filter them; `?sort=` sorts them by `id`, `type`,
+/// `modified` or `revision`, descending with a leading `-`; and `?limit=` pages them, from
+/// `?offset=` or the `?cursor=` of the page before.
+#[get("/graph?<params..>")]
+pub fn graphs(store: State<Store>, principal: Principal, params: Form<GraphListParams>) -> Result<json::Json<GraphList>, status::Custom<String>> {
+    let params = params.into_inner();
     let graphs = accounted_graph_list(&store);
-    if let Some(KnownGraphType::Known(g)) = graph_type.map(KnownGraphType::new) {
-        let filtered_graphs: Vec<GraphData> = graphs
-            .graphs
-            .into_iter()
-            .filter(|data| data.graph_type == g)
-            .collect();
-        json::Json(GraphList {
-            context: graphs.context,
-            graphs: filtered_graphs,
+    let readable: Option<HashSet<String>> = principal.permitted(&store, Access::Read)
+        .map(|r| r.iter().map(|g| g.to_string()).collect());
+    let wanted = params.graph_type.map(KnownGraphType::new);
+
+    let filtered_graphs: Vec<GraphData> = graphs
+        .graphs
+        .into_iter()
+        .filter(|data| match wanted {
+            Some(KnownGraphType::Known(g)) => data.graph_type == g,
+            _ => true,
         })
+        .filter(|data| readable.as_ref().map_or(true, |r| r.contains(&data.id)))
+        .collect();
+
+    let modified_since = match &params.modified_since {
+        Some(since) => Some(listing::parse_time(since).ok_or_else(|| bad_request(format!("{} is not a date or time", since)))?),
+        None => None,
+    };
+    let paged = params.limit.is_some() || params.offset.is_some() || params.cursor.is_some();
+    let page = listing::page(filtered_graphs, &GraphQuery {
+        q: params.q.clone(),
+        modified_since,
+        sort: params.sort.unwrap_or_default(),
+        limit: params.limit.map(|limit| limit.min(listing::MAX_PAGE_SIZE)),
+        offset: params.offset,
+        cursor: params.cursor.clone(),
+    }).map_err(bad_request)?;
+    Ok(json::Json(GraphList {
+        context: graphs.context,
+        graphs: page.graphs,
+        total: if paged { Some(page.total) } else { None },
+        next: page.next.map(|cursor| format!("/graph?{}", params.with_cursor(&cursor))),
+    }))
+}
+
+/// A page of the triples of a graph matching `?s=`, `?p=` and `?o=`, any of which may be left
+/// out, as Turtle. `?limit=` triples from `?offset=`; the total is in `X-Total-Count`, and the
+/// next page in a `Link` header.
+#[get("/graph/<graph_uri>/triples?<s>&<p>&<o>&<limit>&<offset>", rank = 1)]
+pub fn graph_triples_matching(store: State<Store>, aliases: State<Aliases>, principal: Principal, _quota: QueryQuota, graph_uri: GraphName, s: Option<String>, p: Option<String>, o: Option<String>,
+                              limit: Option<usize>, offset: Option<usize>) -> Result<TriplePage, status::Custom<String>> {
+    let graph_uri = named_graph(&aliases, graph_uri).map_err(|e| status::Custom(Status::NotFound, e))?;
+    let graph = graph_uri.0;
+    if !is_readable(&store, &principal, &graph) {
+        return Err(status::Custom(Status::NotFound, format!("Graph {} cannot be found!", graph)));
+    }
+    let pattern = TriplePattern::parse(s.as_deref(), p.as_deref(), o.as_deref()).map_err(bad_request)?;
+    let limit = limit.unwrap_or(fragments::DEFAULT_PAGE_SIZE).min(fragments::MAX_PAGE_SIZE);
+    let offset = offset.unwrap_or(0);
+    let fragment = fragments::fragment(&store, &graph, &pattern, offset, limit);
+
+    let next = if offset.saturating_add(limit) < fragment.total {
+        let path = alias::graph_path(graph.as_ref(), alias::alias_of(&store, graph.as_ref()).as_deref());
+        let mut params: Vec<String> = [("s", &s), ("p", &p), ("o", &o)].iter()
+            .filter_map(|(name, value)| value.as_ref().map(|v| format!("{}={}", name, alias::percent_encode(v))))
+            .collect();
+        params.push(format!("limit={}", limit));
+        params.push(format!("offset={}", offset.saturating_add(limit)));
+        Some(format!("{}/triples?{}", path, params.join("&")))
     } else {
-        json::Json(accounted_graph_list(&store))
+        None
+    };
+    let turtle = triples_as_ttl_string(fragment.triples).map_err(|e| status::Custom(Status::InternalServerError, e))?;
+    Ok(TriplePage { turtle, total: fragment.total, next })
+}
+
+/// A page of triples as Turtle, with how many there are on all pages and the next page in headers.
+pub struct TriplePage {
+    turtle: String,
+    total: usize,
+    next: Option<String>,
+}
+
+impl<'r> Responder<'r> for TriplePage {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        let mut response = content::Content(ContentType::new("text", "turtle"), self.turtle).respond_to(request)?;
+        response.set_raw_header("X-Total-Count", self.total.to_string());
+        if let Some(next) = self.next {
+            response.set_raw_header("Link", format!("<{}>; rel=\"next\"", next));
+        }
+        Ok(response)
     }
 }
 
-#[post("/graph?<graph_uri>&<graph_type>", format="text/turtle", data="<triples>")]
-pub fn add_new_graph_by_ttl(store: State<Store>, graph_uri: UriWrapper, graph_type: GraphType, triples: Vec<u8>) -> Result<json::JsonValue, status::BadRequest<String>> {
+/// Load Turtle into a new graph. With `?merge=true`, an ontology is merged into `<O>` instead,
+/// with `graph_uri` naming the source it came from. With `?validate=true`, a Model is checked
+/// against the registered shapes first and rejected with its validation report if it fails.
+///
+/// Uploading to an existing graph needs write access to it. Anyone with credentials may make a
+/// new graph, and is granted admin access to it.
+#[post("/graph?<graph_uri>&<graph_type>&<merge>&<validate>", format="text/turtle", data="<triples>")]
+pub fn add_new_graph_by_ttl(store: State<Store>, aliases: State<Aliases>, autocomplete: State<Autocomplete>, catalog: State<imports::CatalogLocation>, principal: Principal, _quota: WriteQuota, origin: Origin, graph_uri: GraphName, graph_type: GraphType, merge: Option<bool>, validate: Option<bool>, triples: Upload) -> Result<json::JsonValue, status::Custom<String>> {
+    let graph_uri = named_graph(&aliases, graph_uri).map_err(|e| status::Custom(Status::NotFound, e))?;
     println!("loading into {:?}", graph_uri);
+    let Upload(triples) = triples;
+
+    if merge.unwrap_or(false) {
+        return merge_ontology_source(&store, &autocomplete, &catalog, &principal, &origin, graph_uri.0, graph_type, triples);
+    }
 
     let existing_graphs = accounted_graph_list(&store);
 
     if existing_graphs.graphs.into_iter().any(|g| g.id == graph_uri.0.to_string() ) {
-       return Err(status::BadRequest(Some(format!("Graph URI {} already exists!", graph_uri.0))))
-    } else if graph_uri.0.to_string() == meta::meta_graph_uri().to_string() 
-            || graph_uri.0.to_string() == meta::meta_ontology_uri().to_string() {
+       if !principal.may(&store, graph_uri.0.as_ref(), Access::Write) {
+           return Err(denied(&principal, &graph_uri.0));
+       }
+       if is_unchanged_upload(&store, graph_uri.0.as_ref(), &triples).map_err(bad_request)? {
+           return Ok(rocket_contrib::json!({"loaded": 0, "unchanged": true}))
+       }
+       return Err(bad_request(format!("Graph URI {} already exists!", graph_uri.0)))
+    } else if meta::reserved_graph_uris().iter().any(|g| g.to_string() == graph_uri.0.to_string())
+            || ontology::is_ontology_graph(graph_uri.0.as_ref()) {
         
-        return Err(status::BadRequest(Some("Untouchable graph".to_owned())));
+        return Err(bad_request("Untouchable graph".to_owned()));
+    } else if !principal.may_create() {
+        return Err(denied(&principal, &graph_uri.0));
+    }
+
+    let parsed = parse_turtle(&triples).map_err(|e| bad_request(e.to_string()))?;
+    content_hash(&parsed).map_err(bad_request)?;
+    if validate.unwrap_or(false) && graph_type == GraphType::Model {
+        let report = shacl::validate(&parsed, &shacl::registered_shapes(&store));
+        if !report.conforms() {
+            return Err(bad_request(report.to_turtle().unwrap()));
+        }
     }
     
-    let loaded = load_turtle_into_new_graph(&store, graph_uri.0, graph_type, triples);
+    let loaded = load_new_graph(&store, graph_uri.0.clone(), graph_type, parsed, Some(&origin))
+        .map_err(|e| status::Custom(Status::InternalServerError, e))?;
+    grant_creator(&store, &principal, &graph_uri.0);
+
+    if graph_type == GraphType::Ontology {
+        autocomplete.rebuild_graph(&store, &graph_uri.0);
+        let report = imports::resolve_imports(&store, &graph_uri.0, catalog.load().as_ref(), imports::ImportMode::Graph);
+        index_imports(&store, &autocomplete, &report);
+        return Ok(rocket_contrib::json!({"loaded": loaded, "imports": report}))
+    }
     Ok(rocket_contrib::json!({"loaded": loaded}))
 }
 
-#[get("/graph/<graph_uri..>")]
-pub fn get_graph(store: State<Store>, graph_uri: UriWrapper) -> Result<String, status::NotFound<String>> {
+/// Merges an ontology upload into `<O>` as the source `source`, replacing what that source
+/// contributed before if it was merged already.
+/// Merging needs write access to `<O>` once there is one, and to the source if it was merged before.
+/// Its imports are merged into `<O>` too, each as a source of its own.
+fn merge_ontology_source(store: &Store, autocomplete: &Autocomplete, catalog: &imports::CatalogLocation, principal: &Principal, origin: &Origin, source: NamedNode, graph_type: GraphType, triples: Vec<u8>) -> Result<json::JsonValue, status::Custom<String>> {
+    if graph_type != GraphType::Ontology {
+        return Err(bad_request("Only ontologies can be merged".to_owned()));
+    } else if meta::reserved_graph_uris().iter().any(|g| g.to_string() == source.to_string())
+            || ontology::is_ontology_graph(source.as_ref()) {
+        return Err(bad_request("Untouchable graph".to_owned()));
+    }
+
+    let ontology_graph = NamedNode::from(meta::ontology_graph_uri());
+    let sources = ontology::sources(store);
+    let is_source = sources.contains(&source);
+    let may_write = |graph: &NamedNode, exists: bool| if exists {
+        principal.may(store, graph.as_ref(), Access::Write)
+    } else {
+        principal.may_create()
+    };
+    if !may_write(&ontology_graph, !sources.is_empty()) {
+        return Err(denied(principal, &ontology_graph));
+    } else if !may_write(&source, is_source) {
+        return Err(denied(principal, &source));
+    }
+
+    if !is_source && accounted_graph_list(store).graphs.into_iter().any(|g| g.id == source.to_string()) {
+        return Err(bad_request(format!("Graph URI {} already exists and is not an ontology source!", source)));
+    } else if is_source && is_unchanged_upload(store, source.as_ref(), &triples).map_err(bad_request)? {
+        return Ok(rocket_contrib::json!({"loaded": 0, "unchanged": true}));
+    }
+
+    let parsed = parse_turtle(&triples).map_err(|e| bad_request(e.to_string()))?;
+    content_hash(&parsed).map_err(bad_request)?;
+    let loaded = parsed.len();
+    let merged = ontology::merge_source(store, &source, parsed, Some(origin)).map_err(bad_request)?;
+    if !is_source {
+        grant_creator(store, principal, &source);
+    }
+    if sources.is_empty() {
+        grant_creator(store, principal, &ontology_graph);
+    }
+
+    let report = imports::resolve_imports(store, &source, catalog.load().as_ref(), imports::ImportMode::Merge);
+    autocomplete.rebuild_graph(store, &ontology_graph);
+    Ok(rocket_contrib::json!({"loaded": loaded, "merged": merged, "imports": report}))
+}
+
+/// Whoever makes a graph may administer it. The graph is already written by then, so a failed
+/// grant is logged for an admin to make good rather than failing the request.
+fn grant_creator(store: &Store, principal: &Principal, graph: &NamedNode) {
+    if let Some(user) = &principal.user {
+        if let Err(e) = auth::grant(store, graph, user, Access::Admin) {
+            log::warn!("Could not grant {} admin access to {}: {}", user, graph, e);
+        }
+    }
+}
+
+/// Imported ontologies loaded from the catalog are new graphs, so they need indexing too.
+fn index_imports(store: &Store, autocomplete: &Autocomplete, report: &imports::ImportReport) {
+    for import in report.resolved.iter() {
+        if let Ok(graph) = NamedNode::new(unbracket(import)) {
+            autocomplete.rebuild_graph(store, &graph);
+        }
+    }
+}
+
+/// Takes an ontology source back out of `<O>`. This needs admin access to the source.
+#[delete("/ontology/sources/<source>")]
+pub fn remove_ontology_source(store: State<Store>, aliases: State<Aliases>, autocomplete: State<Autocomplete>, principal: Principal, _quota: WriteQuota, source: GraphName) -> Result<json::Json<MergeReport>, status::Custom<String>> {
+    let source = named_graph(&aliases, source).map_err(|e| status::Custom(Status::NotFound, e))?;
+    if !principal.may(&store, source.0.as_ref(), Access::Admin) {
+        return Err(denied(&principal, &source.0));
+    }
+    match ontology::remove_source(&store, &source.0) {
+        Some(Ok(report)) => {
+            let ontology_graph = NamedNode::from(meta::ontology_graph_uri());
+            autocomplete.rebuild_graph(&store, &ontology_graph);
+            Ok(json::Json(report))
+        },
+        Some(Err(e)) => Err(status::Custom(Status::InternalServerError, e)),
+        None => Err(status::Custom(Status::NotFound, format!("{} is not an ontology source", source.0))),
+    }
+}
+
+/// Get the contents of a graph as Turtle, as canonical N-Triples with `?format=canonical`, or as
+/// N-Triples-star or Turtle-star with `?format=ntriples-star` or `?format=turtle-star`.
+/// With `?version=N`, the graph is rebuilt as it was at revision `N` from its history.
+#[get("/graph/<graph_uri..>?<version>&<format>")]
+pub fn get_graph(store: State<Store>, aliases: State<Aliases>, principal: Principal, graph_uri: GraphName, version: Option<u64>, format: Option<ExportFormat>) -> Result<content::Content<String>, status::Custom<String>> {
+    let graph_uri = named_graph(&aliases, graph_uri).map_err(|e| status::Custom(Status::NotFound, e))?;
     
+    if !is_readable(&store, &principal, &graph_uri.0) {
+        return Err(status::Custom(Status::NotFound, format!("Graph {} cannot be found!", graph_uri.0)));
+    }
+
+    let triples = match version {
+        Some(number) => match history::graph_at_revision(&store, graph_uri.0.as_ref(), number) {
+            Some(triples) => Some(triples),
+            None => return Err(status::Custom(Status::NotFound, format!("Graph {} has no version {}", graph_uri.0, number)))
+        },
+        None => None
+    };
+
+    match (format.unwrap_or(ExportFormat::Turtle), triples) {
+        (ExportFormat::Turtle, Some(triples)) => Ok(content::Content(ContentType::new("text", "turtle"), triples_as_ttl_string(triples).unwrap())),
+        (ExportFormat::Turtle, None) => Ok(content::Content(ContentType::new("text", "turtle"), read_graph_as_ttl_string(&store, graph_uri.0.clone()).unwrap())),
+        (ExportFormat::Canonical, Some(triples)) => canon::canonical_ntriples(&triples)
+            .map(|ntriples| content::Content(ContentType::new("application", "n-triples"), ntriples))
+            .map_err(|e| status::Custom(Status::UnprocessableEntity, e)),
+        (ExportFormat::Canonical, None) => read_graph_as_canonical_ntriples(&store, graph_uri.0.as_ref())
+            .map(|ntriples| content::Content(ContentType::new("application", "n-triples"), ntriples))
+            .map_err(|e| status::Custom(Status::UnprocessableEntity, e)),
+        (ExportFormat::NTriplesStar, triples) => {
+            let triples = triples.unwrap_or_else(|| graph_triples(&store, graph_uri.0.as_ref()));
+            Ok(content::Content(ContentType::new("application", "n-triples"), star::to_ntriples_star(&triples)))
+        }
+        (ExportFormat::TurtleStar, triples) => {
+            let triples = triples.unwrap_or_else(|| graph_triples(&store, graph_uri.0.as_ref()));
+            Ok(content::Content(ContentType::new("text", "turtle"), star::to_turtle_star(&triples)))
+        }
+    }
+}
+
+#[get("/graph/<graph_uri>/versions")]
+pub fn graph_versions(store: State<Store>, aliases: State<Aliases>, principal: Principal, graph_uri: GraphName) -> Result<json::Json<VersionList>, status::NotFound<String>> {
+    let graph_uri = named_graph(&aliases, graph_uri).map_err(status::NotFound)?;
     let all_graphs = accounted_graph_list(&store);
-    
-    match all_graphs.graphs
-        .into_iter()
-        .find(|g: &GraphData| g.id == graph_uri.0.to_string())
-        .map(|_| read_graph_as_ttl_string(&store, graph_uri.0.clone())) {
-        
-        Some(content) => Ok(content.unwrap()),
+
+    match all_graphs.graphs.into_iter().find(|g: &GraphData| g.id == graph_uri.0.to_string() && principal.may(&store, graph_uri.0.as_ref(), Access::Read)) {
+        Some(graph) => Ok(json::Json(VersionList {
+            context: all_graphs.context,
+            graph: graph.id,
+            versions: history::list_revisions(&store, graph_uri.0.as_ref()),
+        })),
         None => Err(status::NotFound(format!("Graph {} cannot be found!", graph_uri.0)))
     }
-}
\ No newline at end of file
+}
+
+/// Every recorded load of a graph, as the PROV-O activities in `<Provenance>` that generated it.
+#[get("/graph/<graph_uri>/provenance")]
+pub fn graph_provenance(store: State<Store>, aliases: State<Aliases>, principal: Principal, graph_uri: GraphName) -> Result<content::Content<String>, status::NotFound<String>> {
+    let graph_uri = named_graph(&aliases, graph_uri).map_err(status::NotFound)?;
+    if !is_readable(&store, &principal, &graph_uri.0) {
+        return Err(status::NotFound(format!("Graph {} cannot be found!", graph_uri.0)));
+    }
+
+    let triples = provenance::provenance_of(&store, graph_uri.0.as_ref());
+    triples_as_ttl_string(triples)
+        .map(|ttl| content::Content(ContentType::new("text", "turtle"), ttl))
+        .map_err(|e| status::Custom(Status::InternalServerError, e))
+}
+
+/// The VoID description of a graph, as Turtle: its size, class and property partitions, and
+/// linksets into other graphs.
+#[get("/graph/<graph_uri>/stats")]
+pub fn graph_stats(store: State<Store>, aliases: State<Aliases>, principal: Principal, _quota: QueryQuota, graph_uri: GraphName) -> Result<content::Content<String>, status::NotFound<String>> {
+    let graph_uri = named_graph(&aliases, graph_uri).map_err(status::NotFound)?;
+    if !is_readable(&store, &principal, &graph_uri.0) {
+        return Err(status::NotFound(format!("Graph {} cannot be found!", graph_uri.0)));
+    }
+
+    let stats = void::stats(&store, &graph_uri.0);
+    Ok(content::Content(ContentType::new("text", "turtle"), triples_as_ttl_string(void::describe(&graph_uri.0, &stats)).unwrap()))
+}
+
+/// The VoID description of the whole store, with every graph the requester may read as a subset.
+#[get("/.well-known/void")]
+pub fn store_void(store: State<Store>, principal: Principal, _quota: QueryQuota) -> content::Content<String> {
+    let readable = principal.permitted(&store, Access::Read);
+    let graphs: Vec<NamedNode> = accounted_graph_list(&store)
+        .graphs
+        .into_iter()
+        .filter_map(|g| NamedNode::new(unbracket(&g.id)).ok())
+        .filter(|g| readable.as_ref().map_or(true, |r| r.contains(g)))
+        .collect();
+    content::Content(ContentType::new("text", "turtle"), triples_as_ttl_string(void::describe_store(&store, &graphs)).unwrap())
+}
+
+/// Who has been granted access to a graph. Needs admin access to it.
+#[get("/graph/<graph_uri>/acl")]
+pub fn graph_acl(store: State<Store>, aliases: State<Aliases>, principal: Principal, graph_uri: GraphName) -> Result<json::Json<GraphAccess>, status::Custom<String>> {
+    let graph_uri = named_graph(&aliases, graph_uri).map_err(|e| status::Custom(Status::NotFound, e))?;
+    let graph = administered(&store, &principal, graph_uri.0)?;
+    Ok(json::Json(GraphAccess::of(&store, &graph)))
+}
+
+/// Gives `?agent=` (a user, `group:<name>` or `*`) `?access=` (`read`, `write` or `admin`) to a
+/// graph, replacing what it had. Needs admin access to the graph.
+#[post("/graph/<graph_uri>/acl?<agent>&<access>")]
+pub fn grant_access(store: State<Store>, aliases: State<Aliases>, principal: Principal, _quota: WriteQuota, graph_uri: GraphName, agent: String, access: Access) -> Result<json::Json<GraphAccess>, status::Custom<String>> {
+    let graph_uri = named_graph(&aliases, graph_uri).map_err(|e| status::Custom(Status::NotFound, e))?;
+    let graph = administered(&store, &principal, graph_uri.0)?;
+    auth::grant(&store, &graph, &agent, access).map_err(|e| status::Custom(Status::InternalServerError, e))?;
+    Ok(json::Json(GraphAccess::of(&store, &graph)))
+}
+
+/// Takes away whatever access `?agent=` had to a graph. Needs admin access to the graph.
+#[delete("/graph/<graph_uri>/acl?<agent>")]
+pub fn revoke_access(store: State<Store>, aliases: State<Aliases>, principal: Principal, _quota: WriteQuota, graph_uri: GraphName, agent: String) -> Result<json::Json<GraphAccess>, status::Custom<String>> {
+    let graph_uri = named_graph(&aliases, graph_uri).map_err(|e| status::Custom(Status::NotFound, e))?;
+    let graph = administered(&store, &principal, graph_uri.0)?;
+    auth::revoke(&store, &graph, &agent).map_err(|e| status::Custom(Status::InternalServerError, e))?;
+    Ok(json::Json(GraphAccess::of(&store, &graph)))
+}
+
+/// Gives a graph the short name `?name=`, which can be used in place of its `<uri>` anywhere.
+/// Needs admin access to the graph.
+#[put("/graph/<graph_uri>/alias?<name>")]
+pub fn set_alias(store: State<Store>, aliases: State<Aliases>, principal: Principal, _quota: WriteQuota, graph_uri: GraphName, name: String) -> Result<json::JsonValue, status::Custom<String>> {
+    let graph_uri = named_graph(&aliases, graph_uri).map_err(|e| status::Custom(Status::NotFound, e))?;
+    let graph = administered(&store, &principal, graph_uri.0)?;
+    aliases.set(&store, &graph, &name).map_err(|e| match e {
+        AliasError::Invalid(_) => bad_request(e.to_string()),
+        AliasError::Taken(_, _) => status::Custom(Status::Conflict, e.to_string()),
+        AliasError::Storage(_) => status::Custom(Status::InternalServerError, e.to_string()),
+    })?;
+    Ok(rocket_contrib::json!({"id": graph.to_string(), "alias": name, "path": alias::graph_path(graph.as_ref(), Some(&name))}))
+}
+
+/// Takes away a graph's alias. Needs admin access to the graph.
+#[delete("/graph/<graph_uri>/alias")]
+pub fn remove_alias(store: State<Store>, aliases: State<Aliases>, principal: Principal, _quota: WriteQuota, graph_uri: GraphName) -> Result<json::JsonValue, status::Custom<String>> {
+    let graph_uri = named_graph(&aliases, graph_uri).map_err(|e| status::Custom(Status::NotFound, e))?;
+    let graph = administered(&store, &principal, graph_uri.0)?;
+    let removed = aliases.remove(&store, &graph).map_err(|e| status::Custom(Status::InternalServerError, e.to_string()))?;
+    Ok(rocket_contrib::json!({"id": graph.to_string(), "removed": removed, "path": alias::graph_path(graph.as_ref(), None)}))
+}
+
+/// `graph`, if it is registered in `<Meta>` and `principal` may administer it.
+fn administered(store: &Store, principal: &Principal, graph: NamedNode) -> Result<NamedNode, status::Custom<String>> {
+    if !is_readable(store, principal, &graph) {
+        Err(status::Custom(Status::NotFound, format!("Graph {} cannot be found!", graph)))
+    } else if !principal.may(store, graph.as_ref(), Access::Admin) {
+        Err(denied(principal, &graph))
+    } else {
+        Ok(graph)
+    }
+}
+
+/// Validate a graph against the registered shapes, returning the `sh:ValidationReport` as
+/// Turtle. With `?inferred=true`, the graph's Inferred graph is validated along with it.
+#[post("/graph/<graph_uri>/validate?<inferred>")]
+pub fn validate_graph(store: State<Store>, aliases: State<Aliases>, principal: Principal, graph_uri: GraphName, inferred: Option<bool>) -> Result<content::Content<String>, status::NotFound<String>> {
+    let graph_uri = named_graph(&aliases, graph_uri).map_err(status::NotFound)?;
+    if !is_readable(&store, &principal, &graph_uri.0) {
+        return Err(status::NotFound(format!("Graph {} cannot be found!", graph_uri.0)));
+    }
+
+    let report = shacl::validate_graph(&store, &graph_uri.0, inferred.unwrap_or(false));
+    Ok(content::Content(ContentType::new("text", "turtle"), report.to_turtle().unwrap()))
+}
+
+/// Labels, definitions, annotations and superclasses of an ontology term, as JSON-LD.
+#[get("/term/<iri>")]
+pub fn get_term(store: State<Store>, principal: Principal, _quota: QueryQuota, iri: UriWrapper) -> Result<json::Json<TermInfo>, status::NotFound<String>> {
+    match term::lookup(&store, &iri.0, CONTEXT.to_owned(), principal.permitted(&store, Access::Read).as_ref()) {
+        Some(info) => Ok(json::Json(info)),
+        None => Err(status::NotFound(format!("Term {} is not in any ontology", iri.0)))
+    }
+}
+
+#[derive(FromForm)]
+pub struct SearchParams {
+    q: String,
+    graph: Option<GraphName>,
+    #[form(field = "type")]
+    class: Option<UriWrapper>,
+    limit: Option<usize>,
+}
+
+/// All ancestors of a term along `?relation=` (`subClassOf` by default), or those at most
+/// `?depth=` steps up.
+#[get("/term/<iri>/ancestors?<relation>&<depth>")]
+pub fn term_ancestors(store: State<Store>, relations: State<Relations>, principal: Principal, _quota: QueryQuota, iri: UriWrapper, relation: Option<RelationName>, depth: Option<usize>) -> Result<json::Json<Traversal>, status::Custom<String>> {
+    let relation = relation.map(|r| relations.relation(r)).transpose().map_err(bad_request)?;
+    Ok(json::Json(traverse(&store, &principal, iri.0, relation, Direction::Ancestors, depth)))
+}
+
+/// All descendants of a term along `?relation=` (`subClassOf` by default), or those at most
+/// `?depth=` steps down.
+#[get("/term/<iri>/descendants?<relation>&<depth>")]
+pub fn term_descendants(store: State<Store>, relations: State<Relations>, principal: Principal, _quota: QueryQuota, iri: UriWrapper, relation: Option<RelationName>, depth: Option<usize>) -> Result<json::Json<Traversal>, status::Custom<String>> {
+    let relation = relation.map(|r| relations.relation(r)).transpose().map_err(bad_request)?;
+    Ok(json::Json(traverse(&store, &principal, iri.0, relation, Direction::Descendants, depth)))
+}
+
+fn traverse(store: &Store, principal: &Principal, iri: NamedNode, relation: Option<Relation>, direction: Direction, depth: Option<usize>) -> Traversal {
+    let relation = relation.unwrap_or_else(|| Relation(NamedNode::from(rdfs::SUB_CLASS_OF)));
+    let related = term::related(store, &iri, &relation, direction, depth, principal.permitted(store, Access::Read).as_ref());
+    Traversal {
+        context: CONTEXT.to_owned(),
+        id: iri.as_str().to_owned(),
+        relation: relation.0.as_str().to_owned(),
+        depth,
+        source: if related.from_closure { "closure" } else { "ontology" }.to_owned(),
+        terms: related.terms.iter().map(|t| t.as_str().to_owned()).collect(),
+    }
+}
+
+/// Full-text search over labels, synonyms, definitions and the like. `?graph=` keeps to one
+/// graph and `?type=` to resources of one `rdf:type`.
+#[get("/search?<params..>")]
+pub fn search(store: State<Store>, aliases: State<Aliases>, index: State<SearchIndex>, principal: Principal, _quota: QueryQuota, params: Form<SearchParams>) -> json::Json<SearchResults> {
+    let params = params.into_inner();
+    let graphs = query_scope(&store, &principal, params.graph.and_then(|g| aliases.graph(g).ok()));
+    let hits = index.search(&store, &params.q, graphs.as_ref(), params.class.as_ref().map(|c| &c.0), params.limit.unwrap_or(20));
+    json::Json(SearchResults {
+        context: CONTEXT.to_owned(),
+        query: params.q,
+        hits,
+    })
+}
+
+/// Suggestions of ontology terms whose label or synonym starts with `?q=`, optionally within
+/// one Ontology graph.
+#[get("/autocomplete?<q>&<ontology>&<limit>")]
+pub fn autocomplete(store: State<Store>, aliases: State<Aliases>, tries: State<Autocomplete>, principal: Principal, _quota: QueryQuota, q: String, ontology: Option<GraphName>, limit: Option<usize>) -> json::Json<Suggestions> {
+    let ontologies = query_scope(&store, &principal, ontology.and_then(|o| aliases.graph(o).ok()));
+    let suggestions = tries.suggest(&q, ontologies.as_ref(), limit.unwrap_or(10));
+    json::Json(Suggestions {
+        context: CONTEXT.to_owned(),
+        query: q,
+        suggestions,
+    })
+}
+
+/// The triples of one side of a diff: either the graph as it is now, or at a given revision.
+fn diff_side(store: &Store, principal: &Principal, graph_uri: &NamedNode, version: Option<u64>) -> Result<Vec<Triple>, status::NotFound<String>> {
+    if !is_readable(store, principal, graph_uri) {
+        return Err(status::NotFound(format!("Graph {} cannot be found!", graph_uri)));
+    }
+
+    match version {
+        Some(number) => history::graph_at_revision(store, graph_uri.as_ref(), number)
+            .ok_or_else(|| status::NotFound(format!("Graph {} has no version {}", graph_uri, number))),
+        None => Ok(graph_triples(store, graph_uri.as_ref()))
+    }
+}
+
+/// Semantic diff between two graphs, or between two revisions of one graph when `right` is left
+/// out, e.g. `/diff?left=<G>&left_version=1&right_version=2`. A missing version means the
+/// current contents of the graph.
+#[get("/diff?<left>&<right>&<left_version>&<right_version>&<format>")]
+pub fn diff_graphs(store: State<Store>, aliases: State<Aliases>, principal: Principal, _quota: QueryQuota, left: GraphName, right: Option<GraphName>, left_version: Option<u64>,
+        right_version: Option<u64>, format: Option<DiffFormat>) -> Result<content::Content<String>, status::Custom<String>> {
+
+    let left = named_graph(&aliases, left).map_err(|e| status::Custom(Status::NotFound, e))?;
+    let right = match right {
+        Some(right) => named_graph(&aliases, right).map_err(|e| status::Custom(Status::NotFound, e))?.0,
+        None => left.0.clone(),
+    };
+    let not_found = |e: status::NotFound<String>| status::Custom(Status::NotFound, e.0);
+    let left_side = diff_side(&store, &principal, &left.0, left_version).map_err(not_found)?;
+    let right_side = diff_side(&store, &principal, &right, right_version).map_err(not_found)?;
+    let d = diff::diff(&left_side, &right_side).map_err(|e| status::Custom(Status::UnprocessableEntity, e))?;
+
+    match format.unwrap_or(DiffFormat::Json) {
+        DiffFormat::Turtle => Ok(content::Content(ContentType::new("text", "turtle"), d.to_turtle().unwrap())),
+        DiffFormat::Patch => Ok(content::Content(ContentType::new("application", "rdf-patch"), d.to_patch())),
+        DiffFormat::Json => {
+            let body = d.to_json(CONTEXT.to_owned(), left.0.to_string(), right.to_string());
+            Ok(content::Content(ContentType::JSON, serde_json::to_string(&body).unwrap()))
+        }
+    }
+}
+
+/// A SPARQL query, as `?query=`. It only sees the graphs the requester may read, and is
+/// cancelled with a `503` if it runs past the query timeout.
+#[get("/sparql?<query>")]
+pub fn sparql_query(store: State<Store>, search: State<SearchIndex>, limits: State<Limits>, principal: Principal, _quota: QueryQuota, query: String) -> Result<content::Content<String>, status::Custom<String>> {
+    answer_query(&store, &search, &limits, &principal, &query)
+}
+
+/// A SPARQL query, as the request body.
+#[post("/sparql", format = "application/sparql-query", data = "<query>")]
+pub fn sparql_query_by_post(store: State<Store>, search: State<SearchIndex>, limits: State<Limits>, principal: Principal, _quota: QueryQuota, query: Upload) -> Result<content::Content<String>, status::Custom<String>> {
+    let query = String::from_utf8(query.0).map_err(|e| bad_request(e.to_string()))?;
+    answer_query(&store, &search, &limits, &principal, &query)
+}
+
+/// The SPARQL Service Description of this endpoint, as Turtle, listing the graphs the requester
+/// may read.
+#[get("/sparql", rank = 3)]
+pub fn service_description(store: State<Store>, principal: Principal, base: BaseUrl) -> content::Content<String> {
+    let triples = describe_service(&store, &principal, &base);
+    content::Content(ContentType::new("text", "turtle"), triples_as_ttl_string(triples).unwrap())
+}
+
+/// The SPARQL Service Description, as JSON-LD.
+#[get("/sparql", format = "application/ld+json", rank = 2)]
+pub fn service_description_as_jsonld(store: State<Store>, principal: Principal, base: BaseUrl) -> content::Content<String> {
+    let triples = describe_service(&store, &principal, &base);
+    content::Content(ContentType::new("application", "ld+json"), jsonld::expanded(&triples).to_string())
+}
+
+fn describe_service(store: &Store, principal: &Principal, base: &BaseUrl) -> Vec<Triple> {
+    service::describe(store, &base.join("/sparql"), &readable_graphs(store, principal))
+}
+
+/// The graphs registered in `<Meta>` that the requester may read, with their types.
+fn readable_graphs(store: &Store, principal: &Principal) -> Vec<(NamedNode, GraphType)> {
+    readable_graph_data(store, principal)
+        .into_iter()
+        .filter_map(|g| NamedNode::new(unbracket(&g.id)).ok().map(|n| (n, g.graph_type)))
+        .collect()
+}
+
+/// The graphs the requester may read, as registered in `<Meta>`.
+fn readable_graph_data(store: &Store, principal: &Principal) -> Vec<GraphData> {
+    let readable = principal.permitted(store, Access::Read);
+    accounted_graph_list(store)
+        .graphs
+        .into_iter()
+        .filter(|g| readable.as_ref().map_or(true, |r| r.iter().any(|n| n.to_string() == g.id)))
+        .collect()
+}
+
+fn answer_query(store: &Store, search: &SearchIndex, limits: &Limits, principal: &Principal, query: &str) -> Result<content::Content<String>, status::Custom<String>> {
+    match sparql::query(store, search, query, principal.permitted(store, Access::Read).as_ref(), limits) {
+        Ok(Answer::Solutions(results)) => Ok(content::Content(ContentType::new("application", "sparql-results+json"), results)),
+        Ok(Answer::Graph(triples)) => Ok(content::Content(ContentType::new("text", "turtle"), triples)),
+        Err(e @ QueryError::TimedOut(_)) | Err(e @ QueryError::Busy) => Err(status::Custom(Status::ServiceUnavailable, e.to_string())),
+        Err(e) => Err(bad_request(e.to_string())),
+    }
+}
+
+/// The whole store, every graph including `<Meta>`, as gzipped N-Quads, or TriG with
+/// `?format=trig`. The dump is written to a temporary file first and streamed from there. Admins only.
+#[get("/admin/dump?<format>")]
+pub fn dump_store(store: State<Store>, principal: Principal, format: Option<ArchiveFormat>) -> Result<content::Content<Stream<File>>, status::Custom<String>> {
+    admins_only(&principal)?;
+    let failed = |e: String| status::Custom(Status::InternalServerError, e);
+
+    let mut file = tempfile::tempfile().map_err(|e| failed(e.to_string()))?;
+    backup::dump(&store, format.unwrap_or(ArchiveFormat::NQuads), &mut file).map_err(failed)?;
+    file.seek(SeekFrom::Start(0)).map_err(|e| failed(e.to_string()))?;
+    Ok(content::Content(ContentType::new("application", "gzip"), Stream::from(file)))
+}
+
+/// Checks that `<Meta>` agrees with the named graphs in the store. Admins only.
+#[get("/admin/fsck")]
+pub fn check_store(store: State<Store>, principal: Principal) -> Result<json::Json<FsckReport>, status::Custom<String>> {
+    admins_only(&principal)?;
+    fsck::check(&store, false).map(json::Json).map_err(|e| status::Custom(Status::InternalServerError, e))
+}
+
+/// Checks the store as `GET` does, and repairs what it can. Admins only.
+#[post("/admin/fsck")]
+pub fn repair_store(store: State<Store>, aliases: State<Aliases>, principal: Principal, _quota: WriteQuota) -> Result<json::Json<FsckReport>, status::Custom<String>> {
+    admins_only(&principal)?;
+    let report = fsck::check(&store, true).map_err(|e| status::Custom(Status::InternalServerError, e))?;
+    aliases.reload(&store);
+    Ok(json::Json(report))
+}
+
+/// The graphs holding data that aren't registered in `<Meta>`, each with the type it would be
+/// adopted as. Admins only.
+#[get("/admin/adopt")]
+pub fn adoptable_graphs(store: State<Store>, principal: Principal) -> Result<json::Json<Vec<Proposal>>, status::Custom<String>> {
+    admins_only(&principal)?;
+    Ok(json::Json(adopt::propose(&store)))
+}
+
+/// Registers unregistered graphs in `<Meta>` with their proposed types: all of them, or just
+/// `?graph_uri=`, as `?graph_type=` if given. Admins only.
+#[post("/admin/adopt?<graph_uri>&<graph_type>")]
+pub fn adopt_graphs(store: State<Store>, autocomplete: State<Autocomplete>, principal: Principal, _quota: WriteQuota, graph_uri: Option<UriWrapper>, graph_type: Option<GraphType>) -> Result<json::Json<Vec<Proposal>>, status::Custom<String>> {
+    admins_only(&principal)?;
+    let mut proposals = adopt::propose(&store);
+    if let Some(graph) = graph_uri {
+        proposals.retain(|p| p.graph == graph.0.to_string());
+        if proposals.is_empty() {
+            return Err(bad_request(format!("{} is not an unregistered graph", graph.0)));
+        }
+    }
+
+    for proposal in proposals.iter_mut() {
+        let graph = NamedNode::new(unbracket(&proposal.graph)).unwrap();
+        if let Some(graph_type) = graph_type {
+            proposal.graph_type = graph_type;
+            proposal.reason = "requested";
+        }
+        adopt::adopt(&store, &graph, proposal.graph_type).map_err(|e| status::Custom(Status::InternalServerError, e))?;
+        if proposal.graph_type == GraphType::Ontology {
+            autocomplete.rebuild_graph(&store, &graph);
+        }
+    }
+    Ok(json::Json(proposals))
+}
+
+/// What a Graph Store Protocol write did: `201` for a new graph, `204` for an existing one.
+#[derive(Responder)]
+pub enum Stored {
+    Created(status::Created<()>),
+    Changed(status::NoContent),
+}
+
+/// The graph a Graph Store Protocol request is about: `?graph=` with a plain IRI (or an alias),
+/// or `?default` for the default graph, as `None`.
+fn gsp_target(aliases: &Aliases, graph: Option<String>, default: Option<String>) -> Result<Option<NamedNode>, status::Custom<String>> {
+    match (graph, default) {
+        (Some(graph), None) => NamedNode::new(graph.as_str()).ok()
+            .or_else(|| aliases.resolve(&graph))
+            .map(Some)
+            .ok_or_else(|| bad_request(format!("{} is not an IRI", graph))),
+        (None, Some(_)) => Ok(None),
+        _ => Err(bad_request("Expected either ?graph=<iri> or ?default".to_owned())),
+    }
+}
+
+/// The type a graph is registered in `<Meta>` with, if it is registered.
+fn registered_type(store: &Store, graph: &NamedNode) -> Option<GraphType> {
+    accounted_graph_list(store).graphs.into_iter().find(|g| g.id == graph.to_string()).map(|g| g.graph_type)
+}
+
+/// Graph Store Protocol `GET` (and `HEAD`): a registered graph, or the default graph, as Turtle,
+/// N-Triples, RDF/XML or JSON-LD going by the `Accept` header.
+#[get("/store?<graph>&<default>")]
+pub fn gsp_get(store: State<Store>, aliases: State<Aliases>, principal: Principal, format: StoreFormat, graph: Option<String>, default: Option<String>) -> Result<content::Content<String>, status::Custom<String>> {
+    let triples = match gsp_target(&aliases, graph, default)? {
+        Some(graph) if is_readable(&store, &principal, &graph) => graph_triples(&store, graph.as_ref()),
+        Some(graph) => return Err(status::Custom(Status::NotFound, format!("Graph {} cannot be found!", graph))),
+        None => {
+            admins_only(&principal)?;
+            store.quads_for_pattern(None, None, None, Some(GraphNameRef::DefaultGraph)).filter_map(Result::ok).map(Triple::from).collect()
+        }
+    };
+    let (top, sub) = format.media_type();
+    let body = format.serialize(triples).map_err(|e| status::Custom(Status::InternalServerError, e))?;
+    Ok(content::Content(ContentType::new(top, sub), body))
+}
+
+/// Graph Store Protocol `PUT`: the body, in Turtle, N-Triples or RDF/XML, replaces the graph,
+/// including whatever was in it before it was registered. A new graph is registered as
+/// `?graph_type=`, a Model by default.
+#[put("/store?<graph>&<default>&<graph_type>", data = "<data>")]
+pub fn gsp_put(store: State<Store>, aliases: State<Aliases>, autocomplete: State<Autocomplete>, principal: Principal, _quota: WriteQuota, origin: Origin, content_type: &ContentType,
+               graph: Option<String>, default: Option<String>, graph_type: Option<GraphType>, data: Upload) -> Result<Stored, status::Custom<String>> {
+    let target = gsp_target(&aliases, graph, default)?;
+    let triples = gsp_triples(content_type, &data.0)?;
+    gsp_write(&store, &autocomplete, &principal, &origin, target, graph_type, triples, true)
+}
+
+/// Graph Store Protocol `POST`: the body is merged into the graph. Without `?graph=` or
+/// `?default`, it becomes a new graph with a name made up for it. A `multipart/form-data` body
+/// merges every part, each in the format of its own `Content-Type`, Turtle if it has none.
+#[post("/store?<graph>&<default>&<graph_type>", data = "<data>")]
+pub fn gsp_post(store: State<Store>, aliases: State<Aliases>, autocomplete: State<Autocomplete>, principal: Principal, _quota: WriteQuota, origin: Origin, base: BaseUrl, content_type: &ContentType,
+                graph: Option<String>, default: Option<String>, graph_type: Option<GraphType>, data: Upload) -> Result<Stored, status::Custom<String>> {
+    let target = match (graph, default) {
+        (None, None) => Some(new_graph_uri(&store, &base.0)),
+        (graph, default) => gsp_target(&aliases, graph, default)?,
+    };
+    let triples = gsp_triples(content_type, &data.0)?;
+    gsp_write(&store, &autocomplete, &principal, &origin, target, graph_type, triples, false)
+}
+
+/// The triples of a Graph Store Protocol upload, from every part of a `multipart/form-data` one.
+fn gsp_triples(content_type: &ContentType, data: &[u8]) -> Result<Vec<Triple>, status::Custom<String>> {
+    fn parse(content_type: &ContentType, data: &[u8]) -> Result<Vec<Triple>, status::Custom<String>> {
+        let format = GraphFormat::from_media_type(&format!("{}/{}", content_type.top(), content_type.sub()))
+            .ok_or_else(|| status::Custom(Status::UnsupportedMediaType, format!("Can't read {}", content_type)))?;
+        parse_graph(data, format).map_err(|e| bad_request(e.to_string()))
+    }
+    if content_type.top() != "multipart" || content_type.sub() != "form-data" {
+        return parse(content_type, data);
+    }
+
+    let boundary = content_type.params().find(|(name, _)| *name == "boundary")
+        .map(|(_, boundary)| boundary.trim_matches('"'))
+        .ok_or_else(|| bad_request("multipart/form-data needs a boundary".to_owned()))?;
+    let mut triples = Vec::new();
+    for part in gsp::multipart_parts(data, boundary).map_err(bad_request)? {
+        let part_type = part.content_type.as_deref().and_then(ContentType::parse_flexible).unwrap_or_else(|| ContentType::new("text", "turtle"));
+        triples.extend(parse(&part_type, part.body)?);
+    }
+    Ok(triples)
+}
+
+/// Writes to a graph the same way uploads to `/graph` do, with a new revision, content hash and
+/// provenance. The default graph has no `<Meta>` entry, so only admins may write to it.
+fn gsp_write(store: &Store, autocomplete: &Autocomplete, principal: &Principal, origin: &Origin,
+             target: Option<NamedNode>, graph_type: Option<GraphType>, triples: Vec<Triple>, replace: bool) -> Result<Stored, status::Custom<String>> {
+    content_hash(&triples).map_err(bad_request)?;
+    let failed = |e: String| status::Custom(Status::InternalServerError, e);
+
+    let graph = match target {
+        Some(graph) => graph,
+        None => {
+            admins_only(principal)?;
+            let default_graph: Vec<Quad> = store.quads_for_pattern(None, None, None, Some(GraphNameRef::DefaultGraph)).filter_map(Result::ok).collect();
+            let inserted: Vec<Quad> = triples.into_iter().map(|t| t.in_graph(GraphName::DefaultGraph)).collect();
+            apply_changes(store, if replace { &default_graph } else { &[] }, &inserted).map_err(failed)?;
+            return Ok(Stored::Changed(status::NoContent));
+        }
+    };
+    if meta::reserved_graph_uris().iter().any(|g| g.to_string() == graph.to_string()) || ontology::is_ontology_graph(graph.as_ref()) {
+        return Err(bad_request("Untouchable graph".to_owned()));
+    }
+
+    let existing_type = registered_type(store, &graph);
+    if existing_type == Some(GraphType::OntologySource) || (existing_type.is_none() && graph_type == Some(GraphType::OntologySource)) {
+        return Err(bad_request(format!("{} is merged into <O>: upload it with POST /graph?merge=true instead", graph)));
+    }
+    match existing_type {
+        Some(existing_type) => {
+            if !principal.may(store, graph.as_ref(), Access::Write) {
+                return Err(denied(principal, &graph));
+            }
+            let after = if replace { triples } else { graph_triples(store, graph.as_ref()).into_iter().chain(triples).collect() };
+            rewrite_graph(store, &graph, after, Some((existing_type, origin))).map_err(failed)?;
+            index_graph(store, autocomplete, &graph, existing_type);
+            Ok(Stored::Changed(status::NoContent))
+        }
+        None => {
+            if !principal.may_create() {
+                return Err(denied(principal, &graph));
+            }
+            let graph_type = graph_type.unwrap_or(GraphType::Model);
+            // The graph may hold data without being registered, from SPARQL Update or a restore
+            let unregistered = graph_triples(store, graph.as_ref());
+            let triples = if replace {
+                let stale: Vec<Quad> = unregistered.into_iter().map(|t| t.in_graph(graph.clone())).collect();
+                apply_changes(store, &stale, &[]).map_err(failed)?;
+                triples
+            } else {
+                unregistered.into_iter().chain(triples).collect()
+            };
+            load_new_graph(store, graph.clone(), graph_type, triples, Some(origin)).map_err(failed)?;
+            grant_creator(store, principal, &graph);
+            index_graph(store, autocomplete, &graph, graph_type);
+            Ok(Stored::Created(status::Created(format!("/store?graph={}", alias::percent_encode(graph.as_str())), None)))
+        }
+    }
+}
+
+fn index_graph(store: &Store, autocomplete: &Autocomplete, graph: &NamedNode, graph_type: GraphType) {
+    if graph_type == GraphType::Ontology {
+        autocomplete.rebuild_graph(store, graph);
+    }
+}
+
+/// Graph Store Protocol `DELETE`: takes the graph and its `<Meta>` entry out of the store, or an
+/// ontology source out of `<O>` too. Needs admin access to the graph.
+#[delete("/store?<graph>&<default>")]
+pub fn gsp_delete(store: State<Store>, aliases: State<Aliases>, autocomplete: State<Autocomplete>, principal: Principal, _quota: WriteQuota, graph: Option<String>, default: Option<String>) -> Result<status::NoContent, status::Custom<String>> {
+    let failed = |e: String| status::Custom(Status::InternalServerError, e);
+    let graph = match gsp_target(&aliases, graph, default)? {
+        Some(graph) => administered(&store, &principal, graph)?,
+        None => {
+            admins_only(&principal)?;
+            let default_graph: Vec<Quad> = store.quads_for_pattern(None, None, None, Some(GraphNameRef::DefaultGraph)).filter_map(Result::ok).collect();
+            apply_changes(&store, &default_graph, &[]).map_err(failed)?;
+            return Ok(status::NoContent);
+        }
+    };
+    if meta::reserved_graph_uris().iter().any(|g| g.to_string() == graph.to_string()) || ontology::is_ontology_graph(graph.as_ref()) {
+        return Err(bad_request("Untouchable graph".to_owned()));
+    }
+
+    if let Some(removed) = ontology::remove_source(&store, &graph) {
+        removed.map_err(failed)?;
+        let ontology_graph = NamedNode::from(meta::ontology_graph_uri());
+        autocomplete.rebuild_graph(&store, &ontology_graph);
+        return Ok(status::NoContent);
+    }
+    delete_graph(&store, &graph).map_err(failed)?;
+    autocomplete.remove_graph(&graph);
+    aliases.reload(&store);
+    Ok(status::NoContent)
+}
+
+/// The concise bounded description of a resource across the graphs the requester may read, or
+/// only `?graph=`, as HTML, Turtle or JSON-LD going by the `Accept` header. The resource is a
+/// percent-encoded `<iri>`, or else a path naming an IRI minted under this server's
+/// `/resource/`.
+#[get("/resource/<iri..>?<graph>")]
+pub fn get_resource(store: State<Store>, aliases: State<Aliases>, principal: Principal, _quota: QueryQuota, base: BaseUrl, format: ResourceFormat, iri: Segments, graph: Option<GraphName>) -> Result<Described, status::NotFound<String>> {
+    // A minted path is never taken for an alias, which names a graph rather than a resource
+    let raw = RawStr::from_str(iri.0);
+    let decoded = raw.percent_decode().map_err(|_| status::NotFound(format!("{} is not a resource", raw)))?;
+    let iri = match decoded.strip_prefix('<').and_then(|d| d.strip_suffix('>')) {
+        Some(iri) => NamedNode::new(iri),
+        None => NamedNode::new(base.join(&format!("/resource/{}", iri.0))),
+    }.map_err(|_| status::NotFound(format!("{} is not a resource", raw)))?;
+
+    let graph = graph.map(|g| named_graph(&aliases, g)).transpose().map_err(status::NotFound)?;
+    let graphs: Vec<NamedNode> = match graph {
+        Some(graph) if is_readable(&store, &principal, &graph.0) => vec![graph.0],
+        Some(graph) => return Err(status::NotFound(format!("Graph {} cannot be found!", graph.0))),
+        None => readable_graphs(&store, &principal).into_iter().map(|(g, _)| g).collect(),
+    };
+    let description = resource::describe(&store, &iri, &graphs)
+        .ok_or_else(|| status::NotFound(format!("Nothing is said about {}", iri)))?;
+
+    Ok(match format {
+        ResourceFormat::Html => Described::Page(Template::render("resource", serde_json::json!({
+            "title": description.resource.as_str(),
+            "resource": browse::resource_page(&description),
+        }))),
+        ResourceFormat::Turtle => Described::Data(content::Content(ContentType::new("text", "turtle"), triples_as_ttl_string(description.triples).unwrap())),
+        ResourceFormat::JsonLd => Described::Data(content::Content(ContentType::new("application", "ld+json"), jsonld::expanded(&description.triples).to_string())),
+    })
+}
+
+/// A resource's description, as a page or as RDF.
+#[derive(Responder)]
+pub enum Described {
+    Page(Template),
+    Data(content::Content<String>),
+}
+
+/// The graphs the requester may read, grouped by type, as HTML.
+#[get("/")]
+pub fn index(store: State<Store>, principal: Principal) -> Template {
+    let graphs = readable_graph_data(&store, &principal);
+    Template::render("index", serde_json::json!({
+        "title": "Graphs",
+        "groups": browse::grouped(&graphs),
+    }))
+}
+
+/// A page of a graph's triples as HTML, `?page=` counting from 1, with links to the graphs it is
+/// inferred from or has its inferences at.
+#[get("/ui/graph/<graph_uri>?<page>")]
+pub fn browse_graph(store: State<Store>, aliases: State<Aliases>, principal: Principal, graph_uri: GraphName, page: Option<usize>) -> Result<Template, status::NotFound<String>> {
+    let graph_uri = named_graph(&aliases, graph_uri).map_err(status::NotFound)?;
+    let graphs = readable_graph_data(&store, &principal);
+    let graph = graphs.iter()
+        .find(|g| g.id == graph_uri.0.to_string())
+        .ok_or_else(|| status::NotFound(format!("Graph {} cannot be found!", graph_uri.0)))?;
+    Ok(Template::render("graph", serde_json::json!({
+        "title": graph_uri.0.as_str(),
+        "page": browse::graph_page(&store, graph, &graphs, page.unwrap_or(1)),
+    })))
+}
+
+/// A SPARQL query box, with the answer to `?query=` as a table, or as Turtle for `CONSTRUCT` and
+/// `DESCRIBE`.
+#[get("/ui/sparql?<query>")]
+pub fn query_page(store: State<Store>, search: State<SearchIndex>, limits: State<Limits>, principal: Principal, _quota: QueryQuota, query: Option<String>) -> Template {
+    let mut page = serde_json::json!({ "title": "SPARQL", "query": query.as_deref().unwrap_or_default() });
+    if let Some(query) = query {
+        match sparql::query(&store, &search, &query, principal.permitted(&store, Access::Read).as_ref(), &limits) {
+            Ok(Answer::Solutions(results)) => {
+                let graphs = readable_graph_data(&store, &principal);
+                page["table"] = serde_json::json!(browse::results_table(&store, &results, &graphs));
+            }
+            Ok(Answer::Graph(triples)) => page["graph"] = serde_json::json!(triples),
+            Err(e) => page["error"] = serde_json::json!(e.to_string()),
+        }
+    }
+    Template::render("sparql", page)
+}
+
+/// A page of the Triple Pattern Fragment of a graph matching `?subject=`, `?predicate=` and
+/// `?object=`, as Turtle, with the Hydra controls and count estimate TPF clients need.
+#[get("/fragments/<graph_uri>?<subject>&<predicate>&<object>&<page>")]
+pub fn triple_pattern_fragment(store: State<Store>, aliases: State<Aliases>, principal: Principal, _quota: QueryQuota, base: BaseUrl, graph_uri: &RawStr, subject: Option<String>, predicate: Option<String>, object: Option<String>,
+                               page: Option<usize>) -> Result<content::Content<String>, status::Custom<String>> {
+    let graph = decode_graph_name(graph_uri).map_err(|_| bad_request(format!("{} is not a graph", graph_uri)))?;
+    let graph = named_graph(&aliases, graph).map_err(|e| status::Custom(Status::NotFound, e))?.0;
+    if !is_readable(&store, &principal, &graph) {
+        return Err(status::Custom(Status::NotFound, format!("Graph {} cannot be found!", graph)));
+    }
+    let pattern = TriplePattern::parse(subject.as_deref(), predicate.as_deref(), object.as_deref()).map_err(bad_request)?;
+
+    // The graph as it was named in the request, so the controls link back to the same URLs
+    let fragments_url = base.join(&format!("/fragments/{}", graph_uri.as_str()));
+    let fragment = fragments::fragment_page(&store, &graph, &pattern, page.unwrap_or(1), &fragments_url);
+    triples_as_ttl_string(fragment.triples)
+        .map(|ttl| content::Content(ContentType::new("text", "turtle"), ttl))
+        .map_err(|e| status::Custom(Status::InternalServerError, e))
+}
+
+/// The cases of the W3C SPARQL 1.1 Graph Store HTTP Protocol tests (`http-rdf-update` in the
+/// SPARQL 1.1 test suite), run against `/store` in the same order.
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocket::http::Header;
+    use rocket::local::Client;
+
+    use crate::api::storage::test::init_store;
+
+    const PERSON: &str = "<http://www.example.org/jd#i> <http://xmlns.com/foaf/0.1/name> \"John Doe\" .\n";
+    const BUSINESS: &str = "<http://www.example.org/jd#i> <http://xmlns.com/foaf/0.1/businessCard> [ <http://www.w3.org/2006/vcard/ns#fn> \"John Doe\" ] .\n";
+
+    fn client() -> (Client, tempfile::TempDir) {
+        let (store, dir) = init_store();
+        let autocomplete = Autocomplete::build(&store);
+        let aliases = Aliases::load(&store);
+        let rocket = rocket::custom(rocket::Config::development())
+            .manage(store)
+            .manage(aliases)
+            .manage(Auth::default())
+            .manage(Limits::default())
+            .manage(autocomplete)
+            .mount("/", routes![gsp_get, gsp_put, gsp_post, gsp_delete]);
+        (Client::new(rocket).unwrap(), dir)
+    }
+
+    fn store_uri(graph: &str) -> String {
+        format!("/store?graph={}", alias::percent_encode(graph))
+    }
+
+    fn turtle() -> ContentType {
+        ContentType::new("text", "turtle")
+    }
+
+    fn get(client: &Client, uri: &str) -> (Status, Option<String>) {
+        let mut response = client.get(uri).header(Header::new("Accept", "application/n-triples")).dispatch();
+        (response.status(), response.body_string())
+    }
+
+    #[test]
+    fn test_put() {
+        let (client, _dir) = client();
+        let graph = store_uri("http://localhost/person/1");
+
+        // PUT - Initial state, and GET of it
+        let response = client.put(graph.as_str()).header(turtle()).body(PERSON).dispatch();
+        assert_eq!(Status::Created, response.status());
+        assert_eq!((Status::Ok, Some(PERSON.to_owned())), get(&client, &graph));
+
+        // PUT - graph already in store
+        let response = client.put(graph.as_str()).header(turtle()).body(BUSINESS).dispatch();
+        assert_eq!(Status::NoContent, response.status());
+        let (status, body) = get(&client, &graph);
+        let body = body.unwrap();
+        assert_eq!(Status::Ok, status);
+        assert!(body.contains("businessCard") && !body.contains("foaf/0.1/name"));
+
+        // PUT - mismatched payload
+        let response = client.put(graph.as_str()).header(turtle()).body("<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\"/>").dispatch();
+        assert_eq!(Status::BadRequest, response.status());
+
+        // PUT - empty graph, and GET of it
+        let empty = store_uri("http://localhost/person/2");
+        let response = client.put(empty.as_str()).header(turtle()).body("").dispatch();
+        assert_eq!(Status::Created, response.status());
+        assert_eq!((Status::Ok, Some(String::new())), get(&client, &empty));
+    }
+
+    #[test]
+    fn test_put_default_graph() {
+        let (client, _dir) = client();
+
+        let response = client.put("/store?default").header(turtle()).body(PERSON).dispatch();
+        assert_eq!(Status::NoContent, response.status());
+        assert_eq!((Status::Ok, Some(PERSON.to_owned())), get(&client, "/store?default"));
+    }
+
+    #[test]
+    fn test_put_replaces_unregistered_data() {
+        let (client, _dir) = client();
+        let graph = NamedNode::new_unchecked("http://localhost/person/1");
+        let stale = Quad::new(graph.clone(), rdfs::LABEL, oxigraph::model::Literal::new_simple_literal("stale"), graph.clone());
+        client.rocket().state::<Store>().unwrap().insert(&stale).unwrap();
+
+        let response = client.put(store_uri(graph.as_str())).header(turtle()).body(PERSON).dispatch();
+        assert_eq!(Status::Created, response.status());
+        assert_eq!((Status::Ok, Some(PERSON.to_owned())), get(&client, &store_uri(graph.as_str())));
+    }
+
+    #[test]
+    fn test_delete() {
+        let (client, _dir) = client();
+        let graph = store_uri("http://localhost/person/1");
+        client.put(graph.as_str()).header(turtle()).body(PERSON).dispatch();
+
+        // DELETE - existing graph, and GET of it
+        assert_eq!(Status::NoContent, client.delete(graph.as_str()).dispatch().status());
+        assert_eq!(Status::NotFound, get(&client, &graph).0);
+
+        // DELETE - non-existent graph
+        assert_eq!(Status::NotFound, client.delete(store_uri("http://localhost/person/3")).dispatch().status());
+    }
+
+    #[test]
+    fn test_post() {
+        let (client, _dir) = client();
+        let graph = store_uri("http://localhost/person/1");
+        client.put(graph.as_str()).header(turtle()).body(PERSON).dispatch();
+
+        // POST - existing graph, and GET of it
+        let response = client.post(graph.as_str()).header(turtle()).body(BUSINESS).dispatch();
+        assert_eq!(Status::NoContent, response.status());
+        let body = get(&client, &graph).1.unwrap();
+        assert!(body.contains("foaf/0.1/name") && body.contains("businessCard"));
+
+        // POST - multipart/form-data, and GET of it
+        let multipart = format!(
+            "--abc\r\nContent-Disposition: form-data; name=\"a\"\r\nContent-Type: text/turtle\r\n\r\n{}\r\n--abc\r\nContent-Disposition: form-data; name=\"b\"\r\nContent-Type: application/n-triples\r\n\r\n{}\r\n--abc--\r\n",
+            "<http://www.example.org/jd#i> <http://xmlns.com/foaf/0.1/givenName> \"John\" .",
+            "<http://www.example.org/jd#i> <http://xmlns.com/foaf/0.1/familyName> \"Doe\" .",
+        );
+        let response = client.post(graph.as_str())
+            .header(ContentType::with_params("multipart", "form-data", ("boundary", "abc")))
+            .body(multipart)
+            .dispatch();
+        assert_eq!(Status::NoContent, response.status());
+        let body = get(&client, &graph).1.unwrap();
+        assert!(body.contains("givenName") && body.contains("familyName") && body.contains("businessCard"));
+    }
+
+    #[test]
+    fn test_post_new_graph() {
+        let (client, _dir) = client();
+
+        // POST - create new graph, and GET of it
+        let response = client.post("/store").header(turtle()).body(PERSON).dispatch();
+        assert_eq!(Status::Created, response.status());
+        let location = response.headers().get_one("Location").unwrap().to_owned();
+        assert_eq!((Status::Ok, Some(PERSON.to_owned())), get(&client, &location));
+
+        // GET of POST - after noop
+        let response = client.post(location.as_str()).header(turtle()).body("").dispatch();
+        assert_eq!(Status::NoContent, response.status());
+        assert_eq!((Status::Ok, Some(PERSON.to_owned())), get(&client, &location));
+    }
+
+    #[test]
+    fn test_head_and_formats() {
+        let (client, _dir) = client();
+        let graph = store_uri("http://localhost/person/1");
+        client.put(graph.as_str()).header(turtle()).body(BUSINESS).dispatch();
+
+        // HEAD on an existing graph, and on a non-existing one
+        let response = client.head(graph.as_str()).dispatch();
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(Some(turtle()), response.content_type());
+        assert_eq!(Status::NotFound, client.head(store_uri("http://localhost/person/3")).dispatch().status());
+
+        for (accept, format) in [("application/rdf+xml", ContentType::new("application", "rdf+xml")), ("application/ld+json, text/turtle;q=0.5", ContentType::new("application", "ld+json"))].iter() {
+            let response = client.get(graph.as_str()).header(Header::new("Accept", *accept)).dispatch();
+            assert_eq!(Some(format.clone()), response.content_type());
+        }
+
+        // The same content again keeps the blank node it is stored with
+        let before = get(&client, &graph).1;
+        client.put(graph.as_str()).header(turtle()).body(BUSINESS).dispatch();
+        assert_eq!(before, get(&client, &graph).1);
+    }
+}